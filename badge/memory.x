@@ -1,6 +1,8 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    /* The last 4K sector holds the badge configuration, see src/config.rs, */
+    /* and the 32K below it the last content shown, see src/saved.rs       */
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 36K
 
     /* Pick one of the two options for RAM layout     */
 
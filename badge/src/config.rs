@@ -0,0 +1,76 @@
+//! Reading the badge configuration from the flash sector reserved for it.
+
+use badge_config::{Config, Network};
+use defmt::*;
+use embassy_rp::flash::{Blocking, Flash};
+use embassy_rp::peripherals::FLASH;
+
+use crate::FLASH_SIZE;
+
+/// Size of a flash erase sector
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Offset of the sector holding the configuration record, the last one in flash.
+/// `memory.x` keeps the program out of it.
+pub const CONFIG_OFFSET: u32 = (FLASH_SIZE - SECTOR_SIZE) as u32;
+
+pub type ConfigFlash<'d> = Flash<'d, FLASH, Blocking, FLASH_SIZE>;
+
+/// Read the configuration record, falling back to the networks built into the
+/// firmware if there isn't a usable one
+pub fn load(flash: &mut ConfigFlash<'_>) -> Config {
+    let mut buf = [0u8; badge_config::MAX_RECORD_LEN];
+    let config = flash
+        .blocking_read(CONFIG_OFFSET, &mut buf)
+        .map_err(|_| "flash read failed")
+        .and_then(|_| Config::decode(&buf));
+    match config {
+        Ok(config) => config,
+        Err(e) => {
+            warn!(
+                "No usable config in flash ({}), using the built in networks",
+                e
+            );
+            built_in()
+        }
+    }
+}
+
+/// Replace the configuration record in flash
+pub fn save(flash: &mut ConfigFlash<'_>, config: &Config) -> Result<(), &'static str> {
+    // The rest of the record is left erased
+    let mut buf = [0xffu8; badge_config::MAX_RECORD_LEN];
+    config.encode(&mut buf)?;
+    flash
+        .blocking_erase(CONFIG_OFFSET, CONFIG_OFFSET + SECTOR_SIZE as u32)
+        .map_err(|_| "flash erase failed")?;
+    flash
+        .blocking_write(CONFIG_OFFSET, &buf)
+        .map_err(|_| "flash write failed")
+}
+
+/// The networks compiled into the firmware, phone first, and the default server
+fn built_in() -> Config {
+    let networks = [
+        (
+            include_str!("../wifi.network_phone.txt"),
+            include_str!("../wifi.password_phone.txt"),
+            1,
+        ),
+        (
+            include_str!("../wifi.network.txt"),
+            include_str!("../wifi.password.txt"),
+            0,
+        ),
+    ];
+    let mut config = Config::default();
+    for (ssid, password, priority) in networks {
+        match Network::new(ssid.trim(), password.trim(), priority)
+            .and_then(|network| config.add_network(network))
+        {
+            Ok(()) => {}
+            Err(e) => warn!("Skipping built in network {}: {}", ssid.trim(), e),
+        }
+    }
+    config
+}
@@ -0,0 +1,211 @@
+//! USB serial console.
+//!
+//! The USB port is a composite device with two CDC-ACM serial ports, the first
+//! carries the log and the second the command console parsed by
+//! [`badge_console`].  The console works without any network so badges can be
+//! operated and debugged at a venue.
+
+extern crate alloc;
+use alloc::string::String;
+use core::cell::{Cell, RefCell};
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use badge_console::{Command, LineReader, Report};
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::Driver;
+use embassy_time::{Instant, Timer};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::{Builder, UsbDevice};
+use static_cell::StaticCell;
+
+use crate::config::ConfigFlash;
+use crate::Screen;
+use badge_draw::Stage;
+
+type UsbDriver = Driver<'static, USB>;
+pub type ConsolePort = CdcAcmClass<'static, UsbDriver>;
+
+const MAX_PACKET_SIZE: u16 = 64;
+/// Longest command line, long enough for `wifi add` with a quoted ssid and password
+const LINE_LEN: usize = 128;
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) -> ! {
+    usb.run().await
+}
+
+#[embassy_executor::task]
+async fn logger_task(class: ConsolePort) {
+    embassy_usb_logger::with_class!(1024, log::LevelFilter::Info, class).await
+}
+
+/// Start the USB device and logging, returning the console port for [`run`]
+pub fn start(spawner: &Spawner, driver: UsbDriver) -> ConsolePort {
+    let mut config = embassy_usb::Config::new(0x2e8a, 0x000a);
+    config.manufacturer = Some("aughey");
+    config.product = Some("Rust badge");
+    config.max_power = 100;
+    // Needed for a composite device with more than one serial port
+    config.device_class = 0xef;
+    config.device_sub_class = 0x02;
+    config.device_protocol = 0x01;
+    config.composite_with_iads = true;
+
+    static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static BOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+    static LOG_STATE: StaticCell<State> = StaticCell::new();
+    static CONSOLE_STATE: StaticCell<State> = StaticCell::new();
+    let mut builder = Builder::new(
+        driver,
+        config,
+        CONFIG_DESCRIPTOR.init([0; 256]),
+        BOS_DESCRIPTOR.init([0; 256]),
+        &mut [],
+        CONTROL_BUF.init([0; 64]),
+    );
+    let log = CdcAcmClass::new(&mut builder, LOG_STATE.init(State::new()), MAX_PACKET_SIZE);
+    let console = CdcAcmClass::new(
+        &mut builder,
+        CONSOLE_STATE.init(State::new()),
+        MAX_PACKET_SIZE,
+    );
+
+    unwrap!(spawner.spawn(usb_task(builder.build())));
+    unwrap!(spawner.spawn(logger_task(log)));
+    console
+}
+
+/// Serve console commands, for as long as the badge runs
+pub async fn run(
+    mut port: ConsolePort,
+    badge_id: u64,
+    flash: &RefCell<ConfigFlash<'_>>,
+    stage: &Cell<Stage>,
+    badge_text: &impl Fn(Screen),
+) -> ! {
+    let mut lines = LineReader::<LINE_LEN>::new();
+    let mut packet = [0u8; MAX_PACKET_SIZE as usize];
+    loop {
+        port.wait_connection().await;
+        info!("console connected");
+        let _ = write(&mut port, badge_console::PROMPT.as_bytes()).await;
+        loop {
+            let n = match port.read_packet(&mut packet).await {
+                Ok(n) => n,
+                Err(EndpointError::Disabled) => break,
+                Err(EndpointError::BufferOverflow) => continue,
+            };
+            // Terminals don't echo what is typed
+            let mut reply = String::new();
+            for &byte in &packet[..n] {
+                match byte {
+                    b'\r' | b'\n' => reply.push_str("\r\n"),
+                    0x08 | 0x7f => reply.push_str("\x08 \x08"),
+                    _ if byte.is_ascii() => reply.push(char::from(byte)),
+                    _ => {}
+                }
+            }
+            let mut reboot = false;
+            for &byte in &packet[..n] {
+                if let Some(line) = lines.push(byte) {
+                    match line.and_then(badge_console::parse) {
+                        Ok(command) => {
+                            reboot |=
+                                execute(command, &mut reply, badge_id, flash, stage, badge_text)
+                        }
+                        Err(e) => {
+                            let _ = write!(reply, "error: {e}\r\n");
+                        }
+                    }
+                    reply.push_str(badge_console::PROMPT);
+                }
+            }
+            if write(&mut port, reply.as_bytes()).await.is_err() {
+                break;
+            }
+            if reboot {
+                // Give the host a moment to read the reply
+                Timer::after_millis(100).await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+        }
+        info!("console disconnected");
+    }
+}
+
+/// Run a command, writing its reply.  Returns whether to restart the badge.
+fn execute(
+    command: Command,
+    reply: &mut String,
+    badge_id: u64,
+    flash: &RefCell<ConfigFlash<'_>>,
+    stage: &Cell<Stage>,
+    badge_text: &impl Fn(Screen),
+) -> bool {
+    match command {
+        Command::Help => reply.push_str(badge_console::HELP),
+        Command::Status => {
+            let report = Report {
+                badge_id,
+                version: env!("CARGO_PKG_VERSION"),
+                uptime_s: Instant::now().as_secs(),
+                led_ms: crate::LED_PERIOD_MS.load(Ordering::Relaxed),
+                stage: stage.get().label(),
+            };
+            let _ = badge_console::write_status(reply, &report);
+        }
+        Command::Text(text) if text.len() <= badge_net::MAX_TEXT_LEN => {
+            badge_text(Screen::Text(text));
+        }
+        Command::Text(_) => reply.push_str("error: text too long\r\n"),
+        Command::Led(period) => {
+            let update = badge_net::OwnedUpdate {
+                freq: Some(period),
+                ..Default::default()
+            };
+            if crate::UPDATE_QUEUE.try_send(update).is_err() {
+                reply.push_str("error: busy, try again\r\n");
+            }
+        }
+        Command::WifiAdd { ssid, password } => {
+            let mut flash = flash.borrow_mut();
+            let mut config = crate::config::load(&mut flash);
+            match config
+                .add_preferred_network(ssid, password)
+                .and_then(|()| crate::config::save(&mut flash, &config))
+            {
+                Ok(()) => reply.push_str("saved, reboot to join it\r\n"),
+                Err(e) => {
+                    let _ = write!(reply, "error: {e}\r\n");
+                }
+            }
+        }
+        Command::ConfigDump => {
+            let config = crate::config::load(&mut flash.borrow_mut());
+            let _ = badge_console::write_config(reply, &config);
+        }
+        Command::Reboot => {
+            reply.push_str("restarting\r\n");
+            return true;
+        }
+    }
+    false
+}
+
+/// Write to the port in packets, ending with a short packet so the host
+/// doesn't wait for more
+async fn write(port: &mut ConsolePort, bytes: &[u8]) -> Result<(), EndpointError> {
+    let max = usize::from(MAX_PACKET_SIZE);
+    for chunk in bytes.chunks(max) {
+        port.write_packet(chunk).await?;
+    }
+    if bytes.len() % max == 0 {
+        port.write_packet(&[]).await?;
+    }
+    Ok(())
+}
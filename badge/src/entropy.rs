@@ -0,0 +1,47 @@
+//! Entropy for the network stack and TLS, see `badge_entropy`.
+
+use badge_entropy::{ChaCha20Rng, Collector};
+use defmt::*;
+use embassy_time::Instant;
+
+/// Cycles between ring oscillator reads.  The oscillator runs far slower than
+/// the core, reading it back to back would mostly repeat the same bit.
+const ROSC_SPACING_CYCLES: u32 = 256;
+
+/// Times collection starts over after a failed health test before the badge
+/// restarts.  The tests raise a false alarm about once in a million bits, a
+/// source that keeps failing is broken.
+const COLLECT_ATTEMPTS: u32 = 4;
+
+/// A generator seeded from fresh ring oscillator bits.  When a health test
+/// fails the collector is replaced and collection starts again, and the badge
+/// restarts if the ring oscillator keeps failing.
+pub fn rng(collector: &mut Collector) -> ChaCha20Rng {
+    for attempt in 1..=COLLECT_ATTEMPTS {
+        match collect(collector).and_then(|()| collector.rng()) {
+            Ok(rng) => return rng,
+            Err(e) => {
+                warn!("entropy collection {} failed: {}", attempt, e);
+                *collector = Collector::new();
+                mix_time(collector);
+            }
+        }
+    }
+    error!("entropy source keeps failing, restarting");
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+/// Collect raw bits from the ring oscillator until there are enough for a seed
+pub fn collect(collector: &mut Collector) -> Result<(), &'static str> {
+    let rosc = embassy_rp::pac::ROSC;
+    while !collector.is_ready() {
+        cortex_m::asm::delay(ROSC_SPACING_CYCLES);
+        collector.push_bit(rosc.randombit().read().randombit())?;
+    }
+    Ok(())
+}
+
+/// Mix in the time, how long the Wi-Fi chip and network take varies a little
+pub fn mix_time(collector: &mut Collector) {
+    collector.mix(&Instant::now().as_ticks().to_le_bytes());
+}
@@ -6,16 +6,27 @@
 #![no_main]
 #![allow(unreachable_code)]
 
+extern crate alloc;
+
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use defmt::*;
 use embassy_executor::Spawner;
-use embassy_rp::gpio::{Input, Level, Output, Pull};
-use embassy_time::Timer;
+use embassy_rp::gpio::{Input, Level, Output, Pin, Pull};
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
 use uc8151::UpdateRegion;
 use {defmt_rtt as _, panic_probe as _};
 
 use embedded_graphics::primitives::PrimitiveStyleBuilder;
 use embedded_graphics::primitives::StrokeAlignment;
+pub mod config;
+pub mod console;
+pub mod entropy;
 pub mod net;
+pub mod portal;
+pub mod saved;
 //use hal::halt;
 // The macro for our start-up function
 
@@ -36,18 +47,7 @@ pub mod net;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::signal::Signal;
-use embedded_graphics::{
-    image::Image,
-    mono_font::{ascii::*, MonoTextStyle},
-    pixelcolor::BinaryColor,
-    prelude::*,
-    primitives::{PrimitiveStyle, Rectangle},
-};
-use embedded_text::{
-    alignment::HorizontalAlignment,
-    style::{HeightMode, TextBoxStyleBuilder},
-    TextBox,
-};
+use embedded_graphics::{image::Image, pixelcolor::BinaryColor, prelude::*};
 // use pimoroni_badger2040::hal;
 // use pimoroni_badger2040::hal::pac;
 // use pimoroni_badger2040::hal::Clock;
@@ -62,13 +62,43 @@ use embassy_rp::bind_interrupts;
 use embassy_rp::peripherals::USB;
 use tinybmp::Bmp;
 
-static FERRIS_IMG: &[u8; 2622] = include_bytes!("../ferris_1bpp.bmp");
+use badge_draw::{Refresh, Stage, Status};
+
+/// Size of the flash chip on the Badger 2040W
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
 
-static mut CORE1_STACK: Stack<4096> = Stack::new();
+/// The display on the Badger 2040 W, reported to the server when connecting
+pub const PANEL: badge_draw::Panel = badge_draw::Panel::BADGER_2040;
+const _: () = assert!(PANEL.size.width == uc8151::WIDTH && PANEL.size.height == uc8151::HEIGHT);
+
+// Updates queued for core1 are moved through its stack, so it has room for a few
+static mut CORE1_STACK: Stack<16384> = Stack::new();
 static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
 static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
-static CHANNEL: Channel<CriticalSectionRawMutex, &'static str, 3> = Channel::new();
-static LED_RATE_CHANNEL: Signal<CriticalSectionRawMutex, u64> = Signal::new();
+/// Updates for core1, which flashes the LED at their rate.  Core0 receives them
+/// and draws them, as it owns the display.
+static UPDATE_QUEUE: UpdateQueue = Channel::new();
+/// Number of updates core0 gets ahead of core1 before it waits
+const UPDATE_QUEUE_LEN: usize = 2;
+pub type UpdateQueue =
+    Channel<CriticalSectionRawMutex, badge_net::OwnedUpdate, UPDATE_QUEUE_LEN>;
+/// Period the LED is flashing at, for the console's status
+static LED_PERIOD_MS: AtomicU32 = AtomicU32::new(500);
+/// Signalled on every button press so a sleeping badge wakes up
+static BUTTON_WAKE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// How long a button must stay released before the next press counts
+const BUTTON_DEBOUNCE_MS: u64 = 50;
+
+/// What to show on the e-ink display
+pub enum Screen<'a> {
+    /// Connection progress or an error
+    Status(badge_draw::Status<'a>),
+    /// Content received from the server and the frame to show if its text is
+    /// paged or scrolled, see `badge_draw::TextMode`
+    Content(&'a badge_net::OwnedUpdate, u32),
+    /// Text typed at the console, shown in place of the text of the content on screen
+    Text(&'a str),
+}
 
 enum LedState {
     On,
@@ -79,11 +109,6 @@ bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => InterruptHandler<USB>;
 });
 
-#[embassy_executor::task]
-async fn logger_task(driver: Driver<'static, USB>) {
-    embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver);
-}
-
 use embedded_alloc::Heap;
 
 #[global_allocator]
@@ -97,8 +122,7 @@ async fn main(spawner: Spawner) {
     // Initialize the allocator BEFORE you use it
     unsafe { HEAP.init(HEAP_MEM.as_ptr() as usize, HEAP_SIZE) }
 
-    let driver = Driver::new(p.USB, Irqs);
-    spawner.spawn(logger_task(driver)).unwrap();
+    let console = crate::console::start(&spawner, Driver::new(p.USB, Irqs));
 
     // // Grab our singleton objects
     // let mut pac = pac::Peripherals::take().unwrap();
@@ -184,92 +208,113 @@ async fn main(spawner: Spawner) {
         // Reset display
         display.reset(&mut timer);
 
-        // Initialise display. Using the default LUT speed setting
+        // Initialise display. Using the default LUT speed setting.
+        // The controller keeps its landscape scan direction, rotation for badges
+        // worn portrait is done by `badge_draw::Rotated` so the shadow frame and
+        // partial refresh windows stay in panel coordinates.
         let _ = display.setup(&mut timer, uc8151::LUT::Internal);
 
-        // {
-        //     let border_stroke = PrimitiveStyleBuilder::new()
-        //         .stroke_color(BinaryColor::Off)
-        //         .stroke_width(3)
-        //         .stroke_alignment(StrokeAlignment::Outside)
-        //         .build();
-
-        //     // Draw a 3px wide outline around the display.
-        //     // _ = display
-        //     //     .bounding_box()
-        //     //     .into_styled(border_stroke)
-        //     //     .draw(&mut display);
-
-        //     // Note we're setting the Text color to `Off`. The driver is set up to treat Off as Black so that BMPs work as expected.
-        //     let character_style = MonoTextStyle::new(
-        //         &FONT_10X20,
-        //         // FONT_9X18_BOLD,
-        //         BinaryColor::Off,
-        //     );
-        //     let textbox_style = TextBoxStyleBuilder::new()
-        //         .height_mode(HeightMode::FitToText)
-        //         .alignment(HorizontalAlignment::Center)
-        //         .paragraph_spacing(6)
-        //         .build();
-
-        //     // Bounding box for our text. Fill it with the opposite color so we can read the text.
-        //     let bounds = Rectangle::new(Point::new(157, 10), Size::new(uc8151::WIDTH - 157, 0));
-        //     bounds
-        //         .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
-        //         .draw(&mut display)
-        //         .unwrap();
-
-        //     // Create the text box and apply styling options.
-        //     let text = "Embassy\nMy name is\nJohn Aughey";
-        //     let text_box =
-        //         TextBox::with_textbox_style(text, bounds, character_style, textbox_style);
-
-        //     // Draw the text box.
-        //     text_box.draw(&mut display).unwrap();
-        //     text_box
-        //         .bounding_box()
-        //         .into_styled(border_stroke)
-        //         .draw(&mut display)
-        //         .unwrap();
-
-        //     // Draw ferris
-        //     let tga: Bmp<BinaryColor> = Bmp::from_slice(FERRIS_IMG).unwrap();
-        //     let image = Image::new(&tga, Point::zero());
-        //     let _ = image.draw(&mut display);
-        //     let _ = display.update();
-        // }
-
-        badge_draw::draw_display(&mut display, "Initialized").expect("drawed");
-        let _ = display.update();
         display
     };
 
-    let mut badge_text = move |text: &str, draw_status: bool| {
-        if draw_status {
-            display.clear(BinaryColor::On).unwrap();
-            let bounds = display.bounding_box();
-            let character_style = MonoTextStyle::new(
-                &FONT_10X20,
-                // FONT_9X18_BOLD,
-                BinaryColor::Off,
-            );
-            let textbox_style = TextBoxStyleBuilder::new()
-                .height_mode(HeightMode::FitToText)
-                .alignment(HorizontalAlignment::Center)
-                .paragraph_spacing(6)
-                .build();
-            let text_box =
-                TextBox::with_textbox_style(text, bounds, character_style, textbox_style);
-
-            text_box.draw(&mut display).unwrap();
-            display.update().unwrap();
-        } else {
-            badge_draw::draw_display(&mut display, text).expect("drawed");
-            display.update().unwrap();
+    // Keep a copy of what is on the panel so small changes can use a partial refresh,
+    // with a full refresh every so often to clear ghosting.
+    let display_size = PANEL.size;
+    let mut shadow: badge_draw::Shadow = badge_draw::Shadow::new(display_size);
+    let mut refresh_policy = PANEL.refresh_policy();
+    let mut draw_screen = move |draw: &mut dyn FnMut(
+        &mut badge_draw::dirty::Tracked<'_, _, { badge_draw::dirty::BADGE_FRAME_LEN }>,
+    ) -> Result<(), &'static str>| {
+        // Content comes from the server, if it can't be drawn show what was drawn
+        // of it rather than stop the badge
+        let refresh = match shadow.track(&mut display, draw) {
+            Ok(dirty) => refresh_policy.refresh(dirty, display_size),
+            Err(e) => {
+                warn!("draw: {}", e);
+                refresh_policy.force_full();
+                Refresh::Full
+            }
+        };
+        match refresh {
+            Refresh::None => {}
+            Refresh::Partial(area) => {
+                // The controller addresses partial windows in whole bytes
+                let area = badge_draw::dirty::align(area, 8, display_size);
+                match UpdateRegion::try_from(area) {
+                    Ok(region) => display.partial_update(region).unwrap(),
+                    Err(_) => {
+                        display.update().unwrap();
+                        refresh_policy.force_full();
+                    }
+                }
+            }
+            Refresh::Full => display.update().unwrap(),
+        }
+    };
+
+    // The flash chip's unique id identifies this badge to the server.  Read it
+    // and the configuration before core1 starts so nothing else is running from flash.
+    // The setup portal writes the configuration later, embassy pauses core1 while it does.
+    let mut flash = crate::config::ConfigFlash::new_blocking(p.FLASH);
+    let badge_id = {
+        let mut id = [0u8; 8];
+        unwrap!(flash.blocking_unique_id(&mut id));
+        u64::from_be_bytes(id)
+    };
+    let config = crate::config::load(&mut flash);
+    let flash = RefCell::new(flash);
+    // Whatever was shown before the restart, so it is back without waiting for the server
+    let restored = crate::saved::load(&flash).await;
+
+    if restored.is_none() {
+        draw_screen(&mut |display| badge_draw::draw_display(display, "Initialized", None));
+    }
+
+    // The apps decide what is on the display, the launcher redraws when the
+    // open one changes.  The QR code points people at the page that changes the badge.
+    let launcher = RefCell::new(alloc::boxed::Box::new(unwrap!(
+        badge_apps::Launcher::with_builtin_apps(&alloc::format!("https://{}/", config.server_host))
+    )));
+    let draw_screen = RefCell::new(draw_screen);
+    let redraw = || {
+        let launcher = launcher.borrow();
+        (*draw_screen.borrow_mut())(&mut |display| launcher.render(display));
+    };
+
+    // The console, buttons and network code share the display and flash.  They
+    // all run on this executor and only borrow them between awaits, so the borrows never overlap.
+    let stage = Cell::new(Stage::Wifi);
+    // The content on screen, so text typed at the console keeps its layout,
+    // theme, rotation and logo.  On the heap, with room for a logo it is large.
+    let shown = RefCell::new(alloc::boxed::Box::new(badge_net::OwnedUpdate::default()));
+    let show_screen = |screen: Screen| {
+        let changed = match screen {
+            Screen::Status(status) => {
+                stage.set(status.stage);
+                launcher.borrow_mut().on_status(&status)
+            }
+            Screen::Content(update, frame) => {
+                shown.borrow_mut().as_mut().clone_from(update);
+                launcher.borrow_mut().on_content(update, frame)
+            }
+            Screen::Text(text) => {
+                let mut shown = shown.borrow_mut();
+                shown.text = text.try_into().ok();
+                launcher.borrow_mut().on_content(&shown, 0)
+            }
+        };
+        if changed {
+            redraw();
         }
     };
 
-    badge_text("Starting net...", true);
+    match &restored {
+        Some(update) => {
+            UPDATE_QUEUE.send(update.clone()).await;
+            show_screen(Screen::Content(update, 0));
+        }
+        None => show_screen(Screen::Status(Status::new(Stage::Wifi, "Starting net..."))),
+    }
 
     let led = Output::new(p.PIN_22, Level::Low);
     spawn_core1(
@@ -278,91 +323,90 @@ async fn main(spawner: Spawner) {
         move || {
             let executor1 = EXECUTOR1.init(Executor::new());
             executor1.run(|spawner| {
-                unwrap!(spawner.spawn(core1_task(led, &LED_RATE_CHANNEL)));
+                unwrap!(spawner.spawn(core1_task(led, &UPDATE_QUEUE)));
             });
         },
     );
 
-    match crate::net::main_net(
-        crate::net::NetPins {
-            PIN_23: p.PIN_23,
-            PIN_25: p.PIN_25,
-            PIO0: p.PIO0,
-            PIN_24: p.PIN_24,
-            PIN_29: p.PIN_29,
-            DMA_CH0: p.DMA_CH0,
-        },
-        spawner,
-        &mut badge_text,
-        &LED_RATE_CHANNEL,
-    )
-    .await
-    {
-        Ok(_) => badge_text("Net done", true),
-        Err(e) => badge_text(e, true),
-    }
-
-    //    status("DONE");
-
-    return;
-
-    let up_button = Input::new(p.PIN_15, Pull::Up);
-
-    //return;
-
-    info!("Hello from core 0");
-    loop {
-        // Note we're setting the Text color to `Off`. The driver is set up to treat Off as Black so that BMPs work as expected.
-        let character_style = MonoTextStyle::new(
-            &FONT_10X20,
-            // FONT_9X18_BOLD,
-            BinaryColor::Off,
-        );
-        let textbox_style = TextBoxStyleBuilder::new()
-            .height_mode(HeightMode::FitToText)
-            .alignment(HorizontalAlignment::Center)
-            .paragraph_spacing(6)
-            .build();
-        let bounds = Rectangle::new(
-            Point::new(157, 0),
-            Size::new(uc8151::WIDTH - 157, uc8151::HEIGHT),
-        );
-        _ = bounds
-            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
-            .draw(&mut display);
-
-        // Create the text box and apply styling options.
-        let text = CHANNEL.receive().await;
-        let text_box = TextBox::with_textbox_style(text, bounds, character_style, textbox_style);
-
-        // Draw the text box.
-        _ = text_box.draw(&mut display);
-        let _region: UpdateRegion = bounds.try_into().unwrap();
-        display.partial_update(bounds.try_into().unwrap()).unwrap();
-        //display.update().unwrap();
-    }
+    let net = async {
+        match crate::net::main_net(
+            crate::net::NetPins {
+                PIN_23: p.PIN_23,
+                PIN_25: p.PIN_25,
+                PIO0: p.PIO0,
+                PIN_24: p.PIN_24,
+                PIN_29: p.PIN_29,
+                DMA_CH0: p.DMA_CH0,
+            },
+            spawner,
+            badge_id,
+            &config,
+            &flash,
+            restored,
+            &mut |screen| show_screen(screen),
+            &UPDATE_QUEUE,
+        )
+        .await
+        {
+            Ok(_) => show_screen(Screen::Status(Status::new(Stage::Server, "Net done"))),
+            Err(e) => show_screen(Screen::Status(
+                Status::new(Stage::Server, "Network stopped").with_error(e),
+            )),
+        }
+    };
+    let console = crate::console::run(console, badge_id, &flash, &stage, &show_screen);
+    // The Badger's buttons pull their pins high while pressed
+    let buttons = async {
+        let mut inputs = [
+            (badge_apps::Button::A, p.PIN_12.degrade()),
+            (badge_apps::Button::B, p.PIN_13.degrade()),
+            (badge_apps::Button::C, p.PIN_14.degrade()),
+            (badge_apps::Button::Up, p.PIN_15.degrade()),
+            (badge_apps::Button::Down, p.PIN_11.degrade()),
+        ]
+        .map(|(button, pin)| (button, Input::new(pin, Pull::Down)));
+        loop {
+            let (_, index) = embassy_futures::select::select_array(
+                inputs
+                    .each_mut()
+                    .map(|(_, input)| input.wait_for_rising_edge()),
+            )
+            .await;
+            let (button, input) = &mut inputs[index];
+            info!("button {}", button.name());
+            BUTTON_WAKE.signal(());
+            if launcher.borrow_mut().on_button(*button) {
+                redraw();
+            }
+            // Ignore contact bounce on release
+            input.wait_for_low().await;
+            Timer::after_millis(BUTTON_DEBOUNCE_MS).await;
+        }
+    };
+    embassy_futures::join::join3(net, console, buttons).await;
 }
 
 #[embassy_executor::task]
 async fn core1_task(
     mut led: Output<'static, embassy_rp::peripherals::PIN_22>,
-    channel: &'static Signal<CriticalSectionRawMutex, u64>,
+    updates: &'static UpdateQueue,
 ) {
     info!("Hello from core 1");
     let mut flash_rate = 500u64;
+    let mut next_toggle = Instant::now();
     loop {
-        led.set_high();
-        Timer::after_millis(flash_rate).await;
-
-        if let Some(rate) = channel.try_take() {
-            flash_rate = rate.clamp(50, 2000);
-        }
-
-        led.set_low();
-        Timer::after_millis(flash_rate).await;
-
-        if let Some(rate) = channel.try_take() {
-            flash_rate = rate.clamp(50, 2000);
+        match select(Timer::at(next_toggle), updates.receive()).await {
+            Either::First(()) => {
+                led.toggle();
+                // From now rather than the last toggle, core1 is paused while flash is written
+                next_toggle = Instant::now() + Duration::from_millis(flash_rate);
+            }
+            Either::Second(update) => {
+                if let Some(rate) = update.freq {
+                    flash_rate = u64::from(rate).clamp(50, 2000);
+                    LED_PERIOD_MS.store(flash_rate as u32, Ordering::Relaxed);
+                }
+            }
         }
     }
 }
@@ -10,16 +10,17 @@ use cyw43_pio::PioSpi;
 use defmt::*;
 use embassy_executor::Spawner;
 use embassy_net::tcp::TcpSocket;
-use embassy_net::{Config, Stack, StackResources};
+use embassy_net::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
 use embassy_rp::bind_interrupts;
 use embassy_rp::gpio::{Level, Output};
 use embassy_rp::peripherals::{DMA_CH0, PIN_23, PIN_24, PIN_25, PIN_29, PIO0};
 use embassy_rp::pio::{InterruptHandler, Pio};
-use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
-use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Timer};
-use rand::SeedableRng;
+use embassy_time::{Duration, Instant, Timer};
+use rand::{RngCore, SeedableRng};
 use static_cell::StaticCell;
+
+use crate::Screen;
+use badge_draw::{Stage, Status};
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -55,6 +56,12 @@ pub struct NetPins {
 const FW: &[u8] = include_bytes!("../firmware/43439A0.bin");
 const CLM: &[u8] = include_bytes!("../firmware/43439A0_clm.bin");
 
+/// Times every configured network is tried before starting the setup portal
+const JOIN_ROUNDS: u32 = 3;
+
+/// Longest sleep between attempts to rejoin the network after a sleep
+const MAX_REJOIN_SLEEP_MS: u64 = 30 * 60 * 1000;
+
 const CA: &str = include_str!("../../certs/CA_cert.crt");
 const CERT: &str = include_str!("../../certs/client.crt");
 const KEY: &str = include_str!("../../certs/client.key");
@@ -62,10 +69,17 @@ const KEY: &str = include_str!("../../certs/client.key");
 pub async fn main_net(
     p: NetPins,
     spawner: Spawner,
-    badge_text: &mut impl FnMut(&str, bool),
-    channel: &Signal<CriticalSectionRawMutex, u64>,
+    badge_id: u64,
+    config: &badge_config::Config,
+    flash: &core::cell::RefCell<crate::config::ConfigFlash<'_>>,
+    restored: Option<badge_net::OwnedUpdate>,
+    badge_text: &mut impl FnMut(Screen),
+    updates: &crate::UpdateQueue,
 ) -> Result<(), &'static str> {
-    badge_text("Starting net initialization", true);
+    badge_text(Screen::Status(Status::new(
+        Stage::Wifi,
+        "Starting net initialization",
+    )));
 
     // To make flashing faster for development, you may want to flash the firmwares independently
     // at hardcoded addresses, instead of baking them into the program with `include_bytes!`:
@@ -95,72 +109,59 @@ pub async fn main_net(
     unwrap!(spawner.spawn(wifi_task(runner)));
 
     control.init(CLM).await;
+    // The badge id only keeps badges apart, the ring oscillator's bits are what counts
+    let mut entropy = badge_entropy::Collector::new();
+    entropy.mix(&badge_id.to_le_bytes());
+    crate::entropy::mix_time(&mut entropy);
+    // Starts always on, the server sends the power policy when the badge connects
+    let mut power = badge_power::PowerManager::new();
     control
-        .set_power_management(cyw43::PowerManagementMode::None)
+        .set_power_management(power_management(power.wifi_power()))
         .await;
 
-    let config = Config::dhcpv4(Default::default());
-    //let config = embassy_net::Config::ipv4_static(embassy_net::StaticConfigV4 {
-    //    address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
-    //    dns_servers: Vec::new(),
-    //    gateway: Some(Ipv4Address::new(192, 168, 69, 1)),
-    //});
-
-    // Generate random seed
-    let seed = 0x0123_4567_89ab_cdef; // chosen by fair dice roll. guarenteed to be random.
+    let net_config = match &config.static_ip {
+        Some(static_ip) => Config::ipv4_static(StaticConfigV4 {
+            address: Ipv4Cidr::new(Ipv4Address(static_ip.address), static_ip.prefix_len),
+            dns_servers: static_ip
+                .dns_servers
+                .iter()
+                .map(|&server| Ipv4Address(server))
+                .collect(),
+            gateway: static_ip.gateway.map(Ipv4Address),
+        }),
+        None => Config::dhcpv4(Default::default()),
+    };
+
+    // Seeds the stack's TCP sequence numbers and ports
+    let seed = crate::entropy::rng(&mut entropy).next_u64();
 
     // Init network stack
     static STACK: StaticCell<Stack<cyw43::NetDriver<'static>>> = StaticCell::new();
     static RESOURCES: StaticCell<StackResources<4>> = StaticCell::new();
     let stack = &*STACK.init(Stack::new(
         net_device,
-        config,
+        net_config,
         RESOURCES.init(StackResources::<4>::new()),
         seed,
     ));
 
     unwrap!(spawner.spawn(net_task(stack)));
 
-    //    status("Joining wpa2");
-    loop {
-        const PHONE_WIFI_NETWORK: &str = include_str!("../wifi.network_phone.txt");
-        const PHONE_WIFI_PASSWORD: &str = include_str!("../wifi.password_phone.txt");
-        log::info!("Trying to connect to phone wifi");
-        badge_text(PHONE_WIFI_NETWORK, true);
-        match control
-            .join_wpa2(PHONE_WIFI_NETWORK, PHONE_WIFI_PASSWORD)
-            .await
-        {
-            Ok(_) => {
-                badge_text("connected to phone wifi", true);
-                break;
-            }
-            Err(err) => {
-                info!("join failed with status={}", err.status);
-            }
-        }
-        log::info!("Trying to connect to home wifi");
-        const WIFI_NETWORK: &str = include_str!("../wifi.network.txt");
-        const WIFI_PASSWORD: &str = include_str!("../wifi.password.txt");
-        match control.join_wpa2(WIFI_NETWORK, WIFI_PASSWORD).await {
-            Ok(_) => {
-                badge_text("Connected to home wifi", true);
-                break;
-            }
-            Err(err) => {
-                info!("join failed with status={}", err.status);
-            }
-        }
-    }
+    // Only fails without the setup portal
+    join(
+        &mut control,
+        stack,
+        flash,
+        badge_id,
+        config,
+        badge_text,
+        true,
+    )
+    .await?;
 
-    // Wait for DHCP, not necessary when using static IP
-    info!("waiting for DHCP...");
-    badge_text("Waiting for DHCP", true);
-    while !stack.is_config_up() {
-        Timer::after_millis(100).await;
-    }
-    info!("DHCP is now up!");
-    badge_text("DHCP is now up!", true);
+    // A generator for TLS, seeded after joining so the time it took is mixed in
+    crate::entropy::mix_time(&mut entropy);
+    let mut tls_rng = crate::entropy::rng(&mut entropy);
 
     let ipaddr = stack
         .config_v4()
@@ -176,7 +177,7 @@ pub async fn main_net(
 
     use embedded_tls::{Certificate, TlsConfig, TlsConnection, TlsContext};
 
-    let config = TlsConfig::new()
+    let tls_config = TlsConfig::new()
         .enable_rsa_signatures()
         .with_ca(Certificate::X509(ca.contents()))
         .with_priv_key(key.contents())
@@ -191,25 +192,39 @@ pub async fn main_net(
     tx_buffer.resize(4096, 0u8);
     //    badge_text("starting main loop", true);
 
+    // Kept across connections so temporary messages still expire on schedule.
+    // Boxed as the logo makes it too large to keep on the stack.
+    let mut content = alloc::boxed::Box::new(badge_net::content::ContentState::new());
+    // What is saved in flash, starting from the content restored at boot
+    let mut saved = alloc::boxed::Box::new(restored.unwrap_or_default());
+    content.apply(saved.as_ref().clone(), Instant::now().as_millis());
+
+    let server = config.server_host.as_str();
     loop {
-        const SERVER: &str = "dev.aughey.com";
-        // Get address for dev.aughey.com through configured DNS
+        // Temporary messages expire on time while the server can't be reached too
+        expire(&mut content, badge_text);
+
+        // Get the server's address through configured DNS
         // Get address from 192.168.86.155
         //let remote_host = embassy_net::Ipv4Address::new(192, 168, 86, 155);
         //let remote_host = embassy_net::Ipv4Address::new(13, 58, 3, 63);
         use embassy_net::dns::DnsQueryType;
-        let remote_host = match stack.dns_query(SERVER, DnsQueryType::A).await {
+        let remote_host = match stack.dns_query(server, DnsQueryType::A).await {
             Ok(addrs) => {
                 if let Some(addr) = addrs.first() {
                     *addr
                 } else {
-                    badge_text("DNS query failed", true);
+                    badge_text(Screen::Status(
+                        Status::new(Stage::Dns, server).with_error("DNS query failed"),
+                    ));
                     Timer::after(Duration::from_secs(3)).await;
                     continue;
                 }
             }
             Err(e) => {
-                badge_text("DNS query failed", true);
+                badge_text(Screen::Status(
+                    Status::new(Stage::Dns, server).with_error("DNS query failed"),
+                ));
                 Timer::after(Duration::from_secs(3)).await;
                 continue;
             }
@@ -219,11 +234,13 @@ pub async fn main_net(
 
         socket.set_timeout(Some(Duration::from_secs(20)));
 
-        match socket.connect((remote_host, 12345)).await {
+        match socket.connect((remote_host, config.server_port)).await {
             Ok(_) => {}
             Err(e) => {
                 // sleep 3 seconds
-                badge_text("Could not connect", true);
+                badge_text(Screen::Status(
+                    Status::new(Stage::Tls, server).with_error("Could not connect"),
+                ));
                 Timer::after(Duration::from_secs(3)).await;
                 continue;
             }
@@ -245,33 +262,147 @@ pub async fn main_net(
             &mut write_buffer,
         );
 
+        // Each connection gets its own generator
+        let mut tls_seed = [0; 32];
+        tls_rng.fill_bytes(&mut tls_seed);
         if let Err(_) = tls
             .open(TlsContext::new(
-                &config,
+                &tls_config,
                 embedded_tls::UnsecureProvider::new::<embedded_tls::Aes128GcmSha256>(
-                    rand_chacha::ChaChaRng::seed_from_u64(1234),
+                    badge_entropy::ChaCha20Rng::from_seed(tls_seed),
                 ),
             ))
             .await
         {
-            badge_text("Failed to setup TLS connection", true);
-            badge_text("Could not connect", true);
+            badge_text(Screen::Status(
+                Status::new(Stage::Tls, server).with_error("Failed to setup TLS connection"),
+            ));
             Timer::after(Duration::from_secs(3)).await;
             continue;
         }
 
         //.map_err(|e| anyhow::anyhow!("Failed to open connection: {:?}", e))?;
 
-        badge_text("TLS connection established!", true);
+        badge_text(Screen::Status(Status::new(
+            Stage::Server,
+            "TLS connection established!",
+        )));
 
         let tls = EmbeddedAsyncWrapper(tls);
 
-        if let Err(e) = handle_connection(tls, badge_text, channel).await {
-            badge_text(e, true);
+        match handle_connection(
+            tls,
+            badge_id,
+            badge_text,
+            updates,
+            &mut content,
+            flash,
+            &mut saved,
+            &mut control,
+            &mut power,
+        )
+        .await
+        {
+            Ok(sleep_ms) => {
+                // Leave the network so the radio can sleep too, what is on
+                // screen stays there without power
+                info!("sleeping for {} ms", sleep_ms);
+                let mut sleep_ms = sleep_ms;
+                loop {
+                    control.leave().await;
+                    if wait_while_disconnected(sleep_ms, &mut content, badge_text).await {
+                        power.on_button(Instant::now().as_millis());
+                    }
+                    // A known network missing after a sleep is most likely out of
+                    // range for a while, so sleep longer each time rather than
+                    // starting the setup portal.  Restarting the badge brings it up.
+                    match join(
+                        &mut control,
+                        stack,
+                        flash,
+                        badge_id,
+                        config,
+                        badge_text,
+                        false,
+                    )
+                    .await
+                    {
+                        Ok(()) => break,
+                        Err(e) => {
+                            sleep_ms = sleep_ms
+                                .saturating_mul(2)
+                                .min(MAX_REJOIN_SLEEP_MS.max(sleep_ms));
+                            warn!("{}, sleeping for {} ms", e, sleep_ms);
+                        }
+                    }
+                }
+            }
+            Err(e) => badge_text(Screen::Status(
+                Status::new(Stage::Server, server).with_error(e),
+            )),
         }
     }
 }
 
+/// Join the first configured network that accepts us and wait for an address.
+/// If none has after a few attempts, starts the setup portal and restarts the
+/// badge if nobody uses it, or fails when `portal` is false.
+async fn join(
+    control: &mut cyw43::Control<'_>,
+    stack: &Stack<cyw43::NetDriver<'static>>,
+    flash: &core::cell::RefCell<crate::config::ConfigFlash<'_>>,
+    badge_id: u64,
+    config: &badge_config::Config,
+    badge_text: &mut impl FnMut(Screen),
+    portal: bool,
+) -> Result<(), &'static str> {
+    // Try each configured network in priority order until one accepts us
+    let mut rounds = 0;
+    'join: loop {
+        if rounds == JOIN_ROUNDS {
+            if !portal {
+                return Err("No known network");
+            }
+            crate::portal::run(control, stack, flash, badge_id, config, badge_text).await;
+            // cyw43 has no way to take the access point down again, so the known
+            // networks are tried again from a restart
+            info!("restarting to try the known networks again");
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        rounds += 1;
+        for network in config.networks_by_priority() {
+            log::info!("Trying to join {}", network.ssid.as_str());
+            badge_text(Screen::Status(Status::new(Stage::Wifi, &network.ssid)));
+            let joined = if network.is_open() {
+                control.join_open(&network.ssid).await
+            } else {
+                control.join_wpa2(&network.ssid, &network.password).await
+            };
+            match joined {
+                Ok(_) => {
+                    badge_text(Screen::Status(Status::new(Stage::Dhcp, &network.ssid)));
+                    break 'join;
+                }
+                Err(err) => {
+                    info!("join failed with status={}", err.status);
+                }
+            }
+        }
+    }
+
+    // Wait for DHCP, not necessary when using static IP
+    if config.static_ip.is_none() {
+        info!("waiting for DHCP...");
+        badge_text(Screen::Status(Status::new(Stage::Dhcp, "Waiting for DHCP")));
+        while !stack.is_config_up() {
+            Timer::after_millis(100).await;
+        }
+        info!("DHCP is now up!");
+        badge_text(Screen::Status(Status::new(Stage::Dns, "DHCP is now up!")));
+    }
+    Ok(())
+}
+
 /// Will wait for a future to complete for duration time before returning an error.
 async fn wait_timeout<F, V>(fut: F, duration: Duration) -> Result<V, &'static str>
 where
@@ -288,15 +419,92 @@ async fn flush(io: &mut impl badge_net::AsyncWrite) -> Result<(), &'static str>
     io.flush().await.map_err(|_| "Failed to flush")
 }
 
+/// Wait up to `ms` for a button press, returns whether one came
+async fn wait_for_button(ms: u64) -> bool {
+    let timeout = Timer::after_millis(ms);
+    matches!(
+        wait_for_one_to_complete(timeout, core::pin::pin!(crate::BUTTON_WAKE.wait())).await,
+        FirstOrSecond::Second(_)
+    )
+}
+
+/// Like [`wait_for_button`], waking to take a temporary message down when it expires
+async fn wait_while_disconnected(
+    ms: u64,
+    content: &mut badge_net::content::ContentState,
+    badge_text: &mut impl FnMut(Screen),
+) -> bool {
+    let until_ms = Instant::now().as_millis().saturating_add(ms);
+    loop {
+        let now = expire(content, badge_text);
+        if now >= until_ms {
+            return false;
+        }
+        let wake_ms = content
+            .expires_at_ms()
+            .map_or(until_ms, |expires_at_ms| expires_at_ms.min(until_ms));
+        if wait_for_button(wake_ms.saturating_sub(now)).await {
+            return true;
+        }
+    }
+}
+
+/// Show the content underneath a temporary message that has expired, returns the time
+fn expire(
+    content: &mut badge_net::content::ContentState,
+    badge_text: &mut impl FnMut(Screen),
+) -> u64 {
+    let now = Instant::now().as_millis();
+    if content.tick(now) {
+        badge_text(Screen::Content(content.current(), 0));
+    }
+    now
+}
+
+fn power_management(power: badge_power::WifiPower) -> cyw43::PowerManagementMode {
+    match power {
+        badge_power::WifiPower::Full => cyw43::PowerManagementMode::None,
+        badge_power::WifiPower::PowerSave => cyw43::PowerManagementMode::PowerSave,
+        badge_power::WifiPower::SuperSave => cyw43::PowerManagementMode::SuperSave,
+    }
+}
+
+/// Sync with the server until the power policy says to sleep, returns how long for
 async fn handle_connection<T>(
     mut tls: T,
-    badge_text: &mut impl FnMut(&str, bool),
-    channel: &Signal<CriticalSectionRawMutex, u64>,
-) -> Result<(), &'static str>
+    badge_id: u64,
+    badge_text: &mut impl FnMut(Screen),
+    updates: &crate::UpdateQueue,
+    content: &mut badge_net::content::ContentState,
+    flash: &core::cell::RefCell<crate::config::ConfigFlash<'_>>,
+    saved: &mut badge_net::OwnedUpdate,
+    control: &mut cyw43::Control<'_>,
+    power: &mut badge_power::PowerManager,
+) -> Result<u64, &'static str>
 where
     T: badge_net::AsyncRead + badge_net::AsyncWrite + Unpin,
 {
-    let mut buf = [0u8; 256];
+    let mut buf = alloc::vec![0u8; badge_net::MAX_FRAME_LEN];
+
+    // Tell the server which badge this is and what it draws on
+    let hello = badge_net::Hello {
+        badge_id,
+        panel: badge_net::PanelInfo {
+            width: crate::PANEL.size.width as u16,
+            height: crate::PANEL.size.height as u16,
+            colors: crate::PANEL.colors.id(),
+            partial_refresh: crate::PANEL.partial_refresh,
+        },
+    };
+    wait_timeout(
+        badge_net::write_frame(&mut tls, &hello, &mut buf),
+        Duration::from_secs(10),
+    )
+    .await?;
+
+    // Frame of paged or scrolling text on screen and when to show the next one
+    let mut frame = 0u32;
+    let mut next_frame_ms = None;
 
     loop {
         // Send a request message
@@ -316,17 +524,78 @@ where
             Duration::from_secs(10),
         )
         .await?;
-
-        if let Some(freq) = update.freq {
-            channel.signal(freq);
+        // Take ownership of the update so the receive buffer is free for the next frame
+        let update = badge_net::OwnedUpdate::try_from(&update)?;
+
+        // Core1 flashes the LED at the update's rate
+        updates.send(update.clone()).await;
+        if let Some(policy) = update.power {
+            if power.set_policy(policy) {
+                info!("power policy {}", defmt::Debug2Format(&policy));
+                control
+                    .set_power_management(power_management(power.wifi_power()))
+                    .await;
+            }
+        }
+        let freq = update.freq.or(saved.freq);
+
+        let now = Instant::now().as_millis();
+        let mut changed = content.tick(now);
+        changed |= content.apply(update, now);
+
+        // Keep the permanent content in flash so it is shown again after a restart
+        let mut update = content.base().clone();
+        update.freq = freq;
+        if update != *saved {
+            // Only what made it into flash counts as saved, a failed save is
+            // tried again with the next update
+            match crate::saved::save(flash, &update, saved).await {
+                Ok(()) => *saved = update,
+                Err(e) => warn!("{}", e),
+            }
+        }
+        let interval = content
+            .current()
+            .mode
+            .and_then(|id| badge_draw::TextMode::try_from(id).ok())
+            .unwrap_or_default()
+            .frame_interval_ms();
+        if changed {
+            // New content starts from its first page
+            frame = 0;
+            next_frame_ms = interval.map(|interval| now + interval);
+            badge_text(Screen::Content(content.current(), frame));
+        } else if let Some(interval) = interval {
+            // Updates arrive about once a second, which is often enough for e-ink
+            if next_frame_ms.map_or(true, |next| now >= next) {
+                frame = frame.wrapping_add(1);
+                next_frame_ms = Some(now + interval);
+                badge_text(Screen::Content(content.current(), frame));
+            }
         }
 
-        if let Some(text) = update.text {
-            badge_text(text, false);
+        // A button pressed since the last sync keeps the badge awake
+        if crate::BUTTON_WAKE.try_take().is_some() {
+            power.on_button(now);
+        }
+        match power.next(now, interval, content.expires_at_ms()) {
+            badge_power::Next::Poll(0) => {}
+            badge_power::Next::Poll(delay_ms) => {
+                if wait_for_button(delay_ms).await {
+                    power.on_button(Instant::now().as_millis());
+                }
+            }
+            badge_power::Next::Sleep(sleep_ms) => {
+                wait_timeout(
+                    badge_net::write_frame(&mut tls, &badge_net::Request::Close, &mut buf),
+                    Duration::from_secs(10),
+                )
+                .await?;
+                wait_timeout(flush(&mut tls), Duration::from_secs(10)).await?;
+                return Ok(sleep_ms);
+            }
         }
     }
-
-    Ok(())
 }
 
 /// Return type of wait_for_one_to_complete indicating which future completed before the other.
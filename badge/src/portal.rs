@@ -0,0 +1,213 @@
+//! Setup portal started when the badge can't join any known network.
+//!
+//! The badge starts an open access point and serves a form for entering Wi-Fi
+//! credentials and the server address.  Saving the form writes the
+//! configuration to flash and restarts the badge.  Requests are parsed and the
+//! pages written by [`badge_config::portal`].
+//!
+//! Phones and laptops joining the access point get an address from a small
+//! DHCP server, see [`badge_config::dhcp`], and reach the badge at 192.168.4.1.
+//! The portal gives up when nobody has used it for [`TIMEOUT`] so the badge can
+//! try the known networks again, they may just have been out of range.
+
+extern crate alloc;
+use alloc::string::String;
+use core::cell::{Cell, RefCell};
+
+use badge_config::portal::{self, Route};
+use badge_config::{dhcp, Config};
+use defmt::*;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{ConfigV4, Ipv4Address, Ipv4Cidr, Stack, StaticConfigV4};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::config::ConfigFlash;
+use crate::Screen;
+use badge_draw::{Stage, Status};
+
+/// Address of the badge on its own network
+const ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 4, 1);
+const WIFI_CHANNEL: u8 = 6;
+const HTTP_PORT: u16 = 80;
+
+/// How long the portal waits for a request before giving up
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Serve the setup form until a new configuration is saved, then restart.
+/// Returns if nobody has used the portal for [`TIMEOUT`].
+pub async fn run(
+    control: &mut cyw43::Control<'_>,
+    stack: &Stack<cyw43::NetDriver<'static>>,
+    flash: &RefCell<ConfigFlash<'_>>,
+    badge_id: u64,
+    config: &Config,
+    badge_text: &mut impl FnMut(Screen),
+) {
+    let ssid = alloc::format!("Badge-{:04x}", badge_id as u16);
+    info!("starting setup portal on {}", ssid.as_str());
+    control.leave().await;
+    control.start_ap_open(&ssid, WIFI_CHANNEL).await;
+    stack.set_config_v4(ConfigV4::Static(StaticConfigV4 {
+        address: Ipv4Cidr::new(ADDRESS, 24),
+        gateway: None,
+        dns_servers: Default::default(),
+    }));
+    badge_text(Screen::Status(
+        Status::new(
+            Stage::Wifi,
+            &alloc::format!("Join {ssid} and open http://192.168.4.1"),
+        )
+        .with_error("No known network"),
+    ));
+
+    let last_used = Cell::new(Instant::now());
+    let timeout = async {
+        // Clients joining and requests push the deadline back while the timer waits
+        while Instant::now() < last_used.get() + TIMEOUT {
+            Timer::at(last_used.get() + TIMEOUT).await;
+        }
+    };
+    embassy_futures::select::select3(
+        serve_form(stack, flash, config, badge_text, &last_used),
+        serve_dhcp(stack, &last_used),
+        timeout,
+    )
+    .await;
+    info!("setup portal timed out");
+}
+
+/// Answer requests for the form, restarting once a new configuration is saved
+async fn serve_form(
+    stack: &Stack<cyw43::NetDriver<'static>>,
+    flash: &RefCell<ConfigFlash<'_>>,
+    config: &Config,
+    badge_text: &mut impl FnMut(Screen),
+    last_used: &Cell<Instant>,
+) -> ! {
+    let mut rx_buffer = alloc::vec![0u8; 1024];
+    let mut tx_buffer = alloc::vec![0u8; 1024];
+    let mut request = alloc::vec![0u8; portal::MAX_REQUEST_LEN];
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+        if let Err(e) = socket.accept(HTTP_PORT).await {
+            warn!("portal accept error: {:?}", e);
+            continue;
+        }
+        last_used.set(Instant::now());
+        let saved = match serve(&mut socket, &mut request, flash, config).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("portal request failed: {}", e);
+                false
+            }
+        };
+        socket.close();
+        let _ = socket.flush().await;
+        if saved {
+            info!("configuration saved, restarting");
+            badge_text(Screen::Status(Status::new(Stage::Wifi, "Settings saved")));
+            Timer::after(Duration::from_secs(1)).await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+}
+
+/// Give every client of the access point an address
+async fn serve_dhcp(stack: &Stack<cyw43::NetDriver<'static>>, last_used: &Cell<Instant>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = alloc::vec![0u8; 2 * dhcp::MAX_MESSAGE_LEN];
+    let mut tx_buffer = alloc::vec![0u8; 2 * dhcp::MAX_MESSAGE_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    unwrap!(socket.bind(dhcp::SERVER_PORT));
+
+    let mut server = dhcp::Server::new(ADDRESS.0);
+    let mut message = alloc::vec![0u8; dhcp::MAX_MESSAGE_LEN];
+    let mut reply = alloc::vec![0u8; dhcp::MAX_MESSAGE_LEN];
+    loop {
+        let len = match socket.recv_from(&mut message).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                warn!("dhcp receive error: {:?}", e);
+                continue;
+            }
+        };
+        match server.handle(&message[..len], &mut reply) {
+            // Clients without an address only hear broadcasts
+            Ok(Some(len)) => {
+                last_used.set(Instant::now());
+                let to = (Ipv4Address::BROADCAST, dhcp::CLIENT_PORT);
+                if let Err(e) = socket.send_to(&reply[..len], to).await {
+                    warn!("dhcp send error: {:?}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("dhcp request ignored: {}", e),
+        }
+    }
+}
+
+/// Answer one request, returns whether a new configuration was saved
+async fn serve(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    flash: &RefCell<ConfigFlash<'_>>,
+    config: &Config,
+) -> Result<bool, &'static str> {
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            return Err("request too large");
+        }
+        let n = socket
+            .read(&mut buf[len..])
+            .await
+            .map_err(|_| "read failed")?;
+        if n == 0 {
+            return Err("connection closed");
+        }
+        len += n;
+        if portal::parse_request(&buf[..len])?.is_some() {
+            break;
+        }
+    }
+    let request = portal::parse_request(&buf[..len])?.ok_or("incomplete request")?;
+
+    let mut page = String::new();
+    let (status, saved) = match portal::route(&request) {
+        Route::Form => {
+            portal::write_form(&mut page, config, None).map_err(|_| "page too long")?;
+            (200, false)
+        }
+        Route::Save => match portal::apply_form(request.body, config)
+            .and_then(|new| crate::config::save(&mut flash.borrow_mut(), &new))
+        {
+            Ok(()) => {
+                portal::write_saved(&mut page).map_err(|_| "page too long")?;
+                (200, true)
+            }
+            Err(e) => {
+                portal::write_form(&mut page, config, Some(e)).map_err(|_| "page too long")?;
+                (400, false)
+            }
+        },
+        Route::NotFound => (404, false),
+    };
+    let mut head = String::new();
+    portal::write_head(&mut head, status, page.len()).map_err(|_| "page too long")?;
+    embedded_io_async::Write::write_all(socket, head.as_bytes())
+        .await
+        .map_err(|_| "write failed")?;
+    embedded_io_async::Write::write_all(socket, page.as_bytes())
+        .await
+        .map_err(|_| "write failed")?;
+    Ok(saved)
+}
@@ -0,0 +1,154 @@
+//! Keeping the last content in flash so it is shown again straight after a restart.
+//!
+//! The records described in [`badge_net::saved`] are kept in a sequential-storage
+//! map in the sectors below the configuration, which spreads the writes over
+//! them for wear levelling.  The content record is written last, it is what
+//! makes a new logo the saved one.
+
+extern crate alloc;
+use core::cell::RefCell;
+use core::ops::Range;
+
+use badge_net::saved::{self, CONTENT_KEY};
+use badge_net::OwnedUpdate;
+use defmt::*;
+use embassy_embedded_hal::adapter::BlockingAsync;
+use sequential_storage::cache::NoCache;
+use sequential_storage::map;
+
+use crate::config::{ConfigFlash, CONFIG_OFFSET, SECTOR_SIZE};
+
+/// Sectors holding the saved content, `memory.x` keeps the program out of them.
+///
+/// While a logo is replaced both it and the new one are in flash.  Each is two
+/// chunk records of 2057 bytes with their header and key, and one of 1161
+/// bytes.  A 4 KiB sector holds only one of the large records, so the two
+/// logos take four sectors, the small records and the content record fit
+/// beside them in at most one more, and the map keeps one sector empty to
+/// move records into.  Two sectors to spare leave room for records that have
+/// been replaced but not yet erased.
+const SECTORS: u32 = 8;
+const FLASH_RANGE: Range<u32> = CONFIG_OFFSET - SECTORS * SECTOR_SIZE as u32..CONFIG_OFFSET;
+
+/// Room for the largest record and the map's own overhead
+const BUFFER_LEN: usize = saved::LOGO_CHUNK_LEN + 64;
+
+// The flash is blocking so the futures below complete without yielding and the
+// RefCell is never borrowed by anything else while they run.
+
+/// Read the saved content, if there is any
+pub async fn load(flash: &RefCell<ConfigFlash<'_>>) -> Option<OwnedUpdate> {
+    let mut flash = flash.borrow_mut();
+    let mut flash = BlockingAsync::new(&mut *flash);
+    let mut buffer = alloc::vec![0u8; BUFFER_LEN];
+
+    let bytes: &[u8] = match map::fetch_item(
+        &mut flash,
+        FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buffer,
+        CONTENT_KEY,
+    )
+    .await
+    {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return None,
+        Err(_) => {
+            warn!("failed to read saved content");
+            return None;
+        }
+    };
+    let (mut update, logo_bank) = match saved::decode_content(bytes) {
+        Ok(saved) => saved,
+        Err(e) => {
+            warn!("ignoring saved content: {}", e);
+            return None;
+        }
+    };
+
+    if let Some(logo) = &mut update.logo {
+        for (key, range) in saved::logo_chunks(logo_bank, logo.len()) {
+            let chunk: Option<&[u8]> = map::fetch_item(
+                &mut flash,
+                FLASH_RANGE,
+                &mut NoCache::new(),
+                &mut buffer,
+                key,
+            )
+            .await
+            .ok()
+            .flatten();
+            match chunk {
+                Some(chunk) if chunk.len() == range.len() => logo[range].copy_from_slice(chunk),
+                _ => {
+                    warn!("saved logo is incomplete");
+                    update.logo = None;
+                    break;
+                }
+            }
+        }
+    }
+    Some(update)
+}
+
+/// Save `update` in place of what was saved before, `previous`.  The logo
+/// is only written if it changed.
+pub async fn save(
+    flash: &RefCell<ConfigFlash<'_>>,
+    update: &OwnedUpdate,
+    previous: &OwnedUpdate,
+) -> Result<(), &'static str> {
+    let mut flash = flash.borrow_mut();
+    let mut flash = BlockingAsync::new(&mut *flash);
+    let mut buffer = alloc::vec![0u8; BUFFER_LEN];
+
+    // The bank the saved content record points at
+    let saved_bank = map::fetch_item::<u8, &[u8], _>(
+        &mut flash,
+        FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buffer,
+        CONTENT_KEY,
+    )
+    .await
+    .ok()
+    .flatten()
+    .and_then(|bytes| saved::decode_content(bytes).ok())
+    .map_or(0, |(_, bank)| bank);
+
+    // A new logo goes in the other bank, the saved one stays whole until the
+    // content record below points at the new one
+    let mut logo_bank = saved_bank;
+    let logo = update
+        .logo
+        .as_ref()
+        .filter(|_| update.logo != previous.logo);
+    if let Some(logo) = logo {
+        logo_bank = (saved_bank + 1) % saved::LOGO_BANKS;
+        for (key, range) in saved::logo_chunks(logo_bank, logo.len()) {
+            map::store_item(
+                &mut flash,
+                FLASH_RANGE,
+                &mut NoCache::new(),
+                &mut buffer,
+                key,
+                &&logo[range],
+            )
+            .await
+            .map_err(|_| "failed to save logo")?;
+        }
+    }
+
+    let mut record = [0u8; saved::MAX_CONTENT_LEN];
+    let record = saved::encode_content(update, logo_bank, &mut record)?;
+    map::store_item(
+        &mut flash,
+        FLASH_RANGE,
+        &mut NoCache::new(),
+        &mut buffer,
+        CONTENT_KEY,
+        &&*record,
+    )
+    .await
+    .map_err(|_| "failed to save content")
+}
@@ -0,0 +1,197 @@
+//! The messages the badge has been sent, newest first.
+
+use core::fmt::Write as _;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{iso_8859_1::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+    Drawable,
+};
+use heapless::{String, Vec};
+
+use badge_net::{OwnedUpdate, MAX_TEXT_LEN};
+
+use crate::{BadgeApp, Button, INK};
+
+/// Messages kept, older ones are dropped
+pub const MAX_MESSAGES: usize = 8;
+
+/// Height of the line counting the messages
+const HEADER_HEIGHT: u32 = 12;
+
+pub struct Inbox {
+    messages: Vec<String<MAX_TEXT_LEN>, MAX_MESSAGES>,
+    /// Index of the message shown, 0 is the newest
+    selected: usize,
+}
+impl Inbox {
+    pub fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|message| message.as_str())
+    }
+}
+impl Default for Inbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BadgeApp for Inbox {
+    fn name(&self) -> &'static str {
+        "Messages"
+    }
+
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        crate::clear(display)?;
+        let size = display.bounding_box().size;
+        let mut header = String::<32>::new();
+        let message = self.messages.get(self.selected);
+        match message {
+            Some(_) => write!(
+                header,
+                "Message {} of {}",
+                self.selected + 1,
+                self.messages.len()
+            ),
+            None => write!(header, "No messages"),
+        }
+        .map_err(|_| "format inbox header")?;
+        Text::with_baseline(
+            &header,
+            Point::new(2, 1),
+            MonoTextStyle::new(&FONT_6X10, INK),
+            Baseline::Top,
+        )
+        .draw(display)
+        .map_err(|_| "failed to draw inbox header")?;
+
+        if let Some(message) = message {
+            let bounds = Rectangle::new(
+                Point::new(0, HEADER_HEIGHT as i32),
+                Size::new(size.width, size.height.saturating_sub(HEADER_HEIGHT)),
+            );
+            badge_draw::draw_callout(display, bounds, message)?;
+        }
+        Ok(())
+    }
+
+    /// Up shows newer messages, down older ones and B deletes the one shown
+    fn on_button(&mut self, button: Button) -> bool {
+        let before = self.selected;
+        match button {
+            Button::Up => self.selected = self.selected.saturating_sub(1),
+            Button::Down if self.selected + 1 < self.messages.len() => self.selected += 1,
+            Button::B if self.selected < self.messages.len() => {
+                self.messages.remove(self.selected);
+                self.selected = self.selected.min(self.messages.len().saturating_sub(1));
+                return true;
+            }
+            _ => {}
+        }
+        self.selected != before
+    }
+
+    /// Each new text is added as a message
+    fn on_content(&mut self, content: &OwnedUpdate, _frame: u32) -> bool {
+        let Some(text) = &content.text else {
+            return false;
+        };
+        if self.messages.first() == Some(text) || text.is_empty() {
+            return false;
+        }
+        if self.messages.is_full() {
+            self.messages.pop();
+        }
+        self.messages
+            .insert(0, text.clone())
+            .expect("room was made");
+        // Keep showing the same message unless the newest was shown
+        if self.selected != 0 {
+            self.selected = (self.selected + 1).min(self.messages.len() - 1);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::ink;
+    use badge_draw::Framebuffer;
+
+    fn text(text: &str) -> OwnedUpdate {
+        OwnedUpdate {
+            text: Some(String::try_from(text).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn messages(inbox: &Inbox) -> heapless::Vec<&str, MAX_MESSAGES> {
+        inbox.messages().collect()
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut inbox = Inbox::new();
+        assert!(inbox.on_content(&text("one"), 0));
+        // frames of the same content aren't new messages
+        assert!(!inbox.on_content(&text("one"), 1));
+        assert!(!inbox.on_content(&OwnedUpdate::default(), 0));
+        for message in [
+            "two", "three", "four", "five", "six", "seven", "eight", "nine",
+        ] {
+            inbox.on_content(&text(message), 0);
+        }
+        assert_eq!(messages(&inbox).len(), MAX_MESSAGES);
+        assert_eq!(messages(&inbox)[0], "nine");
+        assert_eq!(messages(&inbox)[MAX_MESSAGES - 1], "two");
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut inbox = Inbox::new();
+        assert!(!inbox.on_button(Button::Down));
+        assert!(!inbox.on_button(Button::B));
+        for message in ["one", "two", "three"] {
+            inbox.on_content(&text(message), 0);
+        }
+        assert!(!inbox.on_button(Button::Up));
+        assert!(inbox.on_button(Button::Down));
+        assert_eq!(inbox.selected, 1);
+        // a new message doesn't move the one being read
+        inbox.on_content(&text("four"), 0);
+        assert_eq!(inbox.selected, 2);
+        assert!(inbox.on_button(Button::B));
+        assert_eq!(messages(&inbox), ["four", "three", "one"]);
+        assert_eq!(inbox.selected, 2);
+        assert!(inbox.on_button(Button::B));
+        assert_eq!(messages(&inbox), ["four", "three"]);
+        assert_eq!(inbox.selected, 1);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut inbox = Inbox::new();
+        let mut empty = Framebuffer::badge();
+        inbox.render(&mut empty).unwrap();
+        assert!(ink(&empty) > 0);
+
+        inbox.on_content(&text("Meet at the Rust booth"), 0);
+        let mut framebuffer = Framebuffer::badge();
+        inbox.render(&mut framebuffer).unwrap();
+        // the callout's border is drawn around the message
+        assert!(ink(&framebuffer) > ink(&empty));
+    }
+}
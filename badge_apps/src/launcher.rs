@@ -0,0 +1,264 @@
+//! The launcher: a menu of apps and the app that is open.
+//!
+//! Up and down move through the menu and A or B opens the selected app.  C
+//! always goes back to the menu.  Everything is drawn turned to match how the
+//! badge is worn, the rotation from the latest content.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+    Drawable,
+};
+use heapless::Vec;
+
+use badge_draw::{Rotated, Rotation, Status};
+use badge_net::OwnedUpdate;
+
+use crate::{App, BadgeApp, Button, Inbox, NameTag, NetworkStatus, QrCode, INK, PAPER};
+
+/// Most apps a launcher holds
+pub const MAX_APPS: usize = 6;
+
+/// Height of a line in the menu
+const ROW_HEIGHT: u32 = 22;
+
+pub struct Launcher {
+    apps: Vec<App, MAX_APPS>,
+    /// App highlighted in the menu
+    selected: usize,
+    /// App shown instead of the menu
+    open: Option<usize>,
+    rotation: Rotation,
+}
+impl Launcher {
+    /// A launcher with no apps
+    pub fn new() -> Self {
+        Self {
+            apps: Vec::new(),
+            selected: 0,
+            open: None,
+            rotation: Rotation::default(),
+        }
+    }
+
+    /// The apps every badge has, with the name tag open.  The QR code app
+    /// shows `qr_text`.
+    pub fn with_builtin_apps(qr_text: &str) -> Result<Self, &'static str> {
+        let mut launcher = Self::new();
+        launcher.add(App::NameTag(NameTag::new()))?;
+        launcher.add(App::Inbox(Inbox::new()))?;
+        launcher.add(App::QrCode(QrCode::new(qr_text)?))?;
+        launcher.add(App::Network(NetworkStatus::new()))?;
+        launcher.open(0);
+        Ok(launcher)
+    }
+
+    pub fn add(&mut self, app: App) -> Result<(), &'static str> {
+        self.apps.push(app).map_err(|_| "too many apps")
+    }
+
+    /// Open the app at `index`, if there is one
+    pub fn open(&mut self, index: usize) {
+        if index < self.apps.len() {
+            self.selected = index;
+            self.open = Some(index);
+        }
+    }
+
+    /// The app shown, `None` when the menu is
+    pub fn open_app(&self) -> Option<&App> {
+        self.open.map(|index| &self.apps[index])
+    }
+
+    /// Draw the open app or the menu
+    pub fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        let mut display = Rotated::new(display, self.rotation);
+        match self.open_app() {
+            Some(app) => app.render(&mut display),
+            None => self.render_menu(&mut display),
+        }
+    }
+
+    fn render_menu(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        crate::clear(display)?;
+        let width = display.bounding_box().size.width;
+        let visible = (display.bounding_box().size.height / ROW_HEIGHT).max(1) as usize;
+        // Scroll so the selected app is always on screen
+        let first = self.selected.saturating_sub(visible - 1);
+        for (row, (index, app)) in self
+            .apps
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .enumerate()
+        {
+            let top = (row as u32 * ROW_HEIGHT) as i32;
+            let selected = index == self.selected;
+            let (ink, paper) = if selected { (PAPER, INK) } else { (INK, PAPER) };
+            Rectangle::new(Point::new(0, top), Size::new(width, ROW_HEIGHT))
+                .into_styled(PrimitiveStyle::with_fill(paper))
+                .draw(display)
+                .map_err(|_| "failed to draw menu")?;
+            Text::with_baseline(
+                app.name(),
+                Point::new(6, top + 1),
+                MonoTextStyle::new(&FONT_10X20, ink),
+                Baseline::Top,
+            )
+            .draw(display)
+            .map_err(|_| "failed to draw menu")?;
+        }
+        Ok(())
+    }
+
+    /// Handle a button press, returns whether to redraw
+    pub fn on_button(&mut self, button: Button) -> bool {
+        match (self.open, button) {
+            (Some(_), Button::C) => {
+                self.open = None;
+                true
+            }
+            (Some(index), button) => self.apps[index].on_button(button),
+            (None, Button::Up) if !self.apps.is_empty() => {
+                self.selected = self.selected.checked_sub(1).unwrap_or(self.apps.len() - 1);
+                true
+            }
+            (None, Button::Down) if !self.apps.is_empty() => {
+                self.selected = (self.selected + 1) % self.apps.len();
+                true
+            }
+            (None, Button::A | Button::B) if !self.apps.is_empty() => {
+                self.open = Some(self.selected);
+                true
+            }
+            (None, _) => false,
+        }
+    }
+
+    /// Pass content to every app, returns whether to redraw
+    pub fn on_content(&mut self, content: &OwnedUpdate, frame: u32) -> bool {
+        let rotation = content
+            .rotation
+            .and_then(|id| Rotation::try_from(id).ok())
+            .unwrap_or_default();
+        let mut redraw = rotation != self.rotation;
+        self.rotation = rotation;
+        for (index, app) in self.apps.iter_mut().enumerate() {
+            redraw |= app.on_content(content, frame) && self.open == Some(index);
+        }
+        redraw
+    }
+
+    /// Pass connection progress to every app, returns whether to redraw
+    pub fn on_status(&mut self, status: &Status) -> bool {
+        let mut redraw = false;
+        for (index, app) in self.apps.iter_mut().enumerate() {
+            redraw |= app.on_status(status) && self.open == Some(index);
+        }
+        redraw
+    }
+}
+impl Default for Launcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use badge_draw::{Framebuffer, Stage};
+
+    fn launcher() -> Launcher {
+        Launcher::with_builtin_apps("https://dev.aughey.com").unwrap()
+    }
+
+    fn open_name(launcher: &Launcher) -> Option<&'static str> {
+        launcher.open_app().map(|app| app.name())
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut launcher = launcher();
+        assert_eq!(open_name(&launcher), Some("Name tag"));
+        assert!(launcher.on_button(Button::C));
+        assert_eq!(open_name(&launcher), None);
+        // the menu wraps around
+        assert!(launcher.on_button(Button::Up));
+        assert!(launcher.on_button(Button::A));
+        assert_eq!(open_name(&launcher), Some("Network"));
+        launcher.on_button(Button::C);
+        launcher.on_button(Button::Down);
+        launcher.on_button(Button::Down);
+        assert!(launcher.on_button(Button::B));
+        assert_eq!(open_name(&launcher), Some("Messages"));
+        assert!(!launcher.on_button(Button::A));
+
+        let mut empty = Launcher::new();
+        for button in Button::ALL {
+            assert!(!empty.on_button(button));
+        }
+        assert!(empty.add(App::Inbox(Inbox::new())).is_ok());
+    }
+
+    #[test]
+    fn test_events_redraw_open_app() {
+        let mut launcher = launcher();
+        let status = Status::new(Stage::Dns, "dev.aughey.com");
+        // the name tag shows the status until there is content
+        assert!(launcher.on_status(&status));
+        let content = OwnedUpdate {
+            text: Some(heapless::String::try_from("Ferris").unwrap()),
+            ..Default::default()
+        };
+        assert!(launcher.on_content(&content, 0));
+        assert!(!launcher.on_status(&status));
+
+        launcher.on_button(Button::C);
+        launcher.on_button(Button::Up);
+        launcher.on_button(Button::A);
+        assert_eq!(open_name(&launcher), Some("Network"));
+        assert!(launcher.on_status(&Status::new(Stage::Server, "dev.aughey.com")));
+        let other = OwnedUpdate {
+            text: Some(heapless::String::try_from("Rustacean").unwrap()),
+            ..Default::default()
+        };
+        assert!(!launcher.on_content(&other, 0));
+        // a new rotation turns every app
+        let rotated = OwnedUpdate {
+            rotation: Some(Rotation::Deg90.id()),
+            ..other
+        };
+        assert!(launcher.on_content(&rotated, 0));
+    }
+
+    #[test]
+    fn test_render_menu() {
+        let mut launcher = launcher();
+        launcher.on_button(Button::C);
+        launcher.on_button(Button::Down);
+        let mut framebuffer = Framebuffer::badge();
+        launcher.render(&mut framebuffer).unwrap();
+        // the selected app is highlighted
+        assert_eq!(framebuffer.pixel(Point::new(1, 1)), Some(PAPER));
+        assert_eq!(
+            framebuffer.pixel(Point::new(1, ROW_HEIGHT as i32 + 1)),
+            Some(INK)
+        );
+        assert_eq!(
+            framebuffer.pixel(Point::new(1, 2 * ROW_HEIGHT as i32 + 1)),
+            Some(PAPER)
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Apps the badge runs, switched between with its buttons.
+//!
+//! Each app implements [`BadgeApp`]: it draws itself filling the display and
+//! reacts to button presses, content from the server and connection progress.
+//! The [`Launcher`] owns the apps, shows a menu of them and routes events to the
+//! open one.  Nothing here touches hardware, so apps are tested on the host by
+//! drawing into a `badge_draw::Framebuffer`.
+//!
+//! Apps are added as a variant of [`App`].  [`BadgeApp::render`] is generic
+//! over the display so the apps can't be trait objects.
+
+#![no_std]
+
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::BinaryColor};
+
+use badge_draw::Status;
+use badge_net::OwnedUpdate;
+
+pub mod inbox;
+pub mod launcher;
+pub mod name_tag;
+pub mod network;
+pub mod qr;
+
+pub use inbox::Inbox;
+pub use launcher::Launcher;
+pub use name_tag::NameTag;
+pub use network::NetworkStatus;
+pub use qr::QrCode;
+
+/// Note ink is `Off`, the driver treats Off as black
+const INK: BinaryColor = BinaryColor::Off;
+const PAPER: BinaryColor = BinaryColor::On;
+
+/// The buttons on the front of the badge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    C,
+    Up,
+    Down,
+}
+impl Button {
+    pub const ALL: [Button; 5] = [Button::A, Button::B, Button::C, Button::Up, Button::Down];
+
+    /// Label printed next to the button
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::A => "A",
+            Button::B => "B",
+            Button::C => "C",
+            Button::Up => "Up",
+            Button::Down => "Down",
+        }
+    }
+}
+
+/// An app shown by the [`Launcher`].
+///
+/// The event handlers return whether the app needs to be drawn again.  `C` is
+/// kept by the launcher to return to its menu, apps get the other buttons.
+pub trait BadgeApp {
+    /// Shown in the launcher menu
+    fn name(&self) -> &'static str;
+
+    /// Draw the app over the whole display
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str>;
+
+    fn on_button(&mut self, _button: Button) -> bool {
+        false
+    }
+
+    /// Content from the server or the console, and the frame of its paged or
+    /// scrolling text to show
+    fn on_content(&mut self, _content: &OwnedUpdate, _frame: u32) -> bool {
+        false
+    }
+
+    /// Progress connecting to the server
+    fn on_status(&mut self, _status: &Status) -> bool {
+        false
+    }
+}
+
+/// The apps the badge can run
+// The apps are made once and live in the launcher, so the size of the name
+// tag's content isn't worth a heap allocation.
+#[allow(clippy::large_enum_variant)]
+pub enum App {
+    NameTag(NameTag),
+    Inbox(Inbox),
+    QrCode(QrCode),
+    Network(NetworkStatus),
+}
+impl BadgeApp for App {
+    fn name(&self) -> &'static str {
+        match self {
+            App::NameTag(app) => app.name(),
+            App::Inbox(app) => app.name(),
+            App::QrCode(app) => app.name(),
+            App::Network(app) => app.name(),
+        }
+    }
+
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        match self {
+            App::NameTag(app) => app.render(display),
+            App::Inbox(app) => app.render(display),
+            App::QrCode(app) => app.render(display),
+            App::Network(app) => app.render(display),
+        }
+    }
+
+    fn on_button(&mut self, button: Button) -> bool {
+        match self {
+            App::NameTag(app) => app.on_button(button),
+            App::Inbox(app) => app.on_button(button),
+            App::QrCode(app) => app.on_button(button),
+            App::Network(app) => app.on_button(button),
+        }
+    }
+
+    fn on_content(&mut self, content: &OwnedUpdate, frame: u32) -> bool {
+        match self {
+            App::NameTag(app) => app.on_content(content, frame),
+            App::Inbox(app) => app.on_content(content, frame),
+            App::QrCode(app) => app.on_content(content, frame),
+            App::Network(app) => app.on_content(content, frame),
+        }
+    }
+
+    fn on_status(&mut self, status: &Status) -> bool {
+        match self {
+            App::NameTag(app) => app.on_status(status),
+            App::Inbox(app) => app.on_status(status),
+            App::QrCode(app) => app.on_status(status),
+            App::Network(app) => app.on_status(status),
+        }
+    }
+}
+
+/// Fill the display with paper
+fn clear(display: &mut impl DrawTarget<Color = BinaryColor>) -> Result<(), &'static str> {
+    display.clear(PAPER).map_err(|_| "failed to clear display")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use badge_draw::Framebuffer;
+    use embedded_graphics::{geometry::OriginDimensions, Pixel};
+
+    /// Number of inked pixels
+    pub fn ink(framebuffer: &Framebuffer) -> usize {
+        framebuffer
+            .pixels()
+            .filter(|Pixel(_, color)| *color == INK)
+            .count()
+    }
+
+    #[test]
+    fn test_app_names() {
+        let apps = [
+            App::NameTag(NameTag::new()),
+            App::Inbox(Inbox::new()),
+            App::QrCode(QrCode::new("https://example.com").unwrap()),
+            App::Network(NetworkStatus::new()),
+        ];
+        for app in &apps {
+            assert!(!app.name().is_empty());
+            let mut framebuffer = Framebuffer::badge();
+            app.render(&mut framebuffer).unwrap();
+            assert_eq!(framebuffer.size(), badge_draw::Panel::BADGER_2040.size);
+        }
+    }
+}
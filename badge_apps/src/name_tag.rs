@@ -0,0 +1,158 @@
+//! The wearer's name tag: the content from the server drawn with its template,
+//! theme and text mode.
+
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::BinaryColor};
+
+use badge_draw::{Logo, Status, Template, TextMode, Theme};
+use badge_net::OwnedUpdate;
+
+use crate::{BadgeApp, Button, NetworkStatus};
+
+/// Until content arrives the name tag shows the connection progress instead
+pub struct NameTag {
+    content: Option<OwnedUpdate>,
+    /// Page or scroll position of the text
+    frame: u32,
+    network: NetworkStatus,
+}
+impl NameTag {
+    pub fn new() -> Self {
+        Self {
+            content: None,
+            frame: 0,
+            network: NetworkStatus::new(),
+        }
+    }
+
+    fn mode(&self) -> TextMode {
+        self.content
+            .as_ref()
+            .and_then(|content| content.mode)
+            .and_then(|id| TextMode::try_from(id).ok())
+            .unwrap_or_default()
+    }
+}
+impl Default for NameTag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BadgeApp for NameTag {
+    fn name(&self) -> &'static str {
+        "Name tag"
+    }
+
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        let Some(content) = &self.content else {
+            return self.network.render(display);
+        };
+        let template = content
+            .layout
+            .and_then(|id| Template::try_from(id).ok())
+            .unwrap_or_default();
+        let theme = content
+            .theme
+            .and_then(|id| Theme::try_from(id).ok())
+            .unwrap_or_default();
+        // Fall back to Ferris if the server sent something that isn't a BMP
+        let logo = content
+            .logo
+            .as_deref()
+            .and_then(|bmp| Logo::from_bmp(bmp).ok());
+        let text = content.text.as_deref().unwrap_or("");
+        badge_draw::draw_template_frame(
+            display,
+            template,
+            theme,
+            text,
+            logo,
+            self.mode(),
+            self.frame,
+        )
+    }
+
+    /// Up and down turn the pages of paged text
+    fn on_button(&mut self, button: Button) -> bool {
+        if self.mode() != TextMode::Pages {
+            return false;
+        }
+        let frame = match button {
+            Button::Up => self.frame.saturating_sub(1),
+            Button::Down => self.frame.wrapping_add(1),
+            _ => self.frame,
+        };
+        let changed = frame != self.frame;
+        self.frame = frame;
+        changed
+    }
+
+    fn on_content(&mut self, content: &OwnedUpdate, frame: u32) -> bool {
+        let changed = self.content.as_ref() != Some(content) || frame != self.frame;
+        if changed {
+            self.content = Some(content.clone());
+            self.frame = frame;
+        }
+        changed
+    }
+
+    fn on_status(&mut self, status: &Status) -> bool {
+        self.network.on_status(status) && self.content.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use badge_draw::{Framebuffer, Stage};
+
+    fn content(text: &str, mode: TextMode) -> OwnedUpdate {
+        OwnedUpdate {
+            text: Some(heapless::String::try_from(text).unwrap()),
+            mode: Some(mode.id()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_status_until_content() {
+        let mut app = NameTag::new();
+        let status = Status::new(Stage::Dhcp, "home");
+        assert!(app.on_status(&status));
+
+        let mut expected = Framebuffer::badge();
+        badge_draw::draw_status(&mut expected, &status).unwrap();
+        let mut framebuffer = Framebuffer::badge();
+        app.render(&mut framebuffer).unwrap();
+        assert_eq!(framebuffer.count_differences(&expected), Some(0));
+
+        let update = content("Ferris", TextMode::Static);
+        assert!(app.on_content(&update, 0));
+        assert!(!app.on_content(&update, 0));
+        // the name stays on screen as the badge reconnects
+        assert!(!app.on_status(&Status::new(Stage::Wifi, "home")));
+
+        let mut expected = Framebuffer::badge();
+        badge_draw::draw_display(&mut expected, "Ferris", None).unwrap();
+        let mut framebuffer = Framebuffer::badge();
+        app.render(&mut framebuffer).unwrap();
+        assert_eq!(framebuffer.count_differences(&expected), Some(0));
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut app = NameTag::new();
+        app.on_content(&content("Ferris", TextMode::Static), 0);
+        assert!(!app.on_button(Button::Down));
+
+        app.on_content(&content("Ferris", TextMode::Pages), 0);
+        assert!(!app.on_button(Button::Up));
+        assert!(app.on_button(Button::Down));
+        assert_eq!(app.frame, 1);
+        assert!(!app.on_button(Button::A));
+        assert!(app.on_button(Button::Up));
+        assert_eq!(app.frame, 0);
+    }
+}
@@ -0,0 +1,93 @@
+//! Shows how far the badge has got connecting to the server.
+
+use embedded_graphics::{draw_target::DrawTarget, pixelcolor::BinaryColor};
+use heapless::String;
+
+use badge_draw::{Stage, Status};
+
+use crate::BadgeApp;
+
+/// Longest message or error kept, longer ones are cut short
+pub const MAX_MESSAGE_LEN: usize = 64;
+
+/// The last connection status, see `badge_draw::draw_status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkStatus {
+    stage: Stage,
+    message: String<MAX_MESSAGE_LEN>,
+    error: Option<String<MAX_MESSAGE_LEN>>,
+}
+impl NetworkStatus {
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Wifi,
+            message: String::try_from("Starting").expect("message fits"),
+            error: None,
+        }
+    }
+
+    pub fn status(&self) -> Status<'_> {
+        Status {
+            stage: self.stage,
+            message: &self.message,
+            error: self.error.as_deref(),
+        }
+    }
+}
+impl Default for NetworkStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BadgeApp for NetworkStatus {
+    fn name(&self) -> &'static str {
+        "Network"
+    }
+
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        crate::clear(display)?;
+        badge_draw::draw_status(display, &self.status())
+    }
+
+    fn on_status(&mut self, status: &Status) -> bool {
+        let before = self.clone();
+        self.stage = status.stage;
+        self.message = truncated(status.message);
+        self.error = status.error.map(truncated);
+        *self != before
+    }
+}
+
+/// As much of `text` as fits, cut at a character boundary
+fn truncated<const N: usize>(text: &str) -> String<N> {
+    let mut end = text.len().min(N);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    String::try_from(&text[..end]).expect("cut to fit")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(truncated::<4>("Wi-Fi").as_str(), "Wi-F");
+        assert_eq!(truncated::<4>("caf\u{e9}").as_str(), "caf");
+        assert_eq!(truncated::<8>("DNS").as_str(), "DNS");
+    }
+
+    #[test]
+    fn test_status() {
+        let mut app = NetworkStatus::new();
+        let status = Status::new(Stage::Dns, "dev.aughey.com").with_error("DNS query failed");
+        assert!(app.on_status(&status));
+        assert_eq!(app.status(), status);
+        // the same status again needs no redraw
+        assert!(!app.on_status(&status));
+    }
+}
@@ -0,0 +1,85 @@
+//! A QR code people can scan, e.g. the address of the web page that changes the badge.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+};
+use heapless::String;
+
+use crate::BadgeApp;
+
+/// Longest text encoded
+pub const MAX_QR_TEXT_LEN: usize = 128;
+
+/// The code on the left and what it encodes on the right
+pub struct QrCode {
+    text: String<MAX_QR_TEXT_LEN>,
+}
+impl QrCode {
+    pub fn new(text: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            text: String::try_from(text).map_err(|_| "qr code text too long")?,
+        })
+    }
+}
+impl BadgeApp for QrCode {
+    fn name(&self) -> &'static str {
+        "QR code"
+    }
+
+    fn render(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+    ) -> Result<(), &'static str> {
+        crate::clear(display)?;
+        let size = display.bounding_box().size;
+        let side = size.width.min(size.height);
+        badge_draw::draw_qr(
+            display,
+            Rectangle::new(Point::zero(), Size::new(side, side)),
+            &self.text,
+        )?;
+        let caption = Rectangle::new(
+            Point::new(side as i32, 0),
+            Size::new(size.width - side, size.height),
+        );
+        if !caption.is_zero_sized() {
+            badge_draw::draw_callout(display, caption, &self.text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::ink;
+    use badge_draw::Framebuffer;
+
+    #[test]
+    fn test_render() {
+        let too_long = core::str::from_utf8(&[b'x'; MAX_QR_TEXT_LEN + 1]).unwrap();
+        assert!(QrCode::new(too_long).is_err());
+
+        let app = QrCode::new("https://dev.aughey.com").unwrap();
+        let mut framebuffer = Framebuffer::badge();
+        app.render(&mut framebuffer).unwrap();
+        let mut expected = Framebuffer::new(Size::new(128, 128));
+        badge_draw::draw_qr(
+            &mut expected,
+            Rectangle::new(Point::zero(), Size::new(128, 128)),
+            "https://dev.aughey.com",
+        )
+        .unwrap();
+        // the code is drawn in the square on the left
+        assert!(ink(&expected) > 0);
+        for y in 0..128 {
+            for x in 0..128 {
+                let point = Point::new(x, y);
+                assert_eq!(framebuffer.pixel(point), expected.pixel(point));
+            }
+        }
+    }
+}
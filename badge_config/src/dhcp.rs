@@ -0,0 +1,291 @@
+//! A minimal DHCP server for the setup portal's access point, so phones and
+//! laptops joining it get an address without being set up by hand.
+//!
+//! It answers discovers with an offer and requests with an ack, or a nak when
+//! the client asks for an address it wasn't given.  Every client on the small
+//! network gets its own address, there's no gateway and nothing is persisted.
+//! Like [`crate::portal`] this is everything above the socket: the badge
+//! receives on [`SERVER_PORT`] and broadcasts the replies to [`CLIENT_PORT`].
+
+use heapless::Vec;
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+/// Largest message a client must accept, and the most the server reads
+pub const MAX_MESSAGE_LEN: usize = 576;
+
+/// Leases are short, the portal only runs for a few minutes
+pub const LEASE_SECS: u32 = 300;
+
+/// Clients given an address, the addresses after the server's are handed out in turn
+pub const MAX_LEASES: usize = 8;
+
+/// Fixed part of a message before the magic cookie
+const HEADER_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTIONS_START: usize = HEADER_LEN + MAGIC_COOKIE.len();
+/// Replies are padded to the smallest message BOOTP relays and old clients accept
+const MIN_REPLY_LEN: usize = 300;
+
+const BOOT_REQUEST: u8 = 1;
+const BOOT_REPLY: u8 = 2;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+/// Hands out addresses on a /24 network to the clients of the access point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    address: [u8; 4],
+    /// Hardware address of each client, the index picks its address
+    leases: Vec<[u8; 6], MAX_LEASES>,
+    /// Lease given up next when a new client arrives and all are taken
+    next_reused: usize,
+}
+impl Server {
+    /// A server at `address`, giving out the [`MAX_LEASES`] addresses after it
+    pub fn new(address: [u8; 4]) -> Self {
+        Self {
+            address,
+            leases: Vec::new(),
+            next_reused: 0,
+        }
+    }
+
+    /// Answer the message from a client, writing the reply into `reply`.
+    /// Returns the length of the reply, `None` for messages that don't need one.
+    pub fn handle(
+        &mut self,
+        message: &[u8],
+        reply: &mut [u8],
+    ) -> Result<Option<usize>, &'static str> {
+        if message.len() < OPTIONS_START
+            || message[0] != BOOT_REQUEST
+            || message[HEADER_LEN..OPTIONS_START] != MAGIC_COOKIE
+        {
+            return Err("not a DHCP request");
+        }
+        // Only Ethernet style hardware addresses, as Wi-Fi uses
+        if message[1] != 1 || message[2] != 6 {
+            return Err("unsupported hardware address");
+        }
+        let client: [u8; 6] = message[28..34].try_into().unwrap();
+
+        let (message_type, address) = match option(message, OPTION_MESSAGE_TYPE) {
+            Some([DISCOVER]) => (OFFER, self.lease(client)),
+            Some([REQUEST]) => {
+                // A request for another server's offer
+                if option(message, OPTION_SERVER_ID).is_some_and(|id| id != self.address) {
+                    return Ok(None);
+                }
+                let requested = match option(message, OPTION_REQUESTED_ADDRESS) {
+                    Some(&[a, b, c, d]) => [a, b, c, d],
+                    // Renewing clients put their address in ciaddr instead
+                    _ => message[12..16].try_into().unwrap(),
+                };
+                let address = self.lease(client);
+                if requested == address {
+                    (ACK, address)
+                } else {
+                    (NAK, [0; 4])
+                }
+            }
+            // Releases and declines need no answer, the lease stays until reused
+            Some(_) => return Ok(None),
+            None => return Err("missing DHCP message type"),
+        };
+        write_reply(message, message_type, address, self.address, reply).map(Some)
+    }
+
+    /// The address of the client, giving it one if it has none
+    fn lease(&mut self, client: [u8; 6]) -> [u8; 4] {
+        let index = match self.leases.iter().position(|&lease| lease == client) {
+            Some(index) => index,
+            None if !self.leases.is_full() => {
+                self.leases.push(client).unwrap();
+                self.leases.len() - 1
+            }
+            None => {
+                let index = self.next_reused;
+                self.leases[index] = client;
+                self.next_reused = (index + 1) % MAX_LEASES;
+                index
+            }
+        };
+        let [a, b, c, d] = self.address;
+        [a, b, c, d.wrapping_add(1 + index as u8)]
+    }
+}
+
+/// The value of the option `code` in a message
+fn option(message: &[u8], code: u8) -> Option<&[u8]> {
+    let mut options = message.get(OPTIONS_START..)?;
+    while let Some((&found, rest)) = options.split_first() {
+        match found {
+            OPTION_PAD => options = rest,
+            OPTION_END => return None,
+            _ => {
+                let (&len, rest) = rest.split_first()?;
+                let value = rest.get(..usize::from(len))?;
+                if found == code {
+                    return Some(value);
+                }
+                options = &rest[value.len()..];
+            }
+        }
+    }
+    None
+}
+
+fn write_reply(
+    request: &[u8],
+    message_type: u8,
+    address: [u8; 4],
+    server: [u8; 4],
+    reply: &mut [u8],
+) -> Result<usize, &'static str> {
+    if reply.len() < MIN_REPLY_LEN {
+        return Err("reply buffer too small");
+    }
+    let reply = &mut reply[..MIN_REPLY_LEN];
+    reply.fill(0);
+    reply[0] = BOOT_REPLY;
+    // Hardware type and address length, then the transaction id
+    reply[1..3].copy_from_slice(&request[1..3]);
+    reply[4..8].copy_from_slice(&request[4..8]);
+    // Flags, including whether the client wants a broadcast reply
+    reply[10..12].copy_from_slice(&request[10..12]);
+    reply[16..20].copy_from_slice(&address);
+    reply[20..24].copy_from_slice(&server);
+    // Client hardware address
+    reply[28..44].copy_from_slice(&request[28..44]);
+    reply[HEADER_LEN..OPTIONS_START].copy_from_slice(&MAGIC_COOKIE);
+
+    let mut options = OPTIONS_START;
+    let mut put = |code: u8, value: &[u8]| {
+        reply[options] = code;
+        reply[options + 1] = value.len() as u8;
+        reply[options + 2..options + 2 + value.len()].copy_from_slice(value);
+        options += 2 + value.len();
+    };
+    put(OPTION_MESSAGE_TYPE, &[message_type]);
+    put(OPTION_SERVER_ID, &server);
+    if message_type != NAK {
+        put(OPTION_LEASE_TIME, &LEASE_SECS.to_be_bytes());
+        put(OPTION_SUBNET_MASK, &[255, 255, 255, 0]);
+    }
+    reply[options] = OPTION_END;
+    Ok(MIN_REPLY_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: [u8; 4] = [192, 168, 4, 1];
+
+    fn message(client: u8, options: &[(u8, &[u8])]) -> Vec<u8, MAX_MESSAGE_LEN> {
+        let mut message = Vec::new();
+        message.resize(OPTIONS_START, 0).unwrap();
+        message[0] = BOOT_REQUEST;
+        message[1] = 1;
+        message[2] = 6;
+        message[4..8].copy_from_slice(&[1, 2, 3, client]);
+        message[28..34].copy_from_slice(&[2, 0, 0, 0, 0, client]);
+        message[HEADER_LEN..OPTIONS_START].copy_from_slice(&MAGIC_COOKIE);
+        for (code, value) in options {
+            message.push(*code).unwrap();
+            message.push(value.len() as u8).unwrap();
+            message.extend_from_slice(value).unwrap();
+        }
+        message.push(OPTION_END).unwrap();
+        message
+    }
+
+    fn handle(server: &mut Server, message: &[u8]) -> Option<([u8; 4], u8)> {
+        let mut reply = [0; MAX_MESSAGE_LEN];
+        let len = server.handle(message, &mut reply).unwrap()?;
+        let reply = &reply[..len];
+        assert_eq!(reply[0], BOOT_REPLY);
+        assert_eq!(reply[4..8], message[4..8]);
+        assert_eq!(reply[28..34], message[28..34]);
+        assert_eq!(option(reply, OPTION_SERVER_ID), Some(&SERVER[..]));
+        let message_type = option(reply, OPTION_MESSAGE_TYPE).unwrap()[0];
+        Some((reply[16..20].try_into().unwrap(), message_type))
+    }
+
+    #[test]
+    fn test_offer_and_ack() {
+        let mut server = Server::new(SERVER);
+        let offered = [192, 168, 4, 2];
+        let discover = message(1, &[(OPTION_MESSAGE_TYPE, &[DISCOVER])]);
+        assert_eq!(handle(&mut server, &discover), Some((offered, OFFER)));
+        // a second client gets the next address
+        let discover = message(2, &[(OPTION_MESSAGE_TYPE, &[DISCOVER])]);
+        assert_eq!(
+            handle(&mut server, &discover),
+            Some(([192, 168, 4, 3], OFFER))
+        );
+
+        let request = message(
+            1,
+            &[
+                (OPTION_MESSAGE_TYPE, &[REQUEST]),
+                (OPTION_SERVER_ID, &SERVER),
+                (OPTION_REQUESTED_ADDRESS, &offered),
+            ],
+        );
+        assert_eq!(handle(&mut server, &request), Some((offered, ACK)));
+    }
+
+    #[test]
+    fn test_unexpected_requests() {
+        let mut server = Server::new(SERVER);
+        // an address this server didn't give
+        let request = message(
+            1,
+            &[
+                (OPTION_MESSAGE_TYPE, &[REQUEST]),
+                (OPTION_REQUESTED_ADDRESS, &[10, 0, 0, 7]),
+            ],
+        );
+        assert_eq!(handle(&mut server, &request), Some(([0; 4], NAK)));
+        // a request for another server's offer is ignored
+        let request = message(
+            1,
+            &[
+                (OPTION_MESSAGE_TYPE, &[REQUEST]),
+                (OPTION_SERVER_ID, &[10, 0, 0, 1]),
+            ],
+        );
+        assert_eq!(handle(&mut server, &request), None);
+
+        let mut reply = [0; MAX_MESSAGE_LEN];
+        assert!(server.handle(&message(1, &[]), &mut reply).is_err());
+        assert!(server.handle(&[BOOT_REQUEST; 20], &mut reply).is_err());
+    }
+
+    #[test]
+    fn test_leases_are_reused() {
+        let mut server = Server::new(SERVER);
+        for client in 0..MAX_LEASES as u8 {
+            server.lease([2, 0, 0, 0, 0, client]);
+        }
+        // the oldest lease goes to a new client once all are taken
+        assert_eq!(server.lease([2, 0, 0, 0, 0, 0xff]), [192, 168, 4, 2]);
+        assert_eq!(server.lease([2, 0, 0, 0, 0, 1]), [192, 168, 4, 3]);
+        assert_eq!(server.lease([2, 0, 0, 0, 0, 0xff]), [192, 168, 4, 2]);
+    }
+}
@@ -0,0 +1,351 @@
+//! Badge configuration stored in flash: the Wi-Fi networks to try, the server to
+//! connect to and optionally a static IP address.
+//!
+//! The configuration is kept in a versioned record so firmware updates can read
+//! what older firmware wrote.  A record is a small header followed by the
+//! [`Config`] serialized with postcard and a CRC:
+//!
+//! | bytes   | contents                                       |
+//! |---------|------------------------------------------------|
+//! | 4       | [`MAGIC`]                                      |
+//! | 2       | format version, little endian                  |
+//! | 2       | payload length, little endian                  |
+//! | len     | postcard payload                               |
+//! | 4       | CRC-32 of the version, length and payload      |
+//!
+//! Everything here is plain data so it can be tested on the host, reading and
+//! writing the flash is up to the firmware.
+
+#![no_std]
+
+use core::cmp::Reverse;
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+pub mod dhcp;
+pub mod portal;
+
+/// Marks the start of a configuration record
+pub const MAGIC: [u8; 4] = *b"BCFG";
+
+/// Format version written by [`Config::encode`]
+pub const VERSION: u16 = 1;
+
+/// Largest encoded record, it must fit in the flash region reserved for it
+pub const MAX_RECORD_LEN: usize = 1024;
+
+const HEADER_LEN: usize = 8;
+const CRC_LEN: usize = 4;
+
+pub const MAX_NETWORKS: usize = 4;
+pub const MAX_SSID_LEN: usize = 32;
+/// WPA2 passphrases are 8 to 63 characters
+pub const MAX_PASSWORD_LEN: usize = 63;
+pub const MAX_HOST_LEN: usize = 64;
+pub const MAX_DNS_SERVERS: usize = 3;
+
+/// Server used when none is configured
+pub const DEFAULT_SERVER_HOST: &str = "dev.aughey.com";
+pub const DEFAULT_SERVER_PORT: u16 = 12345;
+
+/// A Wi-Fi network the badge may join
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Network {
+    pub ssid: String<MAX_SSID_LEN>,
+    /// WPA2 passphrase, empty for an open network
+    pub password: String<MAX_PASSWORD_LEN>,
+    /// Networks with a higher priority are tried first
+    pub priority: u8,
+}
+impl Network {
+    pub fn new(ssid: &str, password: &str, priority: u8) -> Result<Self, &'static str> {
+        let network = Self {
+            ssid: String::try_from(ssid).map_err(|_| "ssid too long")?,
+            password: String::try_from(password).map_err(|_| "password too long")?,
+            priority,
+        };
+        network.validate()?;
+        Ok(network)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.password.is_empty()
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.ssid.is_empty() {
+            return Err("empty ssid");
+        }
+        if !self.is_open() && self.password.len() < 8 {
+            return Err("password shorter than 8 characters");
+        }
+        Ok(())
+    }
+}
+
+/// Fixed IPv4 settings used instead of DHCP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaticIp {
+    pub address: [u8; 4],
+    /// Length of the network prefix, e.g. 24 for a 255.255.255.0 netmask
+    pub prefix_len: u8,
+    pub gateway: Option<[u8; 4]>,
+    pub dns_servers: Vec<[u8; 4], MAX_DNS_SERVERS>,
+}
+impl StaticIp {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.address == [0; 4] {
+            return Err("static address is unspecified");
+        }
+        if !(1..=32).contains(&self.prefix_len) {
+            return Err("invalid prefix length");
+        }
+        Ok(())
+    }
+}
+
+/// Everything the badge needs to reach the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub networks: Vec<Network, MAX_NETWORKS>,
+    pub server_host: String<MAX_HOST_LEN>,
+    pub server_port: u16,
+    /// Use DHCP if `None`
+    pub static_ip: Option<StaticIp>,
+}
+impl Config {
+    /// Add a network to try, fails if there are already [`MAX_NETWORKS`]
+    pub fn add_network(&mut self, network: Network) -> Result<(), &'static str> {
+        self.networks.push(network).map_err(|_| "too many networks")
+    }
+
+    /// Add a network to try before all the others.  It replaces any network
+    /// with the same name, and the lowest priority network if the list is full.
+    pub fn add_preferred_network(
+        &mut self,
+        ssid: &str,
+        password: &str,
+    ) -> Result<(), &'static str> {
+        let priority = self
+            .networks
+            .iter()
+            .filter(|network| network.ssid != ssid)
+            .map(|network| network.priority)
+            .max()
+            .map_or(0, |priority| priority.saturating_add(1));
+        let network = Network::new(ssid, password, priority)?;
+        self.networks.retain(|network| network.ssid != ssid);
+        if self.networks.is_full() {
+            let lowest = (0..self.networks.len())
+                .rev()
+                .min_by_key(|&i| self.networks[i].priority)
+                .expect("list is full");
+            self.networks.remove(lowest);
+        }
+        self.add_network(network)
+    }
+
+    /// The networks in the order to try them, highest priority first and in the
+    /// order they were added when priorities are equal
+    pub fn networks_by_priority(&self) -> impl Iterator<Item = &Network> {
+        let mut order: Vec<usize, MAX_NETWORKS> = (0..self.networks.len()).collect();
+        order.sort_unstable_by_key(|&i| (Reverse(self.networks[i].priority), i));
+        order.into_iter().map(|i| &self.networks[i])
+    }
+
+    /// Check the configuration can be used to connect
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.networks.is_empty() {
+            return Err("no wifi networks");
+        }
+        for network in &self.networks {
+            network.validate()?;
+        }
+        if self.server_host.is_empty() {
+            return Err("empty server host");
+        }
+        if self.server_port == 0 {
+            return Err("invalid server port");
+        }
+        if let Some(static_ip) = &self.static_ip {
+            static_ip.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Validate and write the configuration as a record into `buf`, returning
+    /// the bytes written
+    pub fn encode<'a>(&self, buf: &'a mut [u8]) -> Result<&'a [u8], &'static str> {
+        self.validate()?;
+        if buf.len() < HEADER_LEN + CRC_LEN {
+            return Err("buffer too small for config record");
+        }
+        let end = buf.len().min(MAX_RECORD_LEN) - CRC_LEN;
+        let payload_len = postcard::to_slice(self, &mut buf[HEADER_LEN..end])
+            .map_err(|_| "config too large")?
+            .len();
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        buf[6..8].copy_from_slice(&(payload_len as u16).to_le_bytes());
+        let crc_start = HEADER_LEN + payload_len;
+        let crc = crc32(&buf[4..crc_start]);
+        buf[crc_start..crc_start + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        Ok(&buf[..crc_start + CRC_LEN])
+    }
+
+    /// Read a record written by [`Config::encode`].  Trailing bytes after the
+    /// record are ignored so the whole flash region can be passed in.
+    pub fn decode(bytes: &[u8]) -> Result<Config, &'static str> {
+        let header = bytes.get(..HEADER_LEN).ok_or("config record truncated")?;
+        if header[..4] == [0xff; 4] {
+            return Err("no config record");
+        }
+        if header[..4] != MAGIC {
+            return Err("not a config record");
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        let payload_len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        if version != VERSION {
+            return Err("unsupported config version");
+        }
+        let crc_start = HEADER_LEN + payload_len;
+        if crc_start + CRC_LEN > MAX_RECORD_LEN {
+            return Err("config record too long");
+        }
+        let crc = bytes
+            .get(crc_start..crc_start + CRC_LEN)
+            .ok_or("config record truncated")?;
+        if crc32(&bytes[4..crc_start]).to_le_bytes() != crc {
+            return Err("config record corrupt");
+        }
+        let config: Config =
+            postcard::from_bytes(&bytes[HEADER_LEN..crc_start]).map_err(|_| "invalid config")?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+impl Default for Config {
+    /// No networks and the default server
+    fn default() -> Self {
+        Self {
+            networks: Vec::new(),
+            server_host: String::try_from(DEFAULT_SERVER_HOST).expect("default host fits"),
+            server_port: DEFAULT_SERVER_PORT,
+            static_ip: None,
+        }
+    }
+}
+
+/// CRC-32 as used by zip and ethernet
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_config() -> Config {
+        let mut config = Config::default();
+        config
+            .add_network(Network::new("home", "correct horse", 1).unwrap())
+            .unwrap();
+        config
+            .add_network(Network::new("phone", "battery staple", 2).unwrap())
+            .unwrap();
+        config
+            .add_network(Network::new("cafe", "", 1).unwrap())
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = example_config();
+        config.static_ip = Some(StaticIp {
+            address: [192, 168, 69, 2],
+            prefix_len: 24,
+            gateway: Some([192, 168, 69, 1]),
+            dns_servers: Vec::from_slice(&[[1, 1, 1, 1]]).unwrap(),
+        });
+        let mut buf = [0xffu8; 4096];
+        let len = config.encode(&mut buf).unwrap().len();
+        assert!(len <= MAX_RECORD_LEN);
+        // the rest of the flash region is left erased
+        assert!(buf[len..].iter().all(|&byte| byte == 0xff));
+        assert_eq!(Config::decode(&buf), Ok(config));
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let config = example_config();
+        let order: Vec<&str, MAX_NETWORKS> = config
+            .networks_by_priority()
+            .map(|network| network.ssid.as_str())
+            .collect();
+        assert_eq!(order, ["phone", "home", "cafe"]);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(Config::decode(&[0xff; 64]), Err("no config record"));
+        assert_eq!(Config::decode(&[0; 64]), Err("not a config record"));
+        assert_eq!(Config::decode(b"BCFG"), Err("config record truncated"));
+
+        let mut buf = [0xffu8; 256];
+        let len = example_config().encode(&mut buf).unwrap().len();
+        let mut corrupt = buf;
+        corrupt[HEADER_LEN] ^= 1;
+        assert_eq!(Config::decode(&corrupt), Err("config record corrupt"));
+        let mut future = buf;
+        future[4] = 2;
+        assert_eq!(Config::decode(&future), Err("unsupported config version"));
+        assert_eq!(
+            Config::decode(&buf[..len - 1]),
+            Err("config record truncated")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Config::default().validate(), Err("no wifi networks"));
+        assert_eq!(
+            Network::new("home", "short", 0),
+            Err("password shorter than 8 characters")
+        );
+        assert_eq!(Network::new("", "", 0), Err("empty ssid"));
+        assert!(Network::new("an ssid longer than thirty-two bytes", "", 0).is_err());
+
+        let mut config = example_config();
+        config.server_port = 0;
+        assert_eq!(config.validate(), Err("invalid server port"));
+        let mut buf = [0u8; 256];
+        assert!(config.encode(&mut buf).is_err());
+
+        let mut config = example_config();
+        config.static_ip = Some(StaticIp {
+            address: [10, 0, 0, 2],
+            prefix_len: 33,
+            gateway: None,
+            dns_servers: Vec::new(),
+        });
+        assert_eq!(config.validate(), Err("invalid prefix length"));
+    }
+}
@@ -0,0 +1,447 @@
+//! The provisioning portal served by the badge when it can't join any network.
+//!
+//! The badge starts an access point and serves a form for the Wi-Fi network
+//! and server.  This module is everything above the socket: parsing HTTP
+//! requests, routing them, reading the submitted form into a [`Config`] and
+//! writing the pages.  It only handles what a browser sends to a single form
+//! page, not HTTP in general.
+
+use core::fmt::{self, Write};
+
+use heapless::{String, Vec};
+
+use crate::{Config, StaticIp, MAX_HOST_LEN, MAX_PASSWORD_LEN, MAX_SSID_LEN};
+
+/// Largest request accepted, headers and body
+pub const MAX_REQUEST_LEN: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Other,
+}
+
+/// An HTTP request read from the socket
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request<'a> {
+    pub method: Method,
+    /// The path without any query string
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+/// Parse the request at the start of `buf`.  Returns `Ok(None)` if the request
+/// isn't complete yet and more should be read into `buf`.
+pub fn parse_request(buf: &[u8]) -> Result<Option<Request<'_>>, &'static str> {
+    let Some(header_end) = buf.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return if buf.len() >= MAX_REQUEST_LEN {
+            Err("request headers too long")
+        } else {
+            Ok(None)
+        };
+    };
+    let head = core::str::from_utf8(&buf[..header_end]).map_err(|_| "request is not text")?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = match request_line.next() {
+        Some("GET") => Method::Get,
+        Some("POST") => Method::Post,
+        _ => Method::Other,
+    };
+    let target = request_line.next().ok_or("missing request path")?;
+    let path = target.split('?').next().unwrap_or(target);
+
+    let mut content_length = 0;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| "invalid content length")?;
+            }
+        }
+    }
+    let body_start = header_end + 4;
+    let body_end = body_start
+        .checked_add(content_length)
+        .filter(|&end| end <= MAX_REQUEST_LEN)
+        .ok_or("request too large")?;
+    Ok(buf
+        .get(body_start..body_end)
+        .map(|body| Request { method, path, body }))
+}
+
+/// What to do with a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Show the form.  Every page is the form so the phone's captive portal
+    /// check lands on it.
+    Form,
+    /// Save the submitted form, see [`apply_form`]
+    Save,
+    NotFound,
+}
+
+pub fn route(request: &Request) -> Route {
+    match (request.method, request.path) {
+        (Method::Post, "/save") => Route::Save,
+        (Method::Get, _) => Route::Form,
+        _ => Route::NotFound,
+    }
+}
+
+/// Decode a `application/x-www-form-urlencoded` value
+fn decode<const N: usize>(value: &[u8]) -> Result<String<N>, &'static str> {
+    let hex = |digit: u8| {
+        char::from(digit)
+            .to_digit(16)
+            .ok_or("invalid form encoding")
+    };
+    let mut bytes = Vec::<u8, N>::new();
+    let mut rest = value;
+    while let Some((&byte, tail)) = rest.split_first() {
+        let (decoded, tail) = match byte {
+            b'+' => (b' ', tail),
+            b'%' => match tail {
+                [high, low, tail @ ..] => ((hex(*high)? * 16 + hex(*low)?) as u8, tail),
+                _ => return Err("invalid form encoding"),
+            },
+            byte => (byte, tail),
+        };
+        bytes.push(decoded).map_err(|_| "form field too long")?;
+        rest = tail;
+    }
+    String::from_utf8(bytes).map_err(|_| "form field is not text")
+}
+
+/// The decoded value of the field `name` in a submitted form, `None` if the
+/// form doesn't have the field
+pub fn form_value<const N: usize>(
+    body: &[u8],
+    name: &str,
+) -> Result<Option<String<N>>, &'static str> {
+    for pair in body.split(|&byte| byte == b'&') {
+        let mut parts = pair.splitn(2, |&byte| byte == b'=');
+        if parts.next() == Some(name.as_bytes()) {
+            return decode(parts.next().unwrap_or_default()).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// A non-empty field of the form
+fn field<const N: usize>(body: &[u8], name: &str) -> Result<Option<String<N>>, &'static str> {
+    Ok(form_value::<N>(body, name)?.filter(|value| !value.trim().is_empty()))
+}
+
+fn parse_ipv4(text: &str) -> Result<[u8; 4], &'static str> {
+    let mut address = [0u8; 4];
+    let mut parts = text.trim().split('.');
+    for byte in &mut address {
+        *byte = parts
+            .next()
+            .and_then(|part| part.parse().ok())
+            .ok_or("invalid IP address")?;
+    }
+    match parts.next() {
+        Some(_) => Err("invalid IP address"),
+        None => Ok(address),
+    }
+}
+
+/// Apply the submitted form to the current configuration.
+///
+/// A network entered in the form replaces any with the same name and is tried
+/// first, dropping the lowest priority network if the list is full.  Empty
+/// server fields keep the current server, an empty or missing IP address means DHCP.
+pub fn apply_form(body: &[u8], current: &Config) -> Result<Config, &'static str> {
+    let mut config = current.clone();
+
+    if let Some(ssid) = field::<MAX_SSID_LEN>(body, "ssid")? {
+        let password = form_value::<MAX_PASSWORD_LEN>(body, "password")?.unwrap_or_default();
+        config.add_preferred_network(&ssid, &password)?;
+    }
+
+    if let Some(host) = field::<MAX_HOST_LEN>(body, "host")? {
+        config.server_host = String::try_from(host.trim()).expect("trimmed host fits");
+    }
+    if let Some(port) = field::<8>(body, "port")? {
+        config.server_port = port.trim().parse().map_err(|_| "invalid port")?;
+    }
+
+    config.static_ip = match field::<18>(body, "ip")? {
+        Some(ip) => {
+            let (address, prefix_len) = ip.split_once('/').unwrap_or((&ip, "24"));
+            let dns = field::<16>(body, "dns")?;
+            Some(StaticIp {
+                address: parse_ipv4(address)?,
+                prefix_len: prefix_len
+                    .trim()
+                    .parse()
+                    .map_err(|_| "invalid prefix length")?,
+                gateway: field::<16>(body, "gateway")?
+                    .map(|gateway| parse_ipv4(&gateway))
+                    .transpose()?,
+                dns_servers: dns
+                    .map(|dns| parse_ipv4(&dns))
+                    .transpose()?
+                    .into_iter()
+                    .collect(),
+            })
+        }
+        None => None,
+    };
+
+    config.validate()?;
+    Ok(config)
+}
+
+/// Write the status line and headers of a response with an HTML body
+pub fn write_head(out: &mut impl Write, status: u16, content_len: usize) -> fmt::Result {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "",
+    };
+    write!(
+        out,
+        "HTTP/1.0 {status} {reason}\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {content_len}\r\n\
+         Connection: close\r\n\r\n"
+    )
+}
+
+/// Write text with the characters that are special in HTML escaped
+fn write_escaped(out: &mut impl Write, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '&' => out.write_str("&amp;")?,
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '"' => out.write_str("&quot;")?,
+            '\'' => out.write_str("&#39;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_ipv4(out: &mut impl Write, [a, b, c, d]: [u8; 4]) -> fmt::Result {
+    write!(out, "{a}.{b}.{c}.{d}")
+}
+
+const PAGE_START: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+<title>Badge setup</title></head><body><h1>Badge setup</h1>";
+const PAGE_END: &str = "</body></html>";
+
+/// Write the setup form filled in with the current configuration.  Passwords
+/// are never sent back.  `message` is shown above the form, e.g. why the last
+/// submission was rejected.
+pub fn write_form(out: &mut impl Write, config: &Config, message: Option<&str>) -> fmt::Result {
+    out.write_str(PAGE_START)?;
+    if let Some(message) = message {
+        out.write_str("<p><strong>")?;
+        write_escaped(out, message)?;
+        out.write_str("</strong></p>")?;
+    }
+    if !config.networks.is_empty() {
+        out.write_str("<p>Known networks, in the order they are tried:</p><ol>")?;
+        for network in config.networks_by_priority() {
+            out.write_str("<li>")?;
+            write_escaped(out, &network.ssid)?;
+            out.write_str("</li>")?;
+        }
+        out.write_str("</ol>")?;
+    }
+    out.write_str(
+        "<form method=\"post\" action=\"/save\">\
+         <h2>Wi-Fi</h2>\
+         <p><label>Network <input name=\"ssid\" maxlength=\"32\"></label></p>\
+         <p><label>Password <input name=\"password\" type=\"password\" maxlength=\"63\"></label></p>\
+         <h2>Server</h2>\
+         <p><label>Host <input name=\"host\" maxlength=\"64\" value=\"",
+    )?;
+    write_escaped(out, &config.server_host)?;
+    write!(
+        out,
+        "\"></label></p><p><label>Port <input name=\"port\" type=\"number\" value=\"{}\">\
+         </label></p>",
+        config.server_port
+    )?;
+    out.write_str(
+        "<h2>Address</h2><p>Leave the address empty to use DHCP.</p>\
+         <p><label>Address <input name=\"ip\" placeholder=\"192.168.1.50/24\" value=\"",
+    )?;
+    let static_ip = config.static_ip.as_ref();
+    if let Some(static_ip) = static_ip {
+        write_ipv4(out, static_ip.address)?;
+        write!(out, "/{}", static_ip.prefix_len)?;
+    }
+    out.write_str("\"></label></p><p><label>Gateway <input name=\"gateway\" value=\"")?;
+    if let Some(gateway) = static_ip.and_then(|static_ip| static_ip.gateway) {
+        write_ipv4(out, gateway)?;
+    }
+    out.write_str("\"></label></p><p><label>DNS server <input name=\"dns\" value=\"")?;
+    if let Some(&dns) = static_ip.and_then(|static_ip| static_ip.dns_servers.first()) {
+        write_ipv4(out, dns)?;
+    }
+    out.write_str("\"></label></p><p><button type=\"submit\">Save</button></p></form>")?;
+    out.write_str(PAGE_END)
+}
+
+/// Write the page shown once the configuration is saved
+pub fn write_saved(out: &mut impl Write) -> fmt::Result {
+    out.write_str(PAGE_START)?;
+    out.write_str("<p>Saved.  The badge is restarting to connect.</p>")?;
+    out.write_str(PAGE_END)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Network;
+
+    const FORM: &[u8] = b"ssid=My+Home%21&password=hunter2hunter2&host=badge.example.com&port=4000&ip=&gateway=&dns=";
+
+    fn post(body: &[u8], buf: &mut Vec<u8, MAX_REQUEST_LEN>) {
+        let mut head = String::<128>::new();
+        write!(
+            head,
+            "POST /save HTTP/1.1\r\nHost: 192.168.4.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        buf.extend_from_slice(head.as_bytes()).unwrap();
+        buf.extend_from_slice(body).unwrap();
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let get = b"GET /generate_204?x=1 HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let request = parse_request(get).unwrap().unwrap();
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/generate_204");
+        assert_eq!(route(&request), Route::Form);
+
+        let mut buf = Vec::new();
+        post(FORM, &mut buf);
+        // incomplete until the whole body has arrived
+        assert_eq!(parse_request(&buf[..20]), Ok(None));
+        assert_eq!(parse_request(&buf[..buf.len() - 1]), Ok(None));
+        let request = parse_request(&buf).unwrap().unwrap();
+        assert_eq!(route(&request), Route::Save);
+        assert_eq!(request.body, FORM);
+
+        assert_eq!(
+            parse_request(b"POST /save HTTP/1.1\r\nContent-Length: 99999\r\n\r\n"),
+            Err("request too large")
+        );
+        assert_eq!(
+            parse_request(b"POST /save HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n"),
+            Err("request too large")
+        );
+        assert_eq!(
+            parse_request(b"DELETE / HTTP/1.1\r\n\r\n").map(|r| r.map(|r| route(&r))),
+            Ok(Some(Route::NotFound))
+        );
+    }
+
+    #[test]
+    fn test_form_value() {
+        assert_eq!(
+            form_value::<32>(FORM, "ssid").unwrap().as_deref(),
+            Some("My Home!")
+        );
+        assert_eq!(form_value::<32>(FORM, "ip").unwrap().as_deref(), Some(""));
+        assert_eq!(form_value::<32>(FORM, "missing"), Ok(None));
+        assert_eq!(
+            form_value::<32>(b"ssid=%4", "ssid"),
+            Err("invalid form encoding")
+        );
+        assert_eq!(form_value::<2>(FORM, "ssid"), Err("form field too long"));
+    }
+
+    #[test]
+    fn test_apply_form() {
+        let mut current = Config::default();
+        current
+            .add_network(Network::new("Office", "password1", 3).unwrap())
+            .unwrap();
+        let config = apply_form(FORM, &current).unwrap();
+        let ssids: Vec<&str, 4> = config
+            .networks_by_priority()
+            .map(|network| network.ssid.as_str())
+            .collect();
+        assert_eq!(ssids, ["My Home!", "Office"]);
+        assert_eq!(config.server_host, "badge.example.com");
+        assert_eq!(config.server_port, 4000);
+        assert_eq!(config.static_ip, None);
+
+        // empty fields keep the current settings
+        let config = apply_form(b"ssid=&host=&port=&ip=", &config).unwrap();
+        assert_eq!(config.networks.len(), 2);
+        assert_eq!(config.server_port, 4000);
+
+        let config = apply_form(
+            b"ip=192.168.4.20%2F16&gateway=192.168.4.1&dns=1.1.1.1",
+            &config,
+        )
+        .unwrap();
+        let static_ip = config.static_ip.unwrap();
+        assert_eq!(static_ip.address, [192, 168, 4, 20]);
+        assert_eq!(static_ip.prefix_len, 16);
+        assert_eq!(static_ip.gateway, Some([192, 168, 4, 1]));
+        assert_eq!(static_ip.dns_servers, [[1, 1, 1, 1]]);
+
+        assert_eq!(
+            apply_form(b"ip=192.168.4", &current),
+            Err("invalid IP address")
+        );
+        assert_eq!(
+            apply_form(b"ssid=x&password=short", &current),
+            Err("password shorter than 8 characters")
+        );
+        // no network at all can't be saved
+        assert_eq!(
+            apply_form(b"host=example.com", &Config::default()),
+            Err("no wifi networks")
+        );
+    }
+
+    #[test]
+    fn test_full_network_list() {
+        let mut current = Config::default();
+        for (i, ssid) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            current
+                .add_network(Network::new(ssid, "", i as u8).unwrap())
+                .unwrap();
+        }
+        let config = apply_form(b"ssid=e", &current).unwrap();
+        let ssids: Vec<&str, 4> = config
+            .networks_by_priority()
+            .map(|network| network.ssid.as_str())
+            .collect();
+        assert_eq!(ssids, ["e", "d", "c", "b"]);
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut config = Config::default();
+        config
+            .add_network(Network::new("<script>", "password1", 0).unwrap())
+            .unwrap();
+        let mut page = String::<4096>::new();
+        write_form(&mut page, &config, Some("bad & wrong")).unwrap();
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(!page.contains("password1"));
+        assert!(page.contains("bad &amp; wrong"));
+        assert!(page.contains("value=\"dev.aughey.com\""));
+
+        let mut head = String::<256>::new();
+        write_head(&mut head, 404, page.len()).unwrap();
+        assert!(head.starts_with("HTTP/1.0 404 Not Found\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+    }
+}
@@ -0,0 +1,425 @@
+//! Command console the badge serves over USB serial.
+//!
+//! Bytes from the serial port are collected into lines by a [`LineReader`],
+//! each line is parsed into a [`Command`] by [`parse`] and the firmware runs
+//! it, using [`write_status`] and [`write_config`] for the longer replies.
+//! Replies end lines with `\r\n` as serial terminals expect.
+//!
+//! Arguments are separated by spaces, put an argument containing spaces in
+//! double quotes, e.g. `wifi add "Venue Guest" hunter22`.
+
+#![no_std]
+
+use core::fmt::{self, Write};
+
+use badge_config::Config;
+
+/// Shortest LED period the badge flashes at
+pub const LED_MIN_MS: u32 = 50;
+/// Longest LED period the badge flashes at
+pub const LED_MAX_MS: u32 = 2000;
+
+/// Printed before each command is read
+pub const PROMPT: &str = "badge> ";
+
+/// Reply to `help`
+pub const HELP: &str = "\
+commands:\r
+  help                       show this list\r
+  status                     show the badge id, uptime, LED and connection\r
+  text <message>             show a message until the server sends new content\r
+  led <ms>                   flash the LED with the given period\r
+  wifi add <ssid> [password] try a network first from the next restart\r
+  config dump                show the stored configuration\r
+  reboot                     restart the badge\r
+";
+
+/// A command typed at the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    Help,
+    Status,
+    /// Show a message, the rest of the line as typed
+    Text(&'a str),
+    /// LED period in milliseconds
+    Led(u32),
+    /// Add a Wi-Fi network, the password is empty for an open network
+    WifiAdd {
+        ssid: &'a str,
+        password: &'a str,
+    },
+    ConfigDump,
+    Reboot,
+}
+
+/// Parse a line read by a [`LineReader`]
+pub fn parse(line: &str) -> Result<Command<'_>, &'static str> {
+    let (name, rest) = next_word(line)?.ok_or("empty command")?;
+    let command = match name {
+        "help" | "?" => Command::Help,
+        "status" => Command::Status,
+        "text" => {
+            let message = rest.trim();
+            if message.is_empty() {
+                return Err("text needs a message");
+            }
+            return Ok(Command::Text(message));
+        }
+        "led" => {
+            let (period, rest) = next_word(rest)?.ok_or("led needs a period in ms")?;
+            let period: u32 = period.parse().map_err(|_| "invalid led period")?;
+            if !(LED_MIN_MS..=LED_MAX_MS).contains(&period) {
+                return Err("led period must be 50 to 2000 ms");
+            }
+            no_more(rest)?;
+            return Ok(Command::Led(period));
+        }
+        "wifi" => {
+            let (sub, rest) = next_word(rest)?.ok_or("wifi needs a subcommand, try help")?;
+            if sub != "add" {
+                return Err("unknown wifi command, try help");
+            }
+            let (ssid, rest) = next_word(rest)?.ok_or("wifi add needs an ssid")?;
+            let (password, rest) = next_word(rest)?.unwrap_or(("", rest));
+            no_more(rest)?;
+            return Ok(Command::WifiAdd { ssid, password });
+        }
+        "config" => match next_word(rest)? {
+            Some(("dump", rest)) => {
+                no_more(rest)?;
+                return Ok(Command::ConfigDump);
+            }
+            _ => return Err("unknown config command, try help"),
+        },
+        "reboot" => Command::Reboot,
+        _ => return Err("unknown command, try help"),
+    };
+    no_more(rest)?;
+    Ok(command)
+}
+
+/// Split the first word, or quoted argument, off `s`
+fn next_word(s: &str) -> Result<Option<(&str, &str)>, &'static str> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if let Some(quoted) = s.strip_prefix('"') {
+        let end = quoted.find('"').ok_or("unterminated quote")?;
+        return Ok(Some((&quoted[..end], &quoted[end + 1..])));
+    }
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    Ok(Some((&s[..end], &s[end..])))
+}
+
+fn no_more(rest: &str) -> Result<(), &'static str> {
+    if rest.trim().is_empty() {
+        Ok(())
+    } else {
+        Err("too many arguments")
+    }
+}
+
+/// Collects bytes from the serial port into lines, handling backspace
+pub struct LineReader<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// The line didn't fit and is discarded up to its end
+    overflowed: bool,
+}
+impl<const N: usize> LineReader<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Add a received byte, returning the line it completes.  Empty lines are
+    /// skipped so `\r\n` ends a single line.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, &'static str>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflowed) {
+                    return Some(Err("line too long"));
+                }
+                if len == 0 {
+                    return None;
+                }
+                Some(core::str::from_utf8(&self.buf[..len]).map_err(|_| "invalid utf-8"))
+            }
+            // Backspace and delete remove a whole character
+            0x08 | 0x7f => {
+                while self.len > 0 && self.buf[self.len - 1] & 0xc0 == 0x80 {
+                    self.len -= 1;
+                }
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len == N => {
+                self.overflowed = true;
+                None
+            }
+            _ => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+impl<const N: usize> Default for LineReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What `status` reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report<'a> {
+    pub badge_id: u64,
+    /// Firmware version
+    pub version: &'a str,
+    pub uptime_s: u64,
+    pub led_ms: u32,
+    /// How far the connection to the server has got
+    pub stage: &'a str,
+}
+
+/// Write the reply to `status`
+pub fn write_status(out: &mut impl Write, report: &Report) -> fmt::Result {
+    write!(out, "badge id: {:016x}\r\n", report.badge_id)?;
+    write!(out, "firmware: {}\r\n", report.version)?;
+    let uptime = report.uptime_s;
+    write!(
+        out,
+        "uptime: {}h {:02}m {:02}s\r\n",
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    )?;
+    write!(out, "led period: {} ms\r\n", report.led_ms)?;
+    write!(out, "connection: {}\r\n", report.stage)
+}
+
+/// Write the reply to `config dump`.  Passwords are never shown.
+pub fn write_config(out: &mut impl Write, config: &Config) -> fmt::Result {
+    write!(
+        out,
+        "server: {}:{}\r\n",
+        config.server_host, config.server_port
+    )?;
+    if config.networks.is_empty() {
+        write!(out, "networks: none\r\n")?;
+    }
+    for network in config.networks_by_priority() {
+        let security = if network.is_open() { "open" } else { "wpa2" };
+        write!(
+            out,
+            "network: \"{}\" {security}, priority {}\r\n",
+            network.ssid, network.priority
+        )?;
+    }
+    match &config.static_ip {
+        None => write!(out, "ip: dhcp\r\n"),
+        Some(static_ip) => {
+            write!(out, "ip: ")?;
+            write_ipv4(out, static_ip.address)?;
+            write!(out, "/{}", static_ip.prefix_len)?;
+            if let Some(gateway) = static_ip.gateway {
+                write!(out, " gateway ")?;
+                write_ipv4(out, gateway)?;
+            }
+            for &dns in &static_ip.dns_servers {
+                write!(out, " dns ")?;
+                write_ipv4(out, dns)?;
+            }
+            write!(out, "\r\n")
+        }
+    }
+}
+
+fn write_ipv4(out: &mut impl Write, [a, b, c, d]: [u8; 4]) -> fmt::Result {
+    write!(out, "{a}.{b}.{c}.{d}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use badge_config::{Network, StaticIp};
+
+    /// Writes into a fixed buffer so the tests run without std
+    struct Buf {
+        bytes: [u8; 512],
+        len: usize,
+    }
+    impl Buf {
+        fn new() -> Self {
+            Self {
+                bytes: [0; 512],
+                len: 0,
+            }
+        }
+
+        fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+        }
+    }
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("  status  "), Ok(Command::Status));
+        assert_eq!(
+            parse("text Hello,  my name is  Ferris "),
+            Ok(Command::Text("Hello,  my name is  Ferris"))
+        );
+        assert_eq!(parse("led 250"), Ok(Command::Led(250)));
+        assert_eq!(
+            parse("wifi add \"Venue Guest\" hunter22"),
+            Ok(Command::WifiAdd {
+                ssid: "Venue Guest",
+                password: "hunter22"
+            })
+        );
+        assert_eq!(
+            parse("wifi add cafe"),
+            Ok(Command::WifiAdd {
+                ssid: "cafe",
+                password: ""
+            })
+        );
+        assert_eq!(parse("config dump"), Ok(Command::ConfigDump));
+        assert_eq!(parse("reboot"), Ok(Command::Reboot));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("   "), Err("empty command"));
+        assert_eq!(parse("dance"), Err("unknown command, try help"));
+        assert_eq!(parse("text"), Err("text needs a message"));
+        assert_eq!(parse("led"), Err("led needs a period in ms"));
+        assert_eq!(parse("led fast"), Err("invalid led period"));
+        assert_eq!(parse("led 10"), Err("led period must be 50 to 2000 ms"));
+        assert_eq!(parse("led 100 200"), Err("too many arguments"));
+        assert_eq!(
+            parse("wifi remove home"),
+            Err("unknown wifi command, try help")
+        );
+        assert_eq!(parse("wifi add"), Err("wifi add needs an ssid"));
+        assert_eq!(parse("wifi add \"Venue Guest"), Err("unterminated quote"));
+        assert_eq!(parse("config"), Err("unknown config command, try help"));
+        assert_eq!(parse("reboot now"), Err("too many arguments"));
+    }
+
+    #[test]
+    fn test_line_reader() {
+        /// Length of the last line the input completes
+        fn read(lines: &mut LineReader<8>, input: &[u8]) -> Option<Result<usize, &'static str>> {
+            let mut last = None;
+            for &byte in input {
+                if let Some(line) = lines.push(byte) {
+                    last = Some(line.map(|line| line.len()));
+                }
+            }
+            last
+        }
+
+        let mut lines = LineReader::<8>::new();
+        assert_eq!(read(&mut lines, b"led 1"), None);
+        assert_eq!(read(&mut lines, b"00\r\n"), Some(Ok(7)));
+        // \r\n ends one line, the empty one after it is skipped
+        assert_eq!(read(&mut lines, b"\r\n"), None);
+        assert_eq!(read(&mut lines, b"statux\x7fs\r"), Some(Ok(6)));
+        // a multibyte character is removed with a single backspace
+        assert_eq!(read(&mut lines, "ab\u{e9}\x08\n".as_bytes()), Some(Ok(2)));
+        assert_eq!(
+            read(&mut lines, b"much too long\r"),
+            Some(Err("line too long"))
+        );
+        assert_eq!(read(&mut lines, b"\xff\r"), Some(Err("invalid utf-8")));
+        assert_eq!(read(&mut lines, b"help\r"), Some(Ok(4)));
+
+        let mut lines = LineReader::<16>::new();
+        let mut line = None;
+        for &byte in b"text hi\x08\x08ok\r" {
+            if let Some(Ok(text)) = lines.push(byte) {
+                line = parse(text)
+                    .ok()
+                    .map(|command| command == Command::Text("ok"));
+            }
+        }
+        assert_eq!(line, Some(true));
+    }
+
+    #[test]
+    fn test_write_status() {
+        let mut out = Buf::new();
+        write_status(
+            &mut out,
+            &Report {
+                badge_id: 0xe661_4103_e73f_2a2f,
+                version: "0.1.0",
+                uptime_s: 3 * 3600 + 4 * 60 + 5,
+                led_ms: 500,
+                stage: "Server",
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            out.as_str(),
+            "badge id: e6614103e73f2a2f\r\n\
+             firmware: 0.1.0\r\n\
+             uptime: 3h 04m 05s\r\n\
+             led period: 500 ms\r\n\
+             connection: Server\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_config() {
+        let mut config = Config::default();
+        config
+            .add_network(Network::new("home", "correct horse", 1).unwrap())
+            .unwrap();
+        config
+            .add_network(Network::new("cafe", "", 2).unwrap())
+            .unwrap();
+        config.static_ip = Some(StaticIp {
+            address: [192, 168, 69, 2],
+            prefix_len: 24,
+            gateway: Some([192, 168, 69, 1]),
+            dns_servers: [[1, 1, 1, 1]].into_iter().collect(),
+        });
+        let mut out = Buf::new();
+        write_config(&mut out, &config).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "server: dev.aughey.com:12345\r\n\
+             network: \"cafe\" open, priority 2\r\n\
+             network: \"home\" wpa2, priority 1\r\n\
+             ip: 192.168.69.2/24 gateway 192.168.69.1 dns 1.1.1.1\r\n"
+        );
+        assert!(!out.as_str().contains("correct horse"));
+
+        let mut out = Buf::new();
+        write_config(&mut out, &Config::default()).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "server: dev.aughey.com:12345\r\nnetworks: none\r\nip: dhcp\r\n"
+        );
+    }
+}
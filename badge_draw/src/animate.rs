@@ -0,0 +1,415 @@
+//! Showing text that is too long for its layout as a sequence of frames.
+//!
+//! Normally text that doesn't fit is drawn in the smallest font and cut off.
+//! With [`TextMode::Pages`] the last text region of the layout shows the text a
+//! page at a time in a readable font, and with [`TextMode::Marquee`] the text is
+//! drawn on a single line that moves along by half the region each frame.  E-ink
+//! takes a moment to refresh, so frames change every few seconds rather than
+//! scrolling smoothly.
+//!
+//! The badge and the web preview both draw frame `n` with [`draw_template_frame`]
+//! so the preview shows exactly the sequence the badge will.
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    geometry::{Point, Size},
+    mono_font::{iso_8859_1::FONT_9X15, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    text::{renderer::TextRenderer, Baseline, Text},
+    Drawable,
+};
+use embedded_text::alignment::VerticalAlignment;
+
+use crate::fit::{candidates, FontSize};
+use crate::font::{ProportionalTextStyle, Spacing};
+use crate::layout::{BadgeLayout, Template, TextRegion, TextSize};
+use crate::logo::Logo;
+use crate::qr::draw_qr;
+use crate::target::{Inverted, Scaled};
+use crate::theme::Theme;
+
+/// How text that is too long for the layout is shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TextMode {
+    /// Shrink the text to fit, cutting off whatever doesn't fit in the smallest font
+    #[default]
+    Static = 0,
+    /// Show the text a page at a time
+    Pages = 1,
+    /// Move the text along a single line
+    Marquee = 2,
+}
+impl TextMode {
+    pub const ALL: [TextMode; 3] = [TextMode::Static, TextMode::Pages, TextMode::Marquee];
+
+    /// Identifier used to select the mode in a badge update
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Human readable name of the mode
+    pub fn name(self) -> &'static str {
+        match self {
+            TextMode::Static => "Shrink to fit",
+            TextMode::Pages => "Pages",
+            TextMode::Marquee => "Scrolling marquee",
+        }
+    }
+
+    /// Time each frame stays on screen, `None` if the text doesn't move
+    pub fn frame_interval_ms(self) -> Option<u64> {
+        match self {
+            TextMode::Static => None,
+            TextMode::Pages => Some(5000),
+            TextMode::Marquee => Some(2000),
+        }
+    }
+}
+impl TryFrom<u8> for TextMode {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<TextMode, Self::Error> {
+        TextMode::ALL
+            .into_iter()
+            .find(|mode| mode.id() == value)
+            .ok_or("unknown text mode")
+    }
+}
+
+/// Font used for pages of text that doesn't fit in one go
+const PAGE_FONT: FontSize = FontSize::new(&FONT_9X15, 1);
+
+/// The region that is paged or scrolled, the last one, and the text it receives
+fn animated_region<'l, 't>(
+    layout: &'l BadgeLayout,
+    text: &'t str,
+) -> Option<(&'l TextRegion, &'t str)> {
+    layout.split_text(text).last()
+}
+
+/// The font for pages of the text: the one it would be drawn with if it fits on one
+fn page_font(region: &TextRegion, text: &str) -> FontSize {
+    let font = region.font_size(text);
+    match region.size {
+        TextSize::Fit(spacing) if !font.fits(text, region.bounds.size) => {
+            PAGE_FONT.with_spacing(spacing)
+        }
+        _ => font,
+    }
+}
+
+/// Byte offset into the text of the start of every page
+fn page_starts(region: &TextRegion, text: &str, mut on_page: impl FnMut(usize)) {
+    let font = page_font(region, text);
+    let rows = font.grid(region.bounds.size).height.max(1);
+    let mut line = 0;
+    font.wrap(text, region.bounds.size.width, |range| {
+        if line % rows == 0 {
+            on_page(range.start);
+        }
+        line += 1;
+    });
+}
+
+/// Fewest characters a marquee shows at once, so a frame is more than a letter or two
+const MARQUEE_COLUMNS: u32 = 8;
+
+/// The largest font whose lines fit the height of the region with room for
+/// [`MARQUEE_COLUMNS`] characters across it
+fn marquee_font(region: &TextRegion) -> FontSize {
+    let spacing = match region.size {
+        TextSize::Fixed(size) => size.spacing,
+        TextSize::Fit(spacing) => spacing,
+    };
+    let size = region.bounds.size;
+    candidates(spacing)
+        .find(|font| {
+            let cell = font.character_size();
+            cell.height <= size.height && cell.width * MARQUEE_COLUMNS <= size.width
+        })
+        .unwrap_or(FontSize::new(crate::fit::MONO_FONTS[0], 1).with_spacing(spacing))
+}
+
+/// Draw the text on one line starting at `position`, with the lines of the text
+/// separated by spaces.  Returns the position after the text.
+fn draw_line<S>(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    text: &str,
+    position: Point,
+    style: S,
+) -> Result<Point, &'static str>
+where
+    S: TextRenderer<Color = BinaryColor> + Clone,
+{
+    let mut position = position;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            position = Text::with_baseline(" ", position, style.clone(), Baseline::Top)
+                .draw(display)
+                .map_err(|_| "draw marquee")?;
+        }
+        position = Text::with_baseline(line, position, style.clone(), Baseline::Top)
+            .draw(display)
+            .map_err(|_| "draw marquee")?;
+    }
+    Ok(position)
+}
+
+/// The width of the text drawn as a marquee, and the width of the region, in
+/// unscaled pixels
+fn marquee_widths(region: &TextRegion, text: &str) -> (u32, u32) {
+    let font = marquee_font(region);
+    let width = line_width(font, text);
+    (width, region.bounds.size.width / font.scale.max(1))
+}
+
+fn line_width(font: FontSize, text: &str) -> u32 {
+    let measure = |line: &str| match font.spacing {
+        Spacing::Mono => {
+            MonoTextStyle::new(font.font, BinaryColor::Off)
+                .measure_string(line, Point::zero(), Baseline::Top)
+                .next_position
+                .x
+        }
+        Spacing::Proportional => {
+            ProportionalTextStyle::new(font.font, BinaryColor::Off)
+                .measure_string(line, Point::zero(), Baseline::Top)
+                .next_position
+                .x
+        }
+    };
+    let lines = text.split('\n').count() as i32;
+    (text.split('\n').map(measure).sum::<i32>() + measure(" ") * (lines - 1)).max(0) as u32
+}
+
+/// Number of frames in the sequence before it starts again
+pub fn frame_count(layout: &BadgeLayout, text: &str, mode: TextMode) -> u32 {
+    let Some((region, text)) = animated_region(layout, text) else {
+        return 1;
+    };
+    match mode {
+        TextMode::Static => 1,
+        TextMode::Pages => {
+            let mut pages = 0;
+            page_starts(region, text, |_| pages += 1);
+            pages.max(1)
+        }
+        TextMode::Marquee => {
+            let (width, visible) = marquee_widths(region, text);
+            let step = (visible / 2).max(1);
+            1 + width.saturating_sub(visible).div_ceil(step)
+        }
+    }
+}
+
+/// Draw frame `frame` of the sequence, counting from zero and starting again
+/// after the last
+pub fn draw_frame(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    layout: &BadgeLayout,
+    text: &str,
+    mode: TextMode,
+    frame: u32,
+) -> Result<(), &'static str> {
+    if layout.theme.is_inverted() {
+        draw_themed_frame(&mut Inverted::new(display), layout, text, mode, frame)
+    } else {
+        draw_themed_frame(display, layout, text, mode, frame)
+    }
+}
+
+/// Draw the theme's decoration, then the frame clipped to the theme's content area
+fn draw_themed_frame(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    layout: &BadgeLayout,
+    text: &str,
+    mode: TextMode,
+    frame: u32,
+) -> Result<(), &'static str> {
+    display.clear(BinaryColor::On).map_err(|_| "clear")?;
+    let (header, _) = layout.split_header(text);
+    layout.theme.decorate(display, header.unwrap_or(""))?;
+    let area = layout.theme.content_area(display.bounding_box().size);
+    let display = &mut display.clipped(&area);
+
+    if let (Some(bounds), (Some(qr), _)) = (layout.qr, layout.split_qr(text)) {
+        draw_qr(display, bounds, qr)?;
+    }
+
+    let animated = match mode {
+        TextMode::Static => None,
+        TextMode::Pages | TextMode::Marquee => animated_region(layout, text),
+    };
+    match animated {
+        None => {
+            for (region, text) in layout.split_text(text) {
+                region.draw(display, text)?;
+            }
+        }
+        Some((region, region_text)) => {
+            let others = layout.regions.len() - 1;
+            for (region, text) in layout.split_text(text).take(others) {
+                region.draw(display, text)?;
+            }
+            let frame = frame % frame_count(layout, text, mode);
+            if mode == TextMode::Pages {
+                draw_page(display, region, region_text, frame)?;
+            } else {
+                draw_marquee(display, region, region_text, frame)?;
+            }
+        }
+    }
+
+    if let Some((position, logo)) = &layout.logo {
+        logo.draw(display, *position)?;
+    }
+    Ok(())
+}
+
+/// Draw page `page` of the text in the region
+fn draw_page(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    region: &TextRegion,
+    text: &str,
+    page: u32,
+) -> Result<(), &'static str> {
+    let mut start = 0;
+    let mut pages = 0;
+    page_starts(region, text, |offset| {
+        if pages == page {
+            start = offset;
+        }
+        pages += 1;
+    });
+    let page_region = TextRegion {
+        size: TextSize::Fixed(page_font(region, text)),
+        // pages that don't fill the region start at the top like the others
+        vertical_alignment: if pages > 1 {
+            VerticalAlignment::Top
+        } else {
+            region.vertical_alignment
+        },
+        ..*region
+    };
+    page_region.draw(display, &text[start..])?;
+    Ok(())
+}
+
+/// Draw frame `frame` of the text moving along a line through the region
+fn draw_marquee(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    region: &TextRegion,
+    text: &str,
+    frame: u32,
+) -> Result<(), &'static str> {
+    let font = marquee_font(region);
+    let (width, visible) = marquee_widths(region, text);
+    let step = (visible / 2).max(1);
+    let offset = (frame * step).min(width.saturating_sub(visible));
+    let top = (region.bounds.size.height / font.scale.max(1))
+        .saturating_sub(font.font.character_size.height)
+        / 2;
+    let position = Point::new(-(offset as i32), top as i32);
+    let mut scaled = Scaled::new(display, region.bounds, font.scale);
+    match font.spacing {
+        Spacing::Mono => draw_line(
+            &mut scaled,
+            text,
+            position,
+            MonoTextStyle::new(font.font, BinaryColor::Off),
+        ),
+        Spacing::Proportional => draw_line(
+            &mut scaled,
+            text,
+            position,
+            ProportionalTextStyle::new(font.font, BinaryColor::Off),
+        ),
+    }?;
+    Ok(())
+}
+
+/// Like [`crate::draw_template`] with a theme, drawing frame `frame` of the sequence
+pub fn draw_template_frame(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    template: Template,
+    theme: Theme,
+    text: &str,
+    logo: Option<Logo<'_>>,
+    mode: TextMode,
+    frame: u32,
+) -> Result<(), &'static str> {
+    let size = display.bounding_box().size;
+    let layout = template.text_layout(size, logo.unwrap_or_default(), theme, text);
+    draw_frame(display, &layout, text, mode, frame)
+}
+
+/// Number of frames [`draw_template_frame`] cycles through on a display of the
+/// given size
+pub fn template_frame_count(
+    size: Size,
+    template: Template,
+    theme: Theme,
+    text: &str,
+    logo: Option<Logo<'_>>,
+    mode: TextMode,
+) -> u32 {
+    let layout = template.text_layout(size, logo.unwrap_or_default(), theme, text);
+    frame_count(&layout, text, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Framebuffer;
+    use embedded_graphics::primitives::Rectangle;
+
+    #[test]
+    fn test_mode_ids() {
+        for mode in TextMode::ALL {
+            assert_eq!(TextMode::try_from(mode.id()), Ok(mode));
+        }
+        assert!(TextMode::try_from(3).is_err());
+    }
+
+    #[test]
+    fn test_pages() {
+        let region = TextRegion::fit(Rectangle::new(Point::zero(), Size::new(100, 40)));
+        // too long for even the smallest font
+        let text = &"The quick brown fox jumps over the lazy dog. ".repeat(4);
+        let mut starts = std::vec::Vec::new();
+        page_starts(&region, text, |start| starts.push(start));
+        assert!(starts.len() > 1);
+        assert_eq!(starts[0], 0);
+        assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let layout = BadgeLayout::new().with_region(region);
+        assert_eq!(
+            frame_count(&layout, text, TextMode::Pages),
+            starts.len() as u32
+        );
+        // short text is a single page in the font it would normally use
+        assert_eq!(frame_count(&layout, "Hi", TextMode::Pages), 1);
+        assert_eq!(frame_count(&layout, text, TextMode::Static), 1);
+    }
+
+    #[test]
+    fn test_marquee() {
+        let size = Size::new(60, 20);
+        let layout =
+            BadgeLayout::new().with_region(TextRegion::fit(Rectangle::new(Point::zero(), size)));
+        let text = "Scrolling along\nslowly";
+        let frames = frame_count(&layout, text, TextMode::Marquee);
+        assert!(frames > 1);
+        assert_eq!(frame_count(&layout, "Hi", TextMode::Marquee), 1);
+
+        let render = |frame| {
+            let mut framebuffer = Framebuffer::new(size);
+            draw_frame(&mut framebuffer, &layout, text, TextMode::Marquee, frame).unwrap();
+            framebuffer
+        };
+        assert_ne!(render(0), render(1));
+        // the sequence starts again after the last frame
+        assert_eq!(render(0), render(frames));
+    }
+}
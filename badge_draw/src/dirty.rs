@@ -0,0 +1,310 @@
+//! Working out which part of the display changed so e-ink panels can do a
+//! partial refresh.
+//!
+//! A [`Shadow`] keeps a copy of the last frame drawn.  Drawing through
+//! [`Shadow::track`] compares every pixel with that copy and returns the
+//! bounding rectangle of the pixels that actually changed.  [`RefreshPolicy`]
+//! then decides between a partial refresh of that rectangle and a full refresh,
+//! which is needed every so often to clear ghosting.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    Pixel,
+};
+
+use crate::panel::Panel;
+
+/// Bytes needed to shadow the 296x128 badge display
+pub const BADGE_FRAME_LEN: usize = Panel::BADGER_2040.frame_len();
+
+/// Copy of the last frame drawn, one bit per pixel.
+/// `N` must be at least `width.div_ceil(8) * height` bytes.
+#[derive(Debug, Clone)]
+pub struct Shadow<const N: usize = BADGE_FRAME_LEN> {
+    size: Size,
+    bits: [u8; N],
+}
+impl<const N: usize> Shadow<N> {
+    /// A frame of the given size with every pixel `On`, panics if `N` is too small
+    pub fn new(size: Size) -> Self {
+        assert!(
+            size.width.div_ceil(8) as usize * size.height as usize <= N,
+            "shadow frame too small for the display"
+        );
+        Self {
+            size,
+            bits: [0xff; N],
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    fn index(&self, point: Point) -> Option<(usize, u8)> {
+        let (x, y) = (u32::try_from(point.x).ok()?, u32::try_from(point.y).ok()?);
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+        let byte = (y * self.size.width.div_ceil(8) + x / 8) as usize;
+        Some((byte, 0x80 >> (x % 8)))
+    }
+
+    pub fn get(&self, point: Point) -> Option<BinaryColor> {
+        self.index(point)
+            .map(|(byte, mask)| (self.bits[byte] & mask != 0).into())
+    }
+
+    pub fn set(&mut self, point: Point, color: BinaryColor) {
+        if let Some((byte, mask)) = self.index(point) {
+            if color.is_on() {
+                self.bits[byte] |= mask;
+            } else {
+                self.bits[byte] &= !mask;
+            }
+        }
+    }
+
+    /// Run `draw` against the display and return the area that differs from the
+    /// previous frame, or `None` if nothing changed.
+    pub fn track<D, E>(
+        &mut self,
+        display: &mut D,
+        draw: impl FnOnce(&mut Tracked<'_, D, N>) -> Result<(), E>,
+    ) -> Result<Option<Rectangle>, E>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let previous = self.bits;
+        draw(&mut Tracked {
+            target: display,
+            shadow: self,
+        })?;
+        Ok(self.changed_since(&previous))
+    }
+
+    /// Bounding box of the pixels that differ from an earlier copy of the frame
+    fn changed_since(&self, previous: &[u8; N]) -> Option<Rectangle> {
+        let row_len = self.size.width.div_ceil(8) as usize;
+        let mut changed: Option<(Point, Point)> = None;
+        let rows = self
+            .bits
+            .chunks_exact(row_len)
+            .zip(previous.chunks_exact(row_len));
+        for (y, (row, previous)) in rows.take(self.size.height as usize).enumerate() {
+            for (byte, (new, old)) in row.iter().zip(previous).enumerate() {
+                let diff = new ^ old;
+                if diff == 0 {
+                    continue;
+                }
+                let x = byte as i32 * 8;
+                let first = Point::new(x + diff.leading_zeros() as i32, y as i32);
+                let last = Point::new(x + 7 - diff.trailing_zeros() as i32, y as i32);
+                changed = Some(match changed {
+                    Some((start, end)) => (start.component_min(first), end.component_max(last)),
+                    None => (first, last),
+                });
+            }
+        }
+        changed.map(|(start, end)| Rectangle::with_corners(start, end))
+    }
+}
+
+/// Draw target passing pixels through to the display while recording them in
+/// the [`Shadow`] frame
+pub struct Tracked<'a, D, const N: usize> {
+    target: &'a mut D,
+    shadow: &'a mut Shadow<N>,
+}
+impl<D: DrawTarget<Color = BinaryColor>, const N: usize> Dimensions for Tracked<'_, D, N> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+impl<D: DrawTarget<Color = BinaryColor>, const N: usize> DrawTarget for Tracked<'_, D, N> {
+    type Color = BinaryColor;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let shadow = &mut *self.shadow;
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .inspect(|&Pixel(point, color)| shadow.set(point, color)),
+        )
+    }
+}
+
+/// Grow the rectangle so its edges fall on multiples of `align` pixels, clipped
+/// to a display of the given size.  Panel controllers address partial windows
+/// in whole bytes.
+pub fn align(area: Rectangle, align: u32, display: Size) -> Rectangle {
+    let align = align.max(1) as i32;
+    let Some(end) = area.bottom_right() else {
+        return area;
+    };
+    let start = Point::new(
+        area.top_left.x.div_euclid(align) * align,
+        area.top_left.y.div_euclid(align) * align,
+    );
+    let end = Point::new(
+        (end.x.div_euclid(align) + 1) * align,
+        (end.y.div_euclid(align) + 1) * align,
+    );
+    Rectangle::with_corners(start, end - Point::new(1, 1))
+        .intersection(&Rectangle::new(Point::zero(), display))
+}
+
+/// How the panel should be refreshed after drawing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    /// Nothing changed
+    None,
+    /// Refresh just this area
+    Partial(Rectangle),
+    /// Refresh the whole panel
+    Full,
+}
+
+/// Chooses partial refreshes for small changes with a full refresh every
+/// [`RefreshPolicy::max_partial`] updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    /// Partial refreshes allowed between full refreshes
+    pub max_partial: u32,
+    /// Changes covering more than this percentage of the display use a full refresh
+    pub max_partial_percent: u32,
+    /// Partial refreshes since the last full refresh.  Starts at the maximum so
+    /// the first refresh, when the panel contents are unknown, is a full one.
+    partial_count: u32,
+}
+impl RefreshPolicy {
+    pub const fn new(max_partial: u32, max_partial_percent: u32) -> Self {
+        Self {
+            max_partial,
+            max_partial_percent,
+            partial_count: max_partial,
+        }
+    }
+
+    /// Decide how to refresh a display of the given size after `dirty` changed
+    pub fn refresh(&mut self, dirty: Option<Rectangle>, display: Size) -> Refresh {
+        let Some(dirty) = dirty else {
+            return Refresh::None;
+        };
+        let area = |size: Size| size.width as u64 * size.height as u64;
+        let large = area(dirty.size) * 100 > area(display) * self.max_partial_percent as u64;
+        if large || self.partial_count >= self.max_partial {
+            self.force_full();
+            Refresh::Full
+        } else {
+            self.partial_count += 1;
+            Refresh::Partial(dirty)
+        }
+    }
+
+    /// Record a full refresh done outside of the policy
+    pub fn force_full(&mut self) {
+        self.partial_count = 0;
+    }
+}
+impl Default for RefreshPolicy {
+    /// A full refresh every 10 updates or when more than half the display changes
+    fn default() -> Self {
+        Self::new(10, 50)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{Primitive, PrimitiveStyle};
+    use embedded_graphics::Drawable;
+
+    /// Draw target that discards every pixel
+    struct Null;
+    impl Dimensions for Null {
+        fn bounding_box(&self) -> Rectangle {
+            Rectangle::new(Point::zero(), Size::new(296, 128))
+        }
+    }
+    impl DrawTarget for Null {
+        type Color = BinaryColor;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            pixels.into_iter().for_each(drop);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_track_changes() {
+        let mut shadow = Shadow::<BADGE_FRAME_LEN>::new(Size::new(296, 128));
+        let square = Rectangle::new(Point::new(10, 20), Size::new(5, 6));
+        let draw = |display: &mut Tracked<'_, Null, BADGE_FRAME_LEN>| {
+            display.clear(BinaryColor::On)?;
+            square
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                .draw(display)
+        };
+        // clearing to the initial colour changes nothing, so only the square is dirty
+        assert_eq!(shadow.track(&mut Null, draw), Ok(Some(square)));
+        assert_eq!(shadow.get(Point::new(10, 20)), Some(BinaryColor::Off));
+        // drawing the same frame again changes nothing
+        assert_eq!(shadow.track(&mut Null, draw), Ok(None));
+        // clearing covers the old square
+        let cleared = shadow.track(&mut Null, |display| display.clear(BinaryColor::On));
+        assert_eq!(cleared, Ok(Some(square)));
+    }
+
+    #[test]
+    fn test_align() {
+        let display = Size::new(296, 128);
+        let area = Rectangle::new(Point::new(10, 3), Size::new(5, 6));
+        assert_eq!(
+            align(area, 8, display),
+            Rectangle::new(Point::new(8, 0), Size::new(8, 16))
+        );
+        let edge = Rectangle::new(Point::new(290, 120), Size::new(6, 8));
+        assert_eq!(
+            align(edge, 8, display),
+            Rectangle::new(Point::new(288, 120), Size::new(8, 8))
+        );
+    }
+
+    #[test]
+    fn test_refresh_policy() {
+        let display = Size::new(296, 128);
+        let small = Rectangle::new(Point::zero(), Size::new(10, 10));
+        let mut policy = RefreshPolicy::new(2, 50);
+        assert_eq!(policy.refresh(None, display), Refresh::None);
+        // the first refresh is always full
+        assert_eq!(policy.refresh(Some(small), display), Refresh::Full);
+        assert_eq!(
+            policy.refresh(Some(small), display),
+            Refresh::Partial(small)
+        );
+        assert_eq!(
+            policy.refresh(Some(small), display),
+            Refresh::Partial(small)
+        );
+        assert_eq!(policy.refresh(Some(small), display), Refresh::Full);
+        // large changes are always full
+        let large = Rectangle::new(Point::zero(), Size::new(200, 128));
+        assert_eq!(policy.refresh(Some(large), display), Refresh::Full);
+        assert_eq!(
+            policy.refresh(Some(small), display),
+            Refresh::Partial(small)
+        );
+    }
+}
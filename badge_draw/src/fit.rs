@@ -0,0 +1,303 @@
+//! Choosing the largest font that fits text into an area.
+//!
+//! Candidates are the built-in mono fonts plus integer magnifications of them,
+//! so a single short word can be drawn much larger than `FONT_10X20`.
+
+use core::ops::Range;
+
+use embedded_graphics::geometry::Size;
+use embedded_graphics::mono_font::iso_8859_1::{
+    FONT_10X20, FONT_4X6, FONT_5X8, FONT_6X10, FONT_7X13, FONT_9X15,
+};
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::pixelcolor::BinaryColor;
+
+use crate::font::{ProportionalTextStyle, Spacing};
+
+/// Mono fonts considered when fitting text, smallest first
+pub const MONO_FONTS: [&MonoFont<'static>; 6] = [
+    &FONT_4X6,
+    &FONT_5X8,
+    &FONT_6X10,
+    &FONT_7X13,
+    &FONT_9X15,
+    &FONT_10X20,
+];
+
+/// Largest magnification applied to a font
+pub const MAX_SCALE: u32 = 4;
+
+/// A mono font drawn at an integer magnification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontSize {
+    pub font: &'static MonoFont<'static>,
+    pub scale: u32,
+    pub spacing: Spacing,
+}
+impl FontSize {
+    pub const fn new(font: &'static MonoFont<'static>, scale: u32) -> Self {
+        Self {
+            font,
+            scale,
+            spacing: Spacing::Mono,
+        }
+    }
+
+    pub const fn with_spacing(mut self, spacing: Spacing) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// Size in pixels of one character cell, including the spacing between characters
+    pub fn character_size(&self) -> Size {
+        Size::new(
+            (self.font.character_size.width + self.font.character_spacing) * self.scale,
+            self.font.character_size.height * self.scale,
+        )
+    }
+
+    /// Number of columns and rows of characters that fit into an area of the given size
+    pub fn grid(&self, size: Size) -> Size {
+        let cell = self.character_size();
+        let spacing = self.font.character_spacing * self.scale;
+        Size::new(
+            (size.width + spacing) / cell.width.max(1),
+            size.height / cell.height.max(1),
+        )
+    }
+
+    /// Word wrap the text into lines at most `width` pixels wide, calling `on_line`
+    /// with the byte range of each line, see [`wrap_by`]
+    pub fn wrap(&self, text: &str, width: u32, on_line: impl FnMut(Range<usize>)) {
+        match self.spacing {
+            Spacing::Mono => {
+                let columns = self.grid(Size::new(width, 0)).width;
+                wrap_by(
+                    text,
+                    columns.max(1),
+                    |word| word.chars().count() as u32,
+                    on_line,
+                )
+            }
+            Spacing::Proportional => {
+                let style = ProportionalTextStyle::new(self.font, BinaryColor::Off);
+                wrap_by(
+                    text,
+                    width / self.scale.max(1),
+                    |word| style.width(word),
+                    on_line,
+                )
+            }
+        }
+    }
+
+    /// Whether the text, word wrapped, fits into an area of the given size
+    pub fn fits(&self, text: &str, size: Size) -> bool {
+        let grid = self.grid(size);
+        match self.spacing {
+            Spacing::Mono => grid.width > 0 && wrapped_lines(text, grid.width) <= grid.height,
+            Spacing::Proportional => {
+                let style = ProportionalTextStyle::new(self.font, BinaryColor::Off);
+                let width = size.width / self.scale.max(1);
+                wrapped_lines_by(text, width, |line| style.width(line)) <= grid.height
+            }
+        }
+    }
+}
+
+/// Every font and scale combination, largest characters first
+pub fn candidates(spacing: Spacing) -> impl Iterator<Item = FontSize> {
+    let mut sizes = heapless::Vec::<FontSize, { MONO_FONTS.len() * MAX_SCALE as usize }>::new();
+    for font in MONO_FONTS {
+        for scale in 1..=MAX_SCALE {
+            // capacity is exactly fonts x scales
+            let _ = sizes.push(FontSize::new(font, scale).with_spacing(spacing));
+        }
+    }
+    sizes.sort_unstable_by_key(|size| {
+        let cell = size.character_size();
+        core::cmp::Reverse((cell.height, cell.width))
+    });
+    sizes.into_iter()
+}
+
+/// Pick the largest font size that fits the text into an area of the given size.
+/// Returns `None` if the text does not fit even with the smallest font.
+pub fn fit_font(text: &str, size: Size) -> Option<FontSize> {
+    fit_font_with_spacing(text, size, Spacing::Mono)
+}
+
+/// Like [`fit_font`], for text drawn with the given spacing
+pub fn fit_font_with_spacing(text: &str, size: Size, spacing: Spacing) -> Option<FontSize> {
+    candidates(spacing).find(|candidate| candidate.fits(text, size))
+}
+
+/// Number of lines needed to word wrap the text at `columns` characters per line.
+/// Lines are broken at spaces and explicit newlines, words longer than a line are split.
+pub fn wrapped_lines(text: &str, columns: u32) -> u32 {
+    wrapped_lines_by(text, columns.max(1), |line| line.chars().count() as u32)
+}
+
+/// Number of lines needed to word wrap the text into lines at most `max_width` wide,
+/// where `width_of` measures a word or a space.
+/// Lines are broken at spaces and explicit newlines, words longer than a line are split.
+pub fn wrapped_lines_by(text: &str, max_width: u32, width_of: impl Fn(&str) -> u32) -> u32 {
+    let mut lines = 0;
+    wrap_by(text, max_width, width_of, |_| lines += 1);
+    lines
+}
+
+/// Word wrap the text like [`wrapped_lines_by`], calling `on_line` with the byte
+/// range of each line in order.
+///
+/// Lines are broken the way embedded-text's `TextBox` breaks them when it draws
+/// the text.  A line is as wide as its words and the spaces between them.  Spaces
+/// that don't fit at the end of a line are dropped along with one more, any
+/// further spaces start the next line.  Spaces at the end of a line are left out
+/// of its range.  Unlike `TextBox`, a single character is never split off a
+/// word or dropped, even if it is wider than a line.
+pub fn wrap_by(
+    text: &str,
+    max_width: u32,
+    width_of: impl Fn(&str) -> u32,
+    mut on_line: impl FnMut(Range<usize>),
+) {
+    let space = width_of(" ").max(1);
+    let mut paragraph_start = 0;
+    for paragraph in text.split('\n') {
+        let base = paragraph_start;
+        paragraph_start += paragraph.len() + 1;
+        // Start of the current line, the end of its last word and its width so far
+        let (mut start, mut end, mut width) = (0, 0, 0);
+        let mut offset = 0;
+        // Whether the last line ended by splitting a word
+        let mut split_word = false;
+        while offset < paragraph.len() {
+            split_word = false;
+            let rest = &paragraph[offset..];
+            let spaces = rest.len() - rest.trim_start_matches(' ').len();
+            if spaces > 0 {
+                let spaces_width = spaces as u32 * space;
+                if width + spaces_width <= max_width {
+                    width += spaces_width;
+                    offset += spaces;
+                } else {
+                    on_line(base + start..base + end);
+                    let fitting = ((max_width - width) / space) as usize;
+                    offset += (fitting + 1).min(spaces);
+                    (start, end, width) = (offset, offset, 0);
+                }
+                continue;
+            }
+
+            let word = &rest[..rest.find(' ').unwrap_or(rest.len())];
+            let word_width = width_of(word);
+            if width + word_width <= max_width {
+                width += word_width;
+                offset += word.len();
+                end = offset;
+            } else if end > start {
+                // The word goes on the next line
+                on_line(base + start..base + end);
+                (start, end, width) = (offset, offset, 0);
+            } else {
+                // The word doesn't fit on a line of its own, split off what fits
+                let first = word.chars().next().map_or(0, char::len_utf8);
+                let split = word
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .skip(1)
+                    .take_while(|&i| width + width_of(&word[..i]) <= max_width)
+                    .last()
+                    .unwrap_or(first);
+                on_line(base + start..base + offset + split);
+                offset += split;
+                (start, end, width) = (offset, offset, 0);
+                split_word = true;
+            }
+        }
+        // A split word ending the paragraph doesn't leave an empty line after it
+        if !split_word {
+            on_line(base + start..base + end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapped_lines() {
+        assert_eq!(wrapped_lines("", 10), 1);
+        assert_eq!(wrapped_lines("Hello", 10), 1);
+        assert_eq!(wrapped_lines("Hello World", 10), 2);
+        assert_eq!(wrapped_lines("Hello\nWorld", 20), 2);
+        assert_eq!(wrapped_lines("abcdefghijkl", 5), 3);
+        assert_eq!(wrapped_lines("a abcdefghij", 5), 3);
+    }
+
+    #[test]
+    fn test_wrapped_lines_by_width() {
+        // wide characters count double
+        let width = |line: &str| line.chars().map(|c| if c == 'W' { 2 } else { 1 }).sum();
+        assert_eq!(wrapped_lines_by("WWW", 6, width), 1);
+        assert_eq!(wrapped_lines_by("WWW a", 6, width), 2);
+        assert_eq!(wrapped_lines_by("WWWW", 6, width), 2);
+        assert_eq!(wrapped_lines_by("ab  cd", 6, width), 1);
+        // a character wider than a line still takes a line
+        assert_eq!(wrapped_lines_by("WW", 1, width), 2);
+    }
+
+    #[test]
+    fn test_wrap_by() {
+        let wrap = |text: &'static str, columns| {
+            let mut lines = std::vec::Vec::new();
+            wrap_by(
+                text,
+                columns,
+                |word| word.chars().count() as u32,
+                |range| lines.push(&text[range]),
+            );
+            lines
+        };
+        assert_eq!(wrap("", 5), [""]);
+        assert_eq!(wrap("Hello World", 5), ["Hello", "World"]);
+        // one space is dropped where the line breaks, the other starts the next line
+        assert_eq!(wrap("ab cd  ef", 5), ["ab cd", " ef"]);
+        assert_eq!(wrap("ab  ", 3), ["ab", ""]);
+        assert_eq!(wrap("abcdefg\n\nhi", 5), ["abcde", "fg", "", "hi"]);
+        assert_eq!(wrap("a abcdefghij", 5), ["a", "abcde", "fghij"]);
+    }
+
+    #[test]
+    fn test_proportional_fits_more() {
+        let size = Size::new(139, 118);
+        let text = "illicit little lilies";
+        let mono = fit_font(text, size).unwrap();
+        let proportional = fit_font_with_spacing(text, size, Spacing::Proportional).unwrap();
+        assert_eq!(proportional.spacing, Spacing::Proportional);
+        assert!(proportional.character_size().height >= mono.character_size().height);
+        assert!(proportional.fits(text, size));
+    }
+
+    #[test]
+    fn test_short_text_is_large() {
+        let size = fit_font("Bob", Size::new(139, 118)).unwrap();
+        assert_eq!(size.scale, 4);
+        assert_eq!(size.character_size(), Size::new(40, 80));
+    }
+
+    #[test]
+    fn test_long_text_is_small() {
+        let text = "The quick brown fox jumps over the lazy dog, then it does it again and again";
+        let size = fit_font(text, Size::new(139, 118)).unwrap();
+        assert!(size.fits(text, Size::new(139, 118)));
+        assert!(size.character_size().height <= FONT_10X20.character_size.height);
+    }
+
+    #[test]
+    fn test_nothing_fits() {
+        assert!(fit_font("Hello", Size::new(2, 2)).is_none());
+    }
+}
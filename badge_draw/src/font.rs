@@ -0,0 +1,424 @@
+//! Character set and proportional rendering of the badge fonts.
+//!
+//! The badge uses the Latin-1 (ISO 8859-1) versions of the embedded-graphics
+//! mono fonts, so accented names and symbols such as `©`, `°` or `½` can be
+//! drawn.  Common characters outside Latin-1, like curly quotes and a few
+//! emoji, are replaced by close equivalents when text is sanitized.
+//!
+//! [`ProportionalTextStyle`] draws the same fonts with each glyph only as wide
+//! as its ink, which fits more text on a line and reads more naturally.
+
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    image::{Image, ImageDrawableExt},
+    mono_font::MonoFont,
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    text::{
+        renderer::{CharacterStyle, TextMetrics, TextRenderer},
+        Baseline,
+    },
+    Drawable, Pixel,
+};
+
+use crate::fit::MONO_FONTS;
+
+/// Whether the badge fonts have a glyph for the character
+pub fn can_render(c: char) -> bool {
+    matches!(c, '\n' | ' '..='~' | '\u{a0}'..='\u{ff}')
+}
+
+/// Replacement for a character the fonts can't draw, if there is a sensible one
+pub fn substitute(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' => "\"",
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2022}' | '\u{2219}' => "\u{b7}",
+        '\u{20ac}' => "EUR",
+        '\u{2122}' => "TM",
+        '\u{2192}' | '\u{27a1}' => "->",
+        '\u{2190}' => "<-",
+        '\u{2713}' | '\u{2714}' | '\u{2705}' => "v",
+        '\u{2605}' | '\u{2606}' | '\u{2b50}' => "*",
+        '\u{2764}' | '\u{2665}' | '\u{1f496}' | '\u{1f499}' => "<3",
+        '\u{1f642}' | '\u{1f60a}' | '\u{263a}' => ":)",
+        '\u{1f600}' | '\u{1f603}' | '\u{1f604}' | '\u{1f601}' => ":D",
+        '\u{1f609}' => ";)",
+        '\u{1f61b}' | '\u{1f61c}' => ":P",
+        '\u{1f641}' | '\u{1f61e}' | '\u{2639}' => ":(",
+        '\u{1f622}' | '\u{1f62d}' => ":'(",
+        '\u{1f44d}' => "+1",
+        '\u{1f980}' => "Ferris",
+        _ => return None,
+    })
+}
+
+/// The characters of `text` the badge can draw, with substitutions applied and
+/// anything else removed.
+pub fn sanitize(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().flat_map(|c| {
+        let (c, replacement) = if can_render(c) {
+            (Some(c), "")
+        } else {
+            (None, substitute(c).unwrap_or(""))
+        };
+        c.into_iter().chain(replacement.chars())
+    })
+}
+
+/// How characters are placed along a line
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Spacing {
+    /// Every character takes the full width of the font
+    #[default]
+    Mono,
+    /// Characters are as wide as their glyph, see [`ProportionalTextStyle`]
+    Proportional,
+}
+
+/// Pixels between two glyphs drawn proportionally
+const LETTER_SPACING: u32 = 1;
+
+/// Number of glyphs measured, enough for the Latin-1 fonts
+const MAX_GLYPHS: usize = 256;
+
+/// Draws a mono font proportionally: blank columns either side of each glyph are
+/// removed and glyphs are separated by a single pixel.  Spaces are half the
+/// width of the font.
+#[derive(Debug, Clone)]
+pub struct ProportionalTextStyle<'a> {
+    pub font: &'a MonoFont<'a>,
+    pub text_color: Option<BinaryColor>,
+    glyphs: Glyphs,
+}
+impl<'a> ProportionalTextStyle<'a> {
+    pub fn new(font: &'a MonoFont<'a>, text_color: BinaryColor) -> Self {
+        Self {
+            font,
+            text_color: Some(text_color),
+            glyphs: Glyphs::of(font),
+        }
+    }
+
+    /// First ink column and ink width of the glyph for `c`
+    fn glyph(&self, c: char) -> (u8, u8) {
+        self.glyphs.get(self.font.glyph_mapping.index(c))
+    }
+
+    /// Horizontal space taken by `c`, not including the spacing after it
+    fn advance(&self, c: char) -> u32 {
+        match self.glyph(c) {
+            (_, 0) => (self.font.character_size.width / 2).max(1),
+            (_, width) => u32::from(width),
+        }
+    }
+
+    /// Width in pixels of a single line of text
+    pub fn width(&self, text: &str) -> u32 {
+        let (count, width) = text.chars().fold((0u32, 0), |(count, width), c| {
+            (count + 1, width + self.advance(c))
+        });
+        width + LETTER_SPACING * count.saturating_sub(1)
+    }
+
+    fn baseline_offset(&self, baseline: Baseline) -> i32 {
+        let height = self.font.character_size.height as i32;
+        match baseline {
+            Baseline::Top => 0,
+            Baseline::Bottom => height.saturating_sub(1),
+            Baseline::Middle => height.saturating_sub(1) / 2,
+            Baseline::Alphabetic => self.font.baseline as i32,
+        }
+    }
+}
+impl TextRenderer for ProportionalTextStyle<'_> {
+    type Color = BinaryColor;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let offset = Point::new(0, self.baseline_offset(baseline));
+        let mut position = position - offset;
+        for (i, c) in text.chars().enumerate() {
+            if i > 0 {
+                position.x += LETTER_SPACING as i32;
+            }
+            let (left, width) = self.glyph(c);
+            if let (Some(color), true) = (self.text_color, width > 0) {
+                let area = glyph_area(self.font, self.font.glyph_mapping.index(c));
+                let glyph = self.font.image.sub_image(&area);
+                Image::new(&glyph, position - Point::new(i32::from(left), 0))
+                    .draw(&mut Foreground { target, color })?;
+            }
+            position.x += self.advance(c) as i32;
+        }
+        Ok(position + offset)
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        _baseline: Baseline,
+        _target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+        let size = Size::new(self.width(text), self.font.character_size.height);
+        TextMetrics {
+            bounding_box: Rectangle::new(
+                position - Point::new(0, self.baseline_offset(baseline)),
+                size,
+            ),
+            next_position: position + size.x_axis(),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.character_size.height
+    }
+}
+impl CharacterStyle for ProportionalTextStyle<'_> {
+    type Color = BinaryColor;
+
+    fn set_text_color(&mut self, text_color: Option<Self::Color>) {
+        self.text_color = text_color;
+    }
+}
+
+/// First ink column and ink width of each glyph of a font
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+enum Glyphs {
+    /// One of the [`MONO_FONTS`], measured once and shared by every style
+    Cached(&'static GlyphTable),
+    /// Any other font, measured for this style
+    Measured([(u8, u8); MAX_GLYPHS]),
+}
+impl Glyphs {
+    fn of(font: &MonoFont) -> Self {
+        // The glyph mapping doesn't change the glyphs, only which is drawn for a character
+        let cached = MONO_FONTS.iter().position(|mono| {
+            mono.character_size == font.character_size && mono.image == font.image
+        });
+        match cached {
+            Some(index) => Self::Cached(GLYPH_TABLES[index].measured(font)),
+            None => Self::Measured(measure(font)),
+        }
+    }
+
+    fn get(&self, index: usize) -> (u8, u8) {
+        match self {
+            Self::Cached(table) => table.get(index),
+            Self::Measured(glyphs) => glyphs.get(index).copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Glyph tables of the [`MONO_FONTS`], in the same order
+static GLYPH_TABLES: [GlyphTable; MONO_FONTS.len()] = [GlyphTable::EMPTY; MONO_FONTS.len()];
+
+/// Glyphs of a font, measured the first time the font is used.  Measuring
+/// always stores the same values, so a second measurement racing the first
+/// does no harm and no lock is needed.
+#[derive(Debug)]
+struct GlyphTable {
+    measured: AtomicBool,
+    /// First ink column in the high byte, ink width in the low byte
+    glyphs: [AtomicU16; MAX_GLYPHS],
+}
+impl GlyphTable {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const GLYPH: AtomicU16 = AtomicU16::new(0);
+        Self {
+            measured: AtomicBool::new(false),
+            glyphs: [GLYPH; MAX_GLYPHS],
+        }
+    };
+
+    /// The table, once it holds the glyphs of `font`
+    fn measured(&self, font: &MonoFont) -> &Self {
+        if !self.measured.load(Ordering::Acquire) {
+            for (glyph, (left, width)) in self.glyphs.iter().zip(measure(font)) {
+                glyph.store(u16::from_be_bytes([left, width]), Ordering::Relaxed);
+            }
+            self.measured.store(true, Ordering::Release);
+        }
+        self
+    }
+
+    fn get(&self, index: usize) -> (u8, u8) {
+        self.glyphs.get(index).map_or((0, 0), |glyph| {
+            let [left, width] = glyph.load(Ordering::Relaxed).to_be_bytes();
+            (left, width)
+        })
+    }
+}
+
+/// First ink column and ink width of every glyph of the font
+fn measure(font: &MonoFont) -> [(u8, u8); MAX_GLYPHS] {
+    let mut glyphs = [(0, 0); MAX_GLYPHS];
+    for (index, glyph) in glyphs.iter_mut().enumerate() {
+        let mut ink = InkColumns::new(font.character_size);
+        let area = glyph_area(font, index);
+        if area.size != Size::zero() {
+            // Drawing into InkColumns can't fail
+            let _ = Image::new(&font.image.sub_image(&area), Point::zero()).draw(&mut ink);
+        }
+        *glyph = ink.columns();
+    }
+    glyphs
+}
+
+/// Area of the font image holding the glyph with the given index
+fn glyph_area(font: &MonoFont, index: usize) -> Rectangle {
+    let size = font.character_size;
+    let image = font.image.bounding_box().size;
+    let per_row = image.width / size.width.max(1);
+    let (row, column) = (index as u32 / per_row.max(1), index as u32 % per_row.max(1));
+    if per_row == 0 || (row + 1) * size.height > image.height {
+        return Rectangle::zero();
+    }
+    Rectangle::new(
+        Point::new((column * size.width) as i32, (row * size.height) as i32),
+        size,
+    )
+}
+
+/// Records which columns of a glyph contain ink
+struct InkColumns {
+    size: Size,
+    first: Option<i32>,
+    last: i32,
+}
+impl InkColumns {
+    fn new(size: Size) -> Self {
+        Self {
+            size,
+            first: None,
+            last: 0,
+        }
+    }
+
+    /// First ink column and the width of the ink
+    fn columns(&self) -> (u8, u8) {
+        match self.first {
+            Some(first) => (first as u8, (self.last - first + 1) as u8),
+            None => (0, 0),
+        }
+    }
+}
+impl Dimensions for InkColumns {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+}
+impl DrawTarget for InkColumns {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if color.is_on() {
+                self.first = Some(self.first.map_or(point.x, |first| first.min(point.x)));
+                self.last = self.last.max(point.x);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws the set pixels of a glyph in the text colour
+struct Foreground<'a, D> {
+    target: &'a mut D,
+    color: BinaryColor,
+}
+impl<D: DrawTarget<Color = BinaryColor>> Dimensions for Foreground<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+impl<D: DrawTarget<Color = BinaryColor>> DrawTarget for Foreground<'_, D> {
+    type Color = BinaryColor;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let color = self.color;
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .filter(|Pixel(_, c)| c.is_on())
+                .map(|Pixel(point, _)| Pixel(point, color)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mono_font::iso_8859_1::{FONT_10X20, FONT_6X13};
+
+    #[test]
+    fn test_sanitize() {
+        let sanitize = |text| sanitize(text).collect::<heapless::String<64>>();
+        assert_eq!(sanitize("Hello\nWorld"), "Hello\nWorld");
+        assert_eq!(sanitize("José Müller ©"), "José Müller ©");
+        assert_eq!(sanitize("\u{201c}Hi\u{201d} \u{2764}\u{fe0f}"), "\"Hi\" <3");
+        assert_eq!(sanitize("日本 \u{1f642}"), " :)");
+        assert_eq!(sanitize("tab\there"), "tabhere");
+        assert_eq!(sanitize("done \u{2705}"), "done v");
+    }
+
+    #[test]
+    fn test_proportional_width() {
+        let style = ProportionalTextStyle::new(&FONT_10X20, BinaryColor::Off);
+        // narrow glyphs take less room than wide ones
+        assert!(style.width("!") < style.width("m"));
+        assert!(style.width("m") <= 10);
+        assert!(style.width("l.!") < 3 * 10);
+        assert_eq!(style.width(" "), 5);
+        assert_eq!(style.width(""), 0);
+        assert_eq!(
+            style.width("ab"),
+            style.width("a") + style.width("b") + LETTER_SPACING
+        );
+        // accented letters have glyphs
+        assert!(style.width("é") > 0);
+    }
+
+    #[test]
+    fn test_glyphs_are_cached() {
+        let style = ProportionalTextStyle::new(&FONT_10X20, BinaryColor::Off);
+        assert!(matches!(style.glyphs, Glyphs::Cached(_)));
+        let measured = measure(&FONT_10X20);
+        assert!((0..MAX_GLYPHS).all(|index| style.glyphs.get(index) == measured[index]));
+        // a font that isn't one of the fitted fonts is measured for the style
+        let style = ProportionalTextStyle::new(&FONT_6X13, BinaryColor::Off);
+        assert!(matches!(style.glyphs, Glyphs::Measured(_)));
+    }
+}
@@ -0,0 +1,374 @@
+//! In-memory display for rendering badges without hardware or a browser.
+//!
+//! [`Framebuffer`] is a 1bpp [`DrawTarget`] the size of the badge.  What is
+//! drawn into it can be exported as PNG or PBM so servers, command line tools
+//! and tests see exactly what the badge would show.
+//!
+//! As on the badge, `On` is white and `Off` is black.
+
+use std::vec::Vec;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    image::GetPixel,
+    pixelcolor::BinaryColor,
+    Pixel,
+};
+
+use crate::panel::Panel;
+
+/// Size of the Badger 2040W display
+pub const BADGE_SIZE: Size = Panel::BADGER_2040.size;
+
+/// File header, info header and two colour palette of a BMP
+const BMP_HEADER_LEN: u32 = 14 + 40 + 8;
+
+/// A 1bpp image, rows packed 8 pixels to a byte, most significant bit first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    size: Size,
+    bits: Vec<u8>,
+}
+impl Framebuffer {
+    /// A white framebuffer of the given size
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            bits: std::vec![0xff; Self::row_len(size.width) * size.height as usize],
+        }
+    }
+
+    /// A white framebuffer the size of the badge display
+    pub fn badge() -> Self {
+        Self::for_panel(Panel::BADGER_2040)
+    }
+
+    /// A white framebuffer the size of the panel
+    pub fn for_panel(panel: Panel) -> Self {
+        Self::new(panel.size)
+    }
+
+    fn row_len(width: u32) -> usize {
+        width.div_ceil(8) as usize
+    }
+
+    fn index(&self, point: Point) -> Option<(usize, u8)> {
+        let (x, y) = (u32::try_from(point.x).ok()?, u32::try_from(point.y).ok()?);
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+        let byte = y as usize * Self::row_len(self.size.width) + x as usize / 8;
+        Some((byte, 0x80 >> (x % 8)))
+    }
+
+    /// Colour of the pixel, `None` outside the image
+    pub fn pixel(&self, point: Point) -> Option<BinaryColor> {
+        self.index(point)
+            .map(|(byte, mask)| (self.bits[byte] & mask != 0).into())
+    }
+
+    pub fn set_pixel(&mut self, point: Point, color: BinaryColor) {
+        if let Some((byte, mask)) = self.index(point) {
+            if color.is_on() {
+                self.bits[byte] |= mask;
+            } else {
+                self.bits[byte] &= !mask;
+            }
+        }
+    }
+
+    /// Every pixel in row order
+    pub fn pixels(&self) -> impl Iterator<Item = Pixel<BinaryColor>> + '_ {
+        let Size { width, height } = self.size;
+        (0..height as i32).flat_map(move |y| {
+            (0..width as i32).map(move |x| {
+                let point = Point::new(x, y);
+                Pixel(point, self.pixel(point).unwrap_or(BinaryColor::On))
+            })
+        })
+    }
+
+    /// Export as a binary (P4) PBM image
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut pbm = std::format!("P4\n{} {}\n", self.size.width, self.size.height).into_bytes();
+        // PBM uses 1 for black.  Padding bits at the end of a row are ignored.
+        pbm.extend(self.bits.iter().map(|byte| !byte));
+        pbm
+    }
+
+    /// Read a binary (P4) PBM image
+    pub fn from_pbm(data: &[u8]) -> Result<Self, &'static str> {
+        let mut fields = PbmFields { data, pos: 0 };
+        if fields.next() != Some(b"P4") {
+            return Err("not a binary pbm");
+        }
+        let mut number = || -> Result<u32, &'static str> {
+            fields
+                .next()
+                .and_then(|field| core::str::from_utf8(field).ok())
+                .and_then(|field| field.parse().ok())
+                .ok_or("invalid pbm size")
+        };
+        let size = Size::new(number()?, number()?);
+        // a single whitespace byte separates the header from the data
+        let start = fields.pos + 1;
+        let len = Self::row_len(size.width) * size.height as usize;
+        let bits = data
+            .get(start..start + len)
+            .ok_or("pbm data too short")?
+            .iter()
+            .map(|byte| !byte)
+            .collect();
+        Ok(Self { size, bits })
+    }
+
+    /// Export as a 1 bit greyscale PNG image
+    pub fn to_png(&self) -> Vec<u8> {
+        let row_len = Self::row_len(self.size.width);
+        // Each row is preceded by its filter type, 0 for none
+        let mut raw = Vec::with_capacity((row_len + 1) * self.size.height as usize);
+        for row in self.bits.chunks_exact(row_len.max(1)) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.size.width.to_be_bytes());
+        header.extend_from_slice(&self.size.height.to_be_bytes());
+        // bit depth 1, greyscale, deflate, standard filters, not interlaced
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+        let mut png = std::vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Length of the BMP [`to_bmp`](Self::to_bmp) exports for an image of the given size
+    pub fn bmp_len(size: Size) -> usize {
+        BMP_HEADER_LEN as usize + Self::bmp_row_len(size.width) * size.height as usize
+    }
+
+    /// BMP rows are padded to four bytes
+    fn bmp_row_len(width: u32) -> usize {
+        Self::row_len(width).div_ceil(4) * 4
+    }
+
+    /// Export as a 1bpp BMP image, the format [`Logo::from_bmp`](crate::Logo::from_bmp) reads
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let row_len = Self::row_len(self.size.width);
+        // BMP rows are stored bottom up
+        let padded_len = Self::bmp_row_len(self.size.width);
+        let data_len = (padded_len * self.size.height as usize) as u32;
+
+        let mut bmp = Vec::with_capacity((BMP_HEADER_LEN + data_len) as usize);
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(BMP_HEADER_LEN + data_len).to_le_bytes());
+        bmp.extend_from_slice(&[0; 4]);
+        bmp.extend_from_slice(&BMP_HEADER_LEN.to_le_bytes());
+        // BITMAPINFOHEADER
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&self.size.width.to_le_bytes());
+        bmp.extend_from_slice(&self.size.height.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&data_len.to_le_bytes());
+        bmp.extend_from_slice(&[0; 16]);
+        // palette: 0 is black, 1 is white
+        bmp.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0]);
+        for row in self.bits.chunks_exact(row_len.max(1)).rev() {
+            bmp.extend_from_slice(row);
+            bmp.resize(bmp.len() + padded_len - row_len, 0);
+        }
+        bmp
+    }
+
+    /// Number of pixels that differ from another image of the same size, or
+    /// `None` if the sizes differ
+    pub fn count_differences(&self, other: &Framebuffer) -> Option<usize> {
+        (self.size == other.size).then(|| {
+            self.pixels()
+                .zip(other.pixels())
+                .filter(|(Pixel(_, a), Pixel(_, b))| a != b)
+                .count()
+        })
+    }
+}
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+impl GetPixel for Framebuffer {
+    type Color = BinaryColor;
+
+    fn pixel(&self, point: Point) -> Option<Self::Color> {
+        Framebuffer::pixel(self, point)
+    }
+}
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            self.set_pixel(point, color);
+        }
+        Ok(())
+    }
+}
+
+/// Whitespace separated fields of a PBM header, skipping `#` comments
+struct PbmFields<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> PbmFields<'a> {
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.pos)? {
+                b'#' => {
+                    while !matches!(self.data.get(self.pos)?, b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+        let start = self.pos;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        Some(&self.data[start..self.pos])
+    }
+}
+
+/// Append a PNG chunk with its length and CRC
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap data in a zlib stream of uncompressed deflate blocks.
+/// Badge images are only a few kilobytes so compression isn't worth a dependency.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+    let mut out = std::vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::{
+        primitives::{Primitive, PrimitiveStyle, Rectangle},
+        Drawable,
+    };
+
+    fn sample() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(Size::new(13, 5));
+        Rectangle::new(Point::new(2, 1), Size::new(9, 3))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::Off, 1))
+            .draw(&mut framebuffer)
+            .unwrap();
+        framebuffer
+    }
+
+    #[test]
+    fn test_draw() {
+        let framebuffer = sample();
+        assert_eq!(framebuffer.pixel(Point::new(0, 0)), Some(BinaryColor::On));
+        assert_eq!(framebuffer.pixel(Point::new(2, 1)), Some(BinaryColor::Off));
+        assert_eq!(framebuffer.pixel(Point::new(5, 2)), Some(BinaryColor::On));
+        assert_eq!(framebuffer.pixel(Point::new(13, 0)), None);
+        assert_eq!(framebuffer.pixels().count(), 13 * 5);
+        assert_eq!(Framebuffer::badge().size(), BADGE_SIZE);
+    }
+
+    #[test]
+    fn test_pbm() {
+        let framebuffer = sample();
+        let pbm = framebuffer.to_pbm();
+        assert!(pbm.starts_with(b"P4\n13 5\n"));
+        assert_eq!(pbm.len(), 8 + 2 * 5);
+        assert_eq!(Framebuffer::from_pbm(&pbm), Ok(framebuffer.clone()));
+        assert_eq!(
+            Framebuffer::from_pbm(b"P4\n# comment\n13 5\n\0\0\0\0\0\0\0\0\0\0"),
+            Ok(Framebuffer::new(Size::new(13, 5)))
+        );
+        assert!(Framebuffer::from_pbm(b"P1\n1 1\n0").is_err());
+        assert!(Framebuffer::from_pbm(&pbm[..pbm.len() - 1]).is_err());
+        assert_eq!(framebuffer.count_differences(&framebuffer), Some(0));
+        assert_eq!(
+            framebuffer.count_differences(&Framebuffer::new(Size::new(13, 5))),
+            // the outline of a 9x3 rectangle
+            Some(9 + 9 + 1 + 1)
+        );
+    }
+
+    #[test]
+    fn test_bmp() {
+        let framebuffer = sample();
+        let bmp = framebuffer.to_bmp();
+        assert_eq!(bmp.len(), 62 + 4 * 5);
+        assert_eq!(Framebuffer::bmp_len(framebuffer.size()), bmp.len());
+        let logo = crate::Logo::from_bmp(&bmp).unwrap();
+        assert_eq!(logo.size(), framebuffer.size());
+        let mut drawn = Framebuffer::new(Size::new(13, 5));
+        logo.draw(&mut drawn, Point::zero()).unwrap();
+        assert_eq!(drawn, framebuffer);
+    }
+
+    #[test]
+    fn test_png() {
+        let png = sample().to_png();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x0d\0\0\0\x05\x01\0"));
+        assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+}
@@ -0,0 +1,788 @@
+//! Describes where text and images go on the badge.
+//!
+//! A [`BadgeLayout`] is a list of text regions and an optional logo position.
+//! The built-in [`Template`]s are shared by the firmware and the web preview so
+//! both render exactly the same thing.
+
+use embedded_graphics::geometry::OriginDimensions as _;
+use embedded_graphics::mono_font::iso_8859_1::{FONT_6X10, FONT_9X15, FONT_9X15_BOLD};
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::text::renderer::{CharacterStyle, TextRenderer};
+use embedded_graphics::Drawable;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+};
+use embedded_text::alignment::{HorizontalAlignment, VerticalAlignment};
+use embedded_text::style::{TextBoxStyle, TextBoxStyleBuilder};
+use embedded_text::TextBox;
+
+use crate::animate::{draw_frame, TextMode};
+use crate::fit::{fit_font_with_spacing, FontSize, MONO_FONTS};
+use crate::font::{ProportionalTextStyle, Spacing};
+use crate::logo::Logo;
+use crate::qr::can_encode;
+use crate::rotate::{Rotated, Rotation};
+use crate::target::Scaled;
+use crate::theme::Theme;
+
+/// Maximum number of text regions in a layout
+pub const MAX_REGIONS: usize = 4;
+
+/// Maximum number of lines reported for a region by [`TextRegion::analyze`], more
+/// than the smallest font fits on the display
+pub const MAX_LINES: usize = 64;
+
+/// How the font of a text region is chosen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextSize {
+    /// Always use this font
+    Fixed(FontSize),
+    /// Use the largest font that fits the text into the region
+    Fit(Spacing),
+}
+
+/// An area of the badge that a single piece of text is drawn into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextRegion {
+    /// Area of the display the text is confined to
+    pub bounds: Rectangle,
+    pub size: TextSize,
+    pub alignment: HorizontalAlignment,
+    pub vertical_alignment: VerticalAlignment,
+}
+impl TextRegion {
+    pub fn new(bounds: Rectangle, font: &'static MonoFont<'static>) -> Self {
+        Self {
+            bounds,
+            size: TextSize::Fixed(FontSize::new(font, 1)),
+            alignment: HorizontalAlignment::Left,
+            vertical_alignment: VerticalAlignment::Middle,
+        }
+    }
+
+    /// A region that draws its text as large as will fit
+    pub fn fit(bounds: Rectangle) -> Self {
+        Self {
+            size: TextSize::Fit(Spacing::Mono),
+            ..Self::new(bounds, MONO_FONTS[0])
+        }
+    }
+
+    /// Draw the text with the given spacing, whether the font is fixed or fitted
+    pub fn with_spacing(mut self, spacing: Spacing) -> Self {
+        self.size = match self.size {
+            TextSize::Fixed(size) => TextSize::Fixed(size.with_spacing(spacing)),
+            TextSize::Fit(_) => TextSize::Fit(spacing),
+        };
+        self
+    }
+
+    /// The font size used to draw the text.  If nothing fits, the smallest font is
+    /// used and the text is cut off.
+    pub fn font_size(&self, text: &str) -> FontSize {
+        match self.size {
+            TextSize::Fixed(size) => size,
+            TextSize::Fit(spacing) => fit_font_with_spacing(text, self.bounds.size, spacing)
+                .unwrap_or(FontSize::new(MONO_FONTS[0], 1).with_spacing(spacing)),
+        }
+    }
+
+    pub fn with_alignment(
+        mut self,
+        alignment: HorizontalAlignment,
+        vertical_alignment: VerticalAlignment,
+    ) -> Self {
+        self.alignment = alignment;
+        self.vertical_alignment = vertical_alignment;
+        self
+    }
+
+    /// Lay the text out the way [`TextRegion::draw`] would without drawing it
+    pub fn analyze<'t>(&self, text: &'t str) -> RegionFit<'t> {
+        let font = self.font_size(text);
+        let rows = (font.grid(self.bounds.size).height as usize).min(MAX_LINES);
+        let mut lines = heapless::Vec::new();
+        let mut remainder = "";
+        font.wrap(text, self.bounds.size.width, |range| {
+            if lines.len() < rows {
+                // there is room for every row
+                let _ = lines.push(&text[range]);
+            } else if remainder.is_empty() {
+                remainder = &text[range.start..];
+            }
+        });
+        RegionFit {
+            font,
+            lines,
+            remainder,
+        }
+    }
+
+    /// Draw the text into this region, returning the font size that was used
+    pub fn draw(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+        text: &str,
+    ) -> Result<FontSize, &'static str> {
+        let size = self.font_size(text);
+        let textbox_style = self.textbox_style();
+
+        // Lay the text out at 1:1 and magnify it onto the display
+        let mut scaled = Scaled::new(display, self.bounds, size.scale);
+        // Note we're setting the Text color to `Off`. The driver is set up to treat Off as Black so that BMPs work as expected.
+        match size.spacing {
+            Spacing::Mono => draw_text_box(
+                &mut scaled,
+                text,
+                MonoTextStyle::new(size.font, BinaryColor::Off),
+                textbox_style,
+            ),
+            Spacing::Proportional => draw_text_box(
+                &mut scaled,
+                text,
+                ProportionalTextStyle::new(size.font, BinaryColor::Off),
+                textbox_style,
+            ),
+        }?;
+        Ok(size)
+    }
+
+    fn textbox_style(&self) -> TextBoxStyle {
+        TextBoxStyleBuilder::new()
+            .alignment(self.alignment)
+            .vertical_alignment(self.vertical_alignment)
+            .paragraph_spacing(0)
+            .build()
+    }
+}
+
+/// How a piece of text is laid out in a [`TextRegion`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionFit<'t> {
+    /// The font size the text is drawn with
+    pub font: FontSize,
+    /// The lines shown, after word wrapping
+    pub lines: heapless::Vec<&'t str, MAX_LINES>,
+    /// Text that doesn't fit and is cut off, empty if everything is shown
+    pub remainder: &'t str,
+}
+
+/// How text is laid out in a [`BadgeLayout`], see [`BadgeLayout::analyze`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextFit<'t> {
+    /// The text in the header bar, if the theme has one.  It is drawn in a fixed
+    /// font and cut off at the edges of the display.
+    pub header: Option<&'t str>,
+    /// The text encoded as the QR code, if the layout has one
+    pub qr: Option<&'t str>,
+    /// The layout of each text region, in order
+    pub regions: heapless::Vec<RegionFit<'t>, MAX_REGIONS>,
+    /// Text the layout has no region for, e.g. all of it for a picture
+    pub unshown: &'t str,
+}
+impl TextFit<'_> {
+    /// Whether any of the text is cut off
+    pub fn overflows(&self) -> bool {
+        self.overflow_chars() > 0
+    }
+
+    /// Number of characters that are not shown, not counting surrounding whitespace
+    pub fn overflow_chars(&self) -> usize {
+        self.regions
+            .iter()
+            .map(|region| region.remainder)
+            .chain([self.unshown])
+            .map(|text| text.trim().chars().count())
+            .sum()
+    }
+}
+
+/// Draw the text in a text box filling the display
+fn draw_text_box<S>(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    text: &str,
+    character_style: S,
+    textbox_style: TextBoxStyle,
+) -> Result<(), &'static str>
+where
+    S: TextRenderer<Color = BinaryColor> + CharacterStyle<Color = BinaryColor>,
+{
+    let bounds = display.bounding_box();
+    TextBox::with_textbox_style(text, bounds, character_style, textbox_style)
+        .draw(display)
+        .map_err(|_| "draw text box")?;
+    Ok(())
+}
+
+/// Description of everything drawn on the badge
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadgeLayout<'a> {
+    /// The logo and its top left corner, if the layout shows one
+    pub logo: Option<(Point, Logo<'a>)>,
+    /// Area for a QR code of the first line of the text, if the layout shows one
+    pub qr: Option<Rectangle>,
+    /// Text regions, filled in order with the lines of the text.
+    /// The last region receives all the remaining lines.
+    pub regions: heapless::Vec<TextRegion, MAX_REGIONS>,
+    /// Colours and decoration.  Everything else in the layout should be inside
+    /// the theme's [`Theme::content_area`].
+    pub theme: Theme,
+}
+impl<'a> BadgeLayout<'a> {
+    pub fn new() -> Self {
+        Self {
+            logo: None,
+            qr: None,
+            regions: heapless::Vec::new(),
+            theme: Theme::Normal,
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Move everything in the layout by `offset`
+    pub fn translated(mut self, offset: Point) -> Self {
+        if let Some((position, _)) = &mut self.logo {
+            *position += offset;
+        }
+        if let Some(qr) = &mut self.qr {
+            qr.top_left += offset;
+        }
+        for region in &mut self.regions {
+            region.bounds.top_left += offset;
+        }
+        self
+    }
+
+    pub fn with_logo(mut self, position: Point, logo: Logo<'a>) -> Self {
+        self.logo = Some((position, logo));
+        self
+    }
+
+    pub fn with_qr(mut self, bounds: Rectangle) -> Self {
+        self.qr = Some(bounds);
+        self
+    }
+
+    /// Add a region to the layout, panics if there are more than [`MAX_REGIONS`].
+    pub fn with_region(mut self, region: TextRegion) -> Self {
+        self.regions
+            .push(region)
+            .expect("layout has too many text regions");
+        self
+    }
+
+    /// Split off the first line of the text for the header bar, if the theme has one.
+    /// Returns the header text and the rest of the text.
+    pub fn split_header<'t>(&self, text: &'t str) -> (Option<&'t str>, &'t str) {
+        if self.theme.has_header() {
+            let (header, rest) = text.split_once('\n').unwrap_or((text, ""));
+            (Some(header), rest)
+        } else {
+            (None, text)
+        }
+    }
+
+    /// Split off the first line after the header to encode as the QR code, if the
+    /// layout has one.  Returns the QR code text and the text left for the regions.
+    pub fn split_qr<'t>(&self, text: &'t str) -> (Option<&'t str>, &'t str) {
+        let (_, text) = self.split_header(text);
+        match self.qr {
+            Some(_) => {
+                let (qr, rest) = text.split_once('\n').unwrap_or((text, ""));
+                (Some(qr), rest)
+            }
+            None => (None, text),
+        }
+    }
+
+    /// Lay the text out the way [`draw_layout`] would without drawing it
+    pub fn analyze<'t>(&self, text: &'t str) -> TextFit<'t> {
+        let (header, _) = self.split_header(text);
+        let (qr, rest) = self.split_qr(text);
+        TextFit {
+            header,
+            qr,
+            regions: self
+                .split_text(text)
+                .map(|(region, text)| region.analyze(text))
+                .collect(),
+            unshown: if self.regions.is_empty() { rest } else { "" },
+        }
+    }
+
+    /// Split the text into the pieces that go into each region
+    pub fn split_text<'t>(&self, text: &'t str) -> impl Iterator<Item = (&TextRegion, &'t str)> {
+        let (_, text) = self.split_qr(text);
+        let mut lines = text.splitn(self.regions.len(), '\n');
+        self.regions
+            .iter()
+            .map(move |region| (region, lines.next().unwrap_or("")))
+    }
+}
+impl Default for BadgeLayout<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Built-in layouts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Template {
+    /// Ferris on the left and a single block of text on the right
+    #[default]
+    Ferris = 0,
+    /// One line each for name, title, company and tagline
+    NameTag = 1,
+    /// A single centred block of text filling the display
+    Headline = 2,
+    /// A QR code of the first line on the left and the rest of the text on the right
+    QrCode = 3,
+    /// Just the logo, centred.  Used for pictures covering the whole display.
+    Picture = 4,
+}
+impl Template {
+    pub const ALL: [Template; 5] = [
+        Template::Ferris,
+        Template::NameTag,
+        Template::Headline,
+        Template::QrCode,
+        Template::Picture,
+    ];
+
+    /// Identifier used to select the template in a badge update
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Human readable name of the template
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::Ferris => "Ferris",
+            Template::NameTag => "Name / title / company",
+            Template::Headline => "Big headline",
+            Template::QrCode => "QR code (first line) and text",
+            Template::Picture => "Picture (no text)",
+        }
+    }
+
+    /// Build the layout with a theme, fitting the template into the area the theme
+    /// leaves on a display of the given size
+    pub fn themed_layout<'a>(self, size: Size, logo: Logo<'a>, theme: Theme) -> BadgeLayout<'a> {
+        let area = theme.content_area(size);
+        self.layout(area.size, logo)
+            .translated(area.top_left)
+            .with_theme(theme)
+    }
+
+    /// Build the layout with a theme for showing `text`.  When the line for the
+    /// QR code is too long to encode, the text is shown on its own like
+    /// [`Template::Headline`] does.
+    pub fn text_layout<'a>(
+        self,
+        size: Size,
+        logo: Logo<'a>,
+        theme: Theme,
+        text: &str,
+    ) -> BadgeLayout<'a> {
+        let layout = self.themed_layout(size, logo, theme);
+        match layout.split_qr(text) {
+            (Some(qr), _) if !can_encode(qr) => Template::Headline.themed_layout(size, logo, theme),
+            _ => layout,
+        }
+    }
+
+    /// Build the layout for a display of the given size.
+    /// Templates with a logo make room for the size of `logo`.  Templates that
+    /// put things side by side stack them instead on a portrait display.
+    pub fn layout<'a>(self, size: Size, logo: Logo<'a>) -> BadgeLayout<'a> {
+        let (width, height) = (size.width, size.height);
+        match self {
+            Template::Ferris if height > width => {
+                // Portrait: the logo centred along the top and the text below it
+                let logo_size = logo.size();
+                let top_edge = logo_size.height;
+                BadgeLayout::new()
+                    .with_logo(
+                        Point::new((width as i32 - logo_size.width as i32) / 2, 0),
+                        logo,
+                    )
+                    .with_region(
+                        TextRegion::fit(Rectangle::new(
+                            Point::new(0, top_edge as i32),
+                            Size::new(width, height.saturating_sub(top_edge)),
+                        ))
+                        .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle),
+                    )
+            }
+            Template::Ferris => {
+                const V_PADDING: u32 = 10;
+                let left_edge = logo.size().width;
+                BadgeLayout::new()
+                    .with_logo(Point::zero(), logo)
+                    .with_region(TextRegion::fit(Rectangle::new(
+                        Point::new(left_edge as i32, V_PADDING as i32),
+                        Size::new(
+                            width.saturating_sub(left_edge),
+                            height.saturating_sub(V_PADDING),
+                        ),
+                    )))
+            }
+            Template::NameTag => {
+                const MARGIN: u32 = 4;
+                let row = |top: u32, bottom: u32, size| TextRegion {
+                    size,
+                    ..TextRegion::fit(Rectangle::new(
+                        Point::new(MARGIN as i32, (height * top / 16) as i32),
+                        Size::new(
+                            width.saturating_sub(2 * MARGIN),
+                            height * (bottom - top) / 16,
+                        ),
+                    ))
+                    .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle)
+                };
+                let fixed = |font| TextSize::Fixed(FontSize::new(font, 1));
+                BadgeLayout::new()
+                    .with_region(row(0, 6, TextSize::Fit(Spacing::Proportional)))
+                    .with_region(row(6, 9, fixed(&FONT_9X15)))
+                    .with_region(row(9, 12, fixed(&FONT_9X15_BOLD)))
+                    .with_region(row(12, 16, fixed(&FONT_6X10)))
+            }
+            Template::Headline => {
+                const MARGIN: u32 = 4;
+                BadgeLayout::new().with_region(
+                    TextRegion::fit(Rectangle::new(
+                        Point::new(MARGIN as i32, MARGIN as i32),
+                        Size::new(
+                            width.saturating_sub(2 * MARGIN),
+                            height.saturating_sub(2 * MARGIN),
+                        ),
+                    ))
+                    .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle)
+                    .with_spacing(Spacing::Proportional),
+                )
+            }
+            Template::QrCode if height > width => {
+                const MARGIN: u32 = 4;
+                // Portrait: a square the full width of the display with the text below
+                let side = width;
+                BadgeLayout::new()
+                    .with_qr(Rectangle::new(Point::zero(), Size::new(side, side)))
+                    .with_region(
+                        TextRegion::fit(Rectangle::new(
+                            Point::new(MARGIN as i32, side as i32),
+                            Size::new(
+                                width.saturating_sub(2 * MARGIN),
+                                height.saturating_sub(side + MARGIN),
+                            ),
+                        ))
+                        .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle),
+                    )
+            }
+            Template::QrCode => {
+                const MARGIN: u32 = 4;
+                // A square the full height of the display
+                let side = height.min(width);
+                BadgeLayout::new()
+                    .with_qr(Rectangle::new(Point::zero(), Size::new(side, side)))
+                    .with_region(
+                        TextRegion::fit(Rectangle::new(
+                            Point::new(side as i32, MARGIN as i32),
+                            Size::new(
+                                width.saturating_sub(side + MARGIN),
+                                height.saturating_sub(2 * MARGIN),
+                            ),
+                        ))
+                        .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle),
+                    )
+            }
+            Template::Picture => {
+                let logo_size = logo.size();
+                let position = Point::new(
+                    (width as i32 - logo_size.width as i32) / 2,
+                    (height as i32 - logo_size.height as i32) / 2,
+                );
+                BadgeLayout::new().with_logo(position, logo)
+            }
+        }
+    }
+}
+impl TryFrom<u8> for Template {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Template, Self::Error> {
+        Template::ALL
+            .into_iter()
+            .find(|template| template.id() == value)
+            .ok_or("unknown template")
+    }
+}
+
+/// Render the text into the display using the given layout
+pub fn draw_layout(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    layout: &BadgeLayout,
+    text: &str,
+) -> Result<(), &'static str> {
+    draw_frame(display, layout, text, TextMode::Static, 0)
+}
+
+/// Render the text with a built-in template sized to the display.
+/// Templates with a logo show Ferris if no logo is given.
+pub fn draw_template(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    template: Template,
+    text: &str,
+    logo: Option<Logo<'_>>,
+) -> Result<(), &'static str> {
+    let size = display.bounding_box().size;
+    let layout = template.text_layout(size, logo.unwrap_or_default(), Theme::Normal, text);
+    draw_layout(display, &layout, text)
+}
+
+/// Render the text with a built-in template on a display mounted with the given
+/// rotation.  The template is laid out for the rotated size.
+pub fn draw_rotated(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    rotation: Rotation,
+    template: Template,
+    text: &str,
+    logo: Option<Logo<'_>>,
+) -> Result<(), &'static str> {
+    draw_template(&mut Rotated::new(display, rotation), template, text, logo)
+}
+
+/// Lay the text out the way [`draw_template`] would on a display of the given size,
+/// without drawing it
+pub fn analyze_template<'t>(
+    size: Size,
+    template: Template,
+    theme: Theme,
+    text: &'t str,
+    logo: Option<Logo<'_>>,
+) -> TextFit<'t> {
+    template
+        .text_layout(size, logo.unwrap_or_default(), theme, text)
+        .analyze(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::text::{renderer::TextMetrics, Baseline};
+
+    #[test]
+    fn test_analyze_region() {
+        let region = TextRegion::new(Rectangle::new(Point::zero(), Size::new(30, 10)), &FONT_6X10);
+        let fit = region.analyze("aaaa bbbb cccc");
+        assert_eq!(fit.lines, ["aaaa"]);
+        assert_eq!(fit.remainder, "bbbb cccc");
+
+        let fit = region.analyze("ab cd");
+        assert_eq!(fit.lines, ["ab cd"]);
+        assert_eq!(fit.remainder, "");
+    }
+
+    #[test]
+    fn test_analyze_template() {
+        let size = Size::new(296, 128);
+        let fit = analyze_template(size, Template::Headline, Theme::Normal, "Hello World", None);
+        assert!(!fit.overflows());
+        // the largest font that fits puts each word on its own line
+        assert_eq!(fit.regions[0].lines, ["Hello", "World"]);
+
+        // far more than the smallest font can fit
+        let long = "word ".repeat(500);
+        let fit = analyze_template(size, Template::Ferris, Theme::Normal, &long, None);
+        assert!(fit.overflows());
+        assert!(fit.overflow_chars() < long.len());
+
+        // the first line is the QR code, the rest goes to the text region
+        let fit = analyze_template(
+            size,
+            Template::QrCode,
+            Theme::Normal,
+            "https://example.com\nScan me",
+            None,
+        );
+        assert_eq!(fit.qr, Some("https://example.com"));
+        assert_eq!(fit.regions[0].lines, ["Scan", "me"]);
+        // a line too long for a QR code is shown as text instead
+        let long = "a".repeat(181) + "\nScan me";
+        let fit = analyze_template(size, Template::QrCode, Theme::Normal, &long, None);
+        assert_eq!(fit.qr, None);
+        assert_eq!(fit.regions.len(), 1);
+        let mut display = crate::Framebuffer::new(size);
+        assert_eq!(
+            draw_template(&mut display, Template::QrCode, &long, None),
+            Ok(())
+        );
+
+        // pictures show no text at all
+        let fit = analyze_template(size, Template::Picture, Theme::Normal, "Hidden", None);
+        assert_eq!(fit.overflow_chars(), 6);
+    }
+
+    /// Records the lines embedded-text draws, each word at the row it lands on
+    #[derive(Clone)]
+    struct Recorder<'r, S> {
+        style: S,
+        top: i32,
+        rows: &'r core::cell::RefCell<std::vec::Vec<std::string::String>>,
+    }
+    impl<S: TextRenderer<Color = BinaryColor>> Recorder<'_, S> {
+        fn push(&self, position: Point, baseline: Baseline, text: &str) {
+            let top = self
+                .style
+                .measure_string("", position, baseline)
+                .bounding_box
+                .top_left;
+            let row = ((top.y - self.top) / self.style.line_height() as i32) as usize;
+            let mut rows = self.rows.borrow_mut();
+            if rows.len() <= row {
+                rows.resize(row + 1, std::string::String::new());
+            }
+            rows[row].push_str(text);
+        }
+    }
+    impl<S: TextRenderer<Color = BinaryColor>> TextRenderer for Recorder<'_, S> {
+        type Color = BinaryColor;
+
+        fn draw_string<D: DrawTarget<Color = BinaryColor>>(
+            &self,
+            text: &str,
+            position: Point,
+            baseline: Baseline,
+            target: &mut D,
+        ) -> Result<Point, D::Error> {
+            self.push(position, baseline, text);
+            self.style.draw_string(text, position, baseline, target)
+        }
+
+        fn draw_whitespace<D: DrawTarget<Color = BinaryColor>>(
+            &self,
+            width: u32,
+            position: Point,
+            baseline: Baseline,
+            target: &mut D,
+        ) -> Result<Point, D::Error> {
+            let space = self
+                .style
+                .measure_string(" ", Point::zero(), baseline)
+                .next_position
+                .x as u32;
+            let spaces = " ".repeat((width / space.max(1)) as usize);
+            self.push(position, baseline, &spaces);
+            self.style
+                .draw_whitespace(width, position, baseline, target)
+        }
+
+        fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+            self.style.measure_string(text, position, baseline)
+        }
+
+        fn line_height(&self) -> u32 {
+            self.style.line_height()
+        }
+    }
+    impl<S: CharacterStyle<Color = BinaryColor>> CharacterStyle for Recorder<'_, S> {
+        type Color = BinaryColor;
+
+        fn set_text_color(&mut self, text_color: Option<BinaryColor>) {
+            self.style.set_text_color(text_color);
+        }
+    }
+
+    /// The rows embedded-text fills in the region, with the style the region draws with
+    fn text_box_rows<S>(
+        region: &TextRegion,
+        text: &str,
+        style: S,
+    ) -> std::vec::Vec<std::string::String>
+    where
+        S: TextRenderer<Color = BinaryColor> + CharacterStyle<Color = BinaryColor>,
+    {
+        let rows = core::cell::RefCell::new(std::vec::Vec::new());
+        let recorder = Recorder {
+            style,
+            top: region.bounds.top_left.y,
+            rows: &rows,
+        };
+        let mut framebuffer = crate::Framebuffer::new(region.bounds.size);
+        TextBox::with_textbox_style(text, region.bounds, recorder, region.textbox_style())
+            .draw(&mut framebuffer)
+            .unwrap();
+        rows.into_inner()
+    }
+
+    #[test]
+    fn test_analyze_matches_text_box() {
+        let texts = [
+            "The quick brown fox jumps over the lazy dog",
+            "illicit little lilies and wide WWW MMM",
+            "abcdefghijklmnopqrstuvwxyz0123456789",
+            "a verylongwordthatneedssplitting here",
+            "Hello  World   again",
+            "ends with spaces   \nand more ",
+            "first\n\nthird line\nfourth",
+            "José Müller, 3½ °C",
+        ];
+        for font in [&FONT_6X10, &FONT_9X15] {
+            for width in (30..=140).step_by(7) {
+                let bounds = Rectangle::new(
+                    Point::zero(),
+                    Size::new(width, 8 * font.character_size.height),
+                );
+                let region = TextRegion::new(bounds, font)
+                    .with_alignment(HorizontalAlignment::Left, VerticalAlignment::Top);
+                for spacing in [Spacing::Mono, Spacing::Proportional] {
+                    let region = region.with_spacing(spacing);
+                    for text in texts {
+                        let drawn = match spacing {
+                            Spacing::Mono => text_box_rows(
+                                &region,
+                                text,
+                                MonoTextStyle::new(font, BinaryColor::Off),
+                            ),
+                            Spacing::Proportional => text_box_rows(
+                                &region,
+                                text,
+                                ProportionalTextStyle::new(font, BinaryColor::Off),
+                            ),
+                        };
+                        let fit = region.analyze(text);
+                        let analyzed = fit
+                            .lines
+                            .iter()
+                            .map(|line| line.trim_end())
+                            .collect::<std::vec::Vec<_>>();
+                        let drawn = drawn
+                            .iter()
+                            .map(|line| line.trim_end())
+                            .collect::<std::vec::Vec<_>>();
+                        // embedded-text leaves empty rows at the end undrawn
+                        let shown = drawn.len();
+                        assert_eq!(
+                            &analyzed[..shown.min(analyzed.len())],
+                            &drawn[..],
+                            "{spacing:?} {width}px {text:?}"
+                        );
+                        assert!(
+                            analyzed[shown.min(analyzed.len())..]
+                                .iter()
+                                .all(|line| line.is_empty()),
+                            "{spacing:?} {width}px {text:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
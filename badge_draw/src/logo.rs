@@ -0,0 +1,100 @@
+//! The logo image drawn by layouts that have one.
+//!
+//! The logo is supplied at runtime as a 1bpp BMP or raw 1bpp bitmap, falling
+//! back to Ferris when none is given.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    image::{Image, ImageRaw},
+    pixelcolor::BinaryColor,
+    Drawable,
+};
+use tinybmp::Bmp;
+
+const FERRIS_IMG: &[u8] = include_bytes!("../../badge/ferris_1bpp.bmp");
+
+/// A 1bpp logo image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Logo<'a> {
+    Bmp(Bmp<'a, BinaryColor>),
+    /// Rows of pixels packed 8 to a byte, most significant bit first, each row padded
+    /// to a whole byte.  A set bit is `On` (white).
+    Raw(ImageRaw<'a, BinaryColor>),
+}
+impl<'a> Logo<'a> {
+    /// The default logo
+    pub fn ferris() -> Logo<'static> {
+        Logo::Bmp(Bmp::from_slice(FERRIS_IMG).expect("ferris is a valid bmp"))
+    }
+
+    /// Parse a 1bpp BMP file
+    pub fn from_bmp(data: &'a [u8]) -> Result<Self, &'static str> {
+        Bmp::from_slice(data)
+            .map(Logo::Bmp)
+            .map_err(|_| "invalid logo bmp")
+    }
+
+    /// Use raw 1bpp data `width` pixels wide, see [`Logo::Raw`]
+    pub fn from_raw(data: &'a [u8], width: u32) -> Result<Self, &'static str> {
+        let row_len = width.div_ceil(8) as usize;
+        if width == 0 || data.is_empty() || !data.chunks_exact(row_len).remainder().is_empty() {
+            return Err("raw logo data is not whole rows");
+        }
+        Ok(Logo::Raw(ImageRaw::new(data, width)))
+    }
+
+    /// Draw the logo with its top left corner at `position`
+    pub fn draw(
+        &self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+        position: Point,
+    ) -> Result<(), &'static str> {
+        match self {
+            Logo::Bmp(bmp) => Image::new(bmp, position).draw(display),
+            Logo::Raw(raw) => Image::new(raw, position).draw(display),
+        }
+        .map_err(|_| "draw logo")
+    }
+}
+impl OriginDimensions for Logo<'_> {
+    fn size(&self) -> Size {
+        match self {
+            Logo::Bmp(bmp) => bmp.size(),
+            Logo::Raw(raw) => raw.size(),
+        }
+    }
+}
+impl Default for Logo<'_> {
+    fn default() -> Self {
+        Logo::ferris()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ferris() {
+        assert_eq!(Logo::ferris().size(), Size::new(157, 128));
+        assert_eq!(Logo::default(), Logo::ferris());
+        assert!(Logo::from_bmp(b"not a bmp").is_err());
+    }
+
+    #[test]
+    fn test_raw() {
+        let logo = Logo::from_raw(&[0xff; 2 * 10], 12).unwrap();
+        assert_eq!(logo.size(), Size::new(12, 10));
+        assert!(Logo::from_raw(&[0xff; 3], 12).is_err());
+        assert!(Logo::from_raw(&[], 12).is_err());
+        assert!(Logo::from_raw(&[0xff; 2], 0).is_err());
+    }
+
+    #[test]
+    fn test_bmp() {
+        let logo = Logo::from_bmp(FERRIS_IMG).unwrap();
+        assert_eq!(logo.size(), Size::new(157, 128));
+        assert!(Logo::from_bmp(&FERRIS_IMG[..20]).is_err());
+    }
+}
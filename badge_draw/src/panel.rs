@@ -0,0 +1,144 @@
+//! Description of the e-ink panel a badge draws on.
+//!
+//! Layouts are sized from the drawing target, so the same code drives panels of
+//! any size.  A [`Panel`] records what else differs between boards, the colours
+//! the panel can show and whether it supports partial refresh, so the firmware
+//! can pick a refresh policy and the server can adapt content to each badge.
+
+use embedded_graphics::geometry::{OriginDimensions, Size};
+
+use crate::dirty::RefreshPolicy;
+use crate::rotate::Rotation;
+
+/// Colours a panel can show.  Layouts are drawn in black and white on all of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Colors {
+    #[default]
+    BlackWhite = 0,
+    BlackWhiteRed = 1,
+    BlackWhiteYellow = 2,
+}
+impl Colors {
+    pub const ALL: [Colors; 3] = [
+        Colors::BlackWhite,
+        Colors::BlackWhiteRed,
+        Colors::BlackWhiteYellow,
+    ];
+
+    /// Identifier used when the badge reports its panel
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Human readable name of the colours
+    pub fn name(self) -> &'static str {
+        match self {
+            Colors::BlackWhite => "Black and white",
+            Colors::BlackWhiteRed => "Black, white and red",
+            Colors::BlackWhiteYellow => "Black, white and yellow",
+        }
+    }
+}
+impl TryFrom<u8> for Colors {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Colors, Self::Error> {
+        Colors::ALL
+            .into_iter()
+            .find(|colors| colors.id() == value)
+            .ok_or("unknown panel colours")
+    }
+}
+
+/// An e-ink panel, in the orientation the controller scans it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panel {
+    /// Size in pixels
+    pub size: Size,
+    pub colors: Colors,
+    /// Whether the controller can refresh part of the panel
+    pub partial_refresh: bool,
+}
+impl Panel {
+    /// The 2.9" UC8151 panel on the Badger 2040 W
+    pub const BADGER_2040: Panel = Panel::new(Size::new(296, 128), Colors::BlackWhite, true);
+    /// The 2.13" Inky pHAT
+    pub const INKY_PHAT: Panel = Panel::new(Size::new(250, 122), Colors::BlackWhiteRed, false);
+    /// The 4.2" Inky wHAT
+    pub const INKY_WHAT: Panel = Panel::new(Size::new(400, 300), Colors::BlackWhiteRed, false);
+
+    pub const fn new(size: Size, colors: Colors, partial_refresh: bool) -> Self {
+        Self {
+            size,
+            colors,
+            partial_refresh,
+        }
+    }
+
+    /// Bytes in a 1bpp frame covering the panel, rows padded to whole bytes
+    pub const fn frame_len(&self) -> usize {
+        self.size.width.div_ceil(8) as usize * self.size.height as usize
+    }
+
+    /// Size of the drawing on the panel mounted with the given rotation
+    pub fn drawing_size(&self, rotation: Rotation) -> Size {
+        rotation.size(self.size)
+    }
+
+    /// Refresh policy suited to the panel.  Panels without partial refresh
+    /// always do a full one.
+    pub fn refresh_policy(&self) -> RefreshPolicy {
+        if self.partial_refresh {
+            RefreshPolicy::default()
+        } else {
+            RefreshPolicy::new(0, 0)
+        }
+    }
+}
+impl Default for Panel {
+    fn default() -> Self {
+        Self::BADGER_2040
+    }
+}
+impl OriginDimensions for Panel {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dirty::Refresh;
+    use embedded_graphics::{geometry::Point, primitives::Rectangle};
+
+    #[test]
+    fn test_colors_ids() {
+        for colors in Colors::ALL {
+            assert_eq!(Colors::try_from(colors.id()), Ok(colors));
+        }
+        assert!(Colors::try_from(3).is_err());
+    }
+
+    #[test]
+    fn test_panel() {
+        let panel = Panel::BADGER_2040;
+        assert_eq!(panel.frame_len(), 37 * 128);
+        assert_eq!(panel.drawing_size(Rotation::Deg90), Size::new(128, 296));
+
+        let dirty = Rectangle::new(Point::zero(), Size::new(8, 8));
+        let mut policy = panel.refresh_policy();
+        policy.force_full();
+        assert_eq!(
+            policy.refresh(Some(dirty), panel.size),
+            Refresh::Partial(dirty)
+        );
+        let mut policy = Panel::INKY_WHAT.refresh_policy();
+        policy.force_full();
+        assert_eq!(
+            policy.refresh(Some(dirty), Panel::INKY_WHAT.size),
+            Refresh::Full
+        );
+    }
+}
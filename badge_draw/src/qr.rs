@@ -0,0 +1,123 @@
+//! Rendering text or URLs as QR codes.
+//!
+//! Encoding uses `qrcodegen-no-heap` with buffers on the stack, so it works the
+//! same on the badge, the server and in the browser.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+};
+use qrcodegen_no_heap::{QrCode, QrCodeEcc, Version};
+
+/// Largest QR code version that is encoded.
+/// Version 9 is 53 modules wide, so with its quiet zone every module is still at
+/// least two pixels on the 128 pixel high badge.  Codes are drawn with medium
+/// error correction, where it holds up to 180 bytes.
+pub const MAX_VERSION: Version = Version::new(9);
+
+/// Width in modules of the blank border required around a QR code
+pub const QUIET_ZONE: u32 = 4;
+
+/// Size of the buffers needed to encode a QR code
+const BUFFER_LEN: usize = MAX_VERSION.buffer_len();
+
+/// Whether `text` fits in a QR code of at most [`MAX_VERSION`]
+pub fn can_encode(text: &str) -> bool {
+    let mut temp = [0u8; BUFFER_LEN];
+    let mut out = [0u8; BUFFER_LEN];
+    encode(text, &mut temp, &mut out).is_ok()
+}
+
+fn encode<'b>(text: &str, temp: &mut [u8], out: &'b mut [u8]) -> Result<QrCode<'b>, &'static str> {
+    QrCode::encode_text(
+        text,
+        temp,
+        out,
+        QrCodeEcc::Medium,
+        Version::MIN,
+        MAX_VERSION,
+        None,
+        true,
+    )
+    .map_err(|_| "qr code text too long")
+}
+
+/// Render `text` as a QR code centred in `bounds`.
+///
+/// Modules are drawn as square blocks of whole pixels, as large as will fit
+/// including the quiet zone.  Returns the area covered by the code and its quiet zone.
+pub fn draw_qr(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    text: &str,
+) -> Result<Rectangle, &'static str> {
+    let mut temp = [0u8; BUFFER_LEN];
+    let mut out = [0u8; BUFFER_LEN];
+    let qr = encode(text, &mut temp, &mut out)?;
+
+    let modules = qr.size() as u32;
+    let scale = module_scale(modules, bounds.size).ok_or("qr code does not fit")?;
+
+    // Centre the code in the bounds
+    let side = (modules + 2 * QUIET_ZONE) * scale;
+    let area = Rectangle::new(
+        bounds.top_left
+            + Point::new(
+                ((bounds.size.width - side) / 2) as i32,
+                ((bounds.size.height - side) / 2) as i32,
+            ),
+        Size::new(side, side),
+    );
+
+    // Note dark modules are `Off`, the driver treats Off as black.
+    display
+        .fill_solid(&area, BinaryColor::On)
+        .map_err(|_| "draw qr code")?;
+    let origin =
+        area.top_left + Point::new((QUIET_ZONE * scale) as i32, (QUIET_ZONE * scale) as i32);
+    for y in 0..qr.size() {
+        for x in 0..qr.size() {
+            if qr.get_module(x, y) {
+                let module = Rectangle::new(
+                    origin + Point::new(x, y) * scale as i32,
+                    Size::new(scale, scale),
+                );
+                display
+                    .fill_solid(&module, BinaryColor::Off)
+                    .map_err(|_| "draw qr code")?;
+            }
+        }
+    }
+    Ok(area)
+}
+
+/// Pixels per module for a code `modules` wide to fit into `size` with its quiet zone.
+/// Returns `None` if it does not fit even at one pixel per module.
+fn module_scale(modules: u32, size: Size) -> Option<u32> {
+    let scale = size.width.min(size.height) / (modules + 2 * QUIET_ZONE);
+    (scale > 0).then_some(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_scale() {
+        // version 1 is 21 modules, 29 with the quiet zone
+        assert_eq!(module_scale(21, Size::new(128, 128)), Some(4));
+        assert_eq!(module_scale(21, Size::new(296, 128)), Some(4));
+        assert_eq!(module_scale(21, Size::new(29, 100)), Some(1));
+        assert_eq!(module_scale(21, Size::new(28, 100)), None);
+        // the largest version still gets two pixels per module on the badge
+        assert_eq!(module_scale(53, Size::new(128, 128)), Some(2));
+    }
+
+    #[test]
+    fn test_can_encode() {
+        assert!(can_encode(&"a".repeat(180)));
+        assert!(!can_encode(&"a".repeat(181)));
+    }
+}
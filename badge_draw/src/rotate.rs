@@ -0,0 +1,211 @@
+//! Drawing onto a display that is mounted rotated.
+//!
+//! The panel is landscape, but a badge hanging from a lanyard is often worn
+//! portrait.  Drawing through [`Rotated`] gives layouts a target the size the
+//! wearer sees, so templates can reflow for portrait, and turns every pixel onto
+//! the physical panel.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// Clockwise rotation of the drawing on the physical display
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Rotation {
+    /// Landscape, as the panel is mounted
+    #[default]
+    Deg0 = 0,
+    /// Portrait, top of the drawing on the right of the panel
+    Deg90 = 1,
+    /// Landscape, upside down
+    Deg180 = 2,
+    /// Portrait, top of the drawing on the left of the panel
+    Deg270 = 3,
+}
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::Deg0,
+        Rotation::Deg90,
+        Rotation::Deg180,
+        Rotation::Deg270,
+    ];
+
+    /// Identifier used to select the rotation in a badge update
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Rotation in degrees clockwise
+    pub fn degrees(self) -> u32 {
+        u32::from(self.id()) * 90
+    }
+
+    /// Human readable name of the rotation
+    pub fn name(self) -> &'static str {
+        match self {
+            Rotation::Deg0 => "Landscape",
+            Rotation::Deg90 => "Portrait (90°)",
+            Rotation::Deg180 => "Landscape upside down",
+            Rotation::Deg270 => "Portrait (270°)",
+        }
+    }
+
+    /// Whether the long side of the display is vertical
+    pub fn is_portrait(self) -> bool {
+        matches!(self, Rotation::Deg90 | Rotation::Deg270)
+    }
+
+    /// Size of the drawing on a display of the given physical size
+    pub fn size(self, physical: Size) -> Size {
+        if self.is_portrait() {
+            Size::new(physical.height, physical.width)
+        } else {
+            physical
+        }
+    }
+
+    /// Where a point of the drawing ends up on a display of the given physical size
+    pub fn to_physical(self, point: Point, physical: Size) -> Point {
+        let (width, height) = (physical.width as i32, physical.height as i32);
+        match self {
+            Rotation::Deg0 => point,
+            Rotation::Deg90 => Point::new(width - 1 - point.y, point.x),
+            Rotation::Deg180 => Point::new(width - 1 - point.x, height - 1 - point.y),
+            Rotation::Deg270 => Point::new(point.y, height - 1 - point.x),
+        }
+    }
+
+    /// The area of the physical display covered by an area of the drawing
+    pub fn rectangle_to_physical(self, area: &Rectangle, physical: Size) -> Rectangle {
+        let Some(bottom_right) = area.bottom_right() else {
+            return Rectangle::new(self.to_physical(area.top_left, physical), Size::zero());
+        };
+        Rectangle::with_corners(
+            self.to_physical(area.top_left, physical),
+            self.to_physical(bottom_right, physical),
+        )
+    }
+}
+impl TryFrom<u8> for Rotation {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Rotation, Self::Error> {
+        Rotation::ALL
+            .into_iter()
+            .find(|rotation| rotation.id() == value)
+            .ok_or("unknown rotation")
+    }
+}
+
+/// Draws onto another target turned by a [`Rotation`].  The target must have its
+/// origin at zero, as displays do.
+pub struct Rotated<'a, D> {
+    target: &'a mut D,
+    rotation: Rotation,
+}
+impl<'a, D> Rotated<'a, D>
+where
+    D: Dimensions,
+{
+    pub fn new(target: &'a mut D, rotation: Rotation) -> Self {
+        Self { target, rotation }
+    }
+
+    fn physical_size(&self) -> Size {
+        self.target.bounding_box().size
+    }
+}
+impl<D> Dimensions for Rotated<'_, D>
+where
+    D: Dimensions,
+{
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.rotation.size(self.physical_size()))
+    }
+}
+impl<D> DrawTarget for Rotated<'_, D>
+where
+    D: DrawTarget,
+{
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (rotation, physical) = (self.rotation, self.physical_size());
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(rotation.to_physical(point, physical), color)),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let area = self
+            .rotation
+            .rectangle_to_physical(&area, self.physical_size());
+        self.target.fill_solid(&area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.clear(color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Framebuffer;
+    use embedded_graphics::{
+        pixelcolor::BinaryColor,
+        primitives::{Primitive, PrimitiveStyle},
+        Drawable,
+    };
+
+    #[test]
+    fn test_to_physical() {
+        let physical = Size::new(4, 2);
+        // the top left corner of the drawing
+        let corners = [
+            (Rotation::Deg0, Point::new(0, 0)),
+            (Rotation::Deg90, Point::new(3, 0)),
+            (Rotation::Deg180, Point::new(3, 1)),
+            (Rotation::Deg270, Point::new(0, 1)),
+        ];
+        for (rotation, expected) in corners {
+            assert_eq!(rotation.to_physical(Point::zero(), physical), expected);
+            assert_eq!(Rotation::try_from(rotation.id()), Ok(rotation));
+        }
+        assert_eq!(Rotation::Deg90.size(physical), Size::new(2, 4));
+        assert!(Rotation::try_from(4).is_err());
+    }
+
+    #[test]
+    fn test_rotated_target() {
+        let mut framebuffer = Framebuffer::new(Size::new(4, 2));
+        let mut rotated = Rotated::new(&mut framebuffer, Rotation::Deg90);
+        assert_eq!(rotated.bounding_box().size, Size::new(2, 4));
+        Pixel(Point::new(0, 0), BinaryColor::Off)
+            .draw(&mut rotated)
+            .unwrap();
+        Rectangle::new(Point::new(0, 2), Size::new(2, 2))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+            .draw(&mut rotated)
+            .unwrap();
+        // the top row of the drawing runs down the right hand column of the panel
+        // and the bottom half of the drawing is the left half of the panel
+        let black: std::vec::Vec<_> = framebuffer
+            .pixels()
+            .filter(|Pixel(_, color)| color.is_off())
+            .map(|Pixel(point, _)| (point.x, point.y))
+            .collect();
+        assert_eq!(black, [(0, 0), (1, 0), (3, 0), (0, 1), (1, 1)]);
+    }
+}
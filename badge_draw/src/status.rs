@@ -0,0 +1,244 @@
+//! Connection progress and diagnostics screen.
+//!
+//! The badge connects in a fixed sequence of [`Stage`]s.  [`draw_status`] shows a
+//! row of indicators, one per stage, the current progress message and, if
+//! something went wrong, an error line along the bottom.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{iso_8859_1::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    primitives::{Circle, Line, Primitive, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+use embedded_text::alignment::{HorizontalAlignment, VerticalAlignment};
+
+use crate::layout::TextRegion;
+
+/// Steps the badge goes through to reach the server, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Joining a Wi-Fi network
+    Wifi,
+    /// Waiting for an address from DHCP
+    Dhcp,
+    /// Looking up the server
+    Dns,
+    /// Connecting to the server and setting up TLS
+    Tls,
+    /// Connected and exchanging updates with the server
+    Server,
+}
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Wifi,
+        Stage::Dhcp,
+        Stage::Dns,
+        Stage::Tls,
+        Stage::Server,
+    ];
+
+    /// Short label shown under the indicator
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Wifi => "Wi-Fi",
+            Stage::Dhcp => "DHCP",
+            Stage::Dns => "DNS",
+            Stage::Tls => "TLS",
+            Stage::Server => "Server",
+        }
+    }
+}
+
+/// State of a single stage, derived from the current [`Status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageState {
+    Pending,
+    InProgress,
+    Done,
+    Failed,
+}
+
+/// What the status screen shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status<'a> {
+    /// The stage being worked on, every earlier stage is done
+    pub stage: Stage,
+    /// What is happening, e.g. the network being joined
+    pub message: &'a str,
+    /// Why the current stage failed
+    pub error: Option<&'a str>,
+}
+impl<'a> Status<'a> {
+    pub fn new(stage: Stage, message: &'a str) -> Self {
+        Self {
+            stage,
+            message,
+            error: None,
+        }
+    }
+
+    /// Mark the current stage as failed
+    pub fn with_error(mut self, error: &'a str) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    pub fn state(&self, stage: Stage) -> StageState {
+        match stage.cmp(&self.stage) {
+            core::cmp::Ordering::Less => StageState::Done,
+            core::cmp::Ordering::Equal if self.error.is_some() => StageState::Failed,
+            core::cmp::Ordering::Equal => StageState::InProgress,
+            core::cmp::Ordering::Greater => StageState::Pending,
+        }
+    }
+}
+
+/// Height of the indicator row, including the labels
+const INDICATOR_HEIGHT: u32 = 36;
+/// Diameter of an indicator
+const INDICATOR_DIAMETER: u32 = 15;
+/// Height of the error line along the bottom
+const ERROR_HEIGHT: u32 = 16;
+
+/// Render the status screen, filling the display
+pub fn draw_status(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    status: &Status,
+) -> Result<(), &'static str> {
+    // Note ink is `Off`, the driver treats Off as black.
+    const INK: BinaryColor = BinaryColor::Off;
+    let bounds = display.bounding_box();
+    display.clear(BinaryColor::On).map_err(|_| "clear")?;
+
+    // One column per stage with a line joining the indicators
+    let column = bounds.size.width / Stage::ALL.len() as u32;
+    let centre_y = bounds.top_left.y + 4 + INDICATOR_DIAMETER as i32 / 2;
+    let label_style = MonoTextStyle::new(&FONT_6X10, INK);
+    let centred = TextStyleBuilder::new()
+        .alignment(Alignment::Center)
+        .baseline(Baseline::Top)
+        .build();
+    for (i, stage) in Stage::ALL.into_iter().enumerate() {
+        let centre = Point::new(
+            bounds.top_left.x + (column * i as u32 + column / 2) as i32,
+            centre_y,
+        );
+        if i > 0 {
+            let radius = INDICATOR_DIAMETER as i32 / 2;
+            Line::new(
+                centre - Point::new(column as i32 - radius, 0),
+                centre - Point::new(radius + 1, 0),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(INK, 1))
+            .draw(display)
+            .map_err(|_| "draw status")?;
+        }
+        draw_indicator(display, centre, status.state(stage))?;
+        Text::with_text_style(
+            stage.label(),
+            centre + Point::new(0, INDICATOR_DIAMETER as i32 / 2 + 4),
+            label_style,
+            centred,
+        )
+        .draw(display)
+        .map_err(|_| "draw status")?;
+    }
+
+    // The message fills the space between the indicators and the error line
+    let message = TextRegion::fit(Rectangle::new(
+        bounds.top_left + Point::new(4, INDICATOR_HEIGHT as i32),
+        Size::new(
+            bounds.size.width.saturating_sub(8),
+            bounds
+                .size
+                .height
+                .saturating_sub(INDICATOR_HEIGHT + ERROR_HEIGHT + 2),
+        ),
+    ))
+    .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle);
+    message.draw(display, status.message)?;
+
+    // Errors are shown as white text on a black bar
+    if let Some(error) = status.error {
+        let bar = Rectangle::new(
+            bounds.top_left + Point::new(0, bounds.size.height.saturating_sub(ERROR_HEIGHT) as i32),
+            Size::new(bounds.size.width, ERROR_HEIGHT),
+        );
+        display.fill_solid(&bar, INK).map_err(|_| "draw status")?;
+        let centred = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        Text::with_text_style(
+            error,
+            bar.center(),
+            MonoTextStyle::new(&FONT_6X10, BinaryColor::On),
+            centred,
+        )
+        .draw(display)
+        .map_err(|_| "draw status")?;
+    }
+    Ok(())
+}
+
+/// Pending stages are an empty circle, the current stage has a dot in the middle,
+/// finished stages are filled in and a failed stage is crossed out.
+fn draw_indicator(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    centre: Point,
+    state: StageState,
+) -> Result<(), &'static str> {
+    const INK: BinaryColor = BinaryColor::Off;
+    let circle = Circle::with_center(centre, INDICATOR_DIAMETER);
+    let style = match state {
+        StageState::Done => PrimitiveStyle::with_fill(INK),
+        _ => PrimitiveStyle::with_stroke(INK, 1),
+    };
+    circle
+        .into_styled(style)
+        .draw(display)
+        .map_err(|_| "draw status")?;
+
+    match state {
+        StageState::InProgress => Circle::with_center(centre, INDICATOR_DIAMETER / 2)
+            .into_styled(PrimitiveStyle::with_fill(INK))
+            .draw(display),
+        StageState::Failed => {
+            let d = INDICATOR_DIAMETER as i32 / 2 - 3;
+            let stroke = PrimitiveStyle::with_stroke(INK, 2);
+            Line::new(centre - Point::new(d, d), centre + Point::new(d, d))
+                .into_styled(stroke)
+                .draw(display)
+                .and_then(|_| {
+                    Line::new(centre + Point::new(-d, d), centre + Point::new(d, -d))
+                        .into_styled(stroke)
+                        .draw(display)
+                })
+        }
+        StageState::Pending | StageState::Done => Ok(()),
+    }
+    .map_err(|_| "draw status")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_states() {
+        let status = Status::new(Stage::Dns, "dev.aughey.com");
+        assert_eq!(status.state(Stage::Wifi), StageState::Done);
+        assert_eq!(status.state(Stage::Dhcp), StageState::Done);
+        assert_eq!(status.state(Stage::Dns), StageState::InProgress);
+        assert_eq!(status.state(Stage::Tls), StageState::Pending);
+        assert_eq!(status.state(Stage::Server), StageState::Pending);
+
+        let status = status.with_error("DNS query failed");
+        assert_eq!(status.state(Stage::Dhcp), StageState::Done);
+        assert_eq!(status.state(Stage::Dns), StageState::Failed);
+        assert_eq!(status.state(Stage::Tls), StageState::Pending);
+    }
+}
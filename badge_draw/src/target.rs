@@ -0,0 +1,108 @@
+//! Draw target adapters used to transform drawing onto the badge display.
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// Draws into an area of another target, magnifying every pixel into a
+/// `scale` x `scale` block.  Pixels falling outside the area are clipped.
+pub struct Scaled<'a, D> {
+    target: &'a mut D,
+    area: Rectangle,
+    scale: u32,
+}
+impl<'a, D> Scaled<'a, D> {
+    pub fn new(target: &'a mut D, area: Rectangle, scale: u32) -> Self {
+        Self {
+            target,
+            area,
+            scale: scale.max(1),
+        }
+    }
+}
+impl<D> Dimensions for Scaled<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.area.size / self.scale)
+    }
+}
+impl<D> DrawTarget for Scaled<'_, D>
+where
+    D: DrawTarget,
+{
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                self.fill_solid(&Rectangle::new(point, Size::new(1, 1)), color)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let scaled = Rectangle::new(
+            self.area.top_left + area.top_left * self.scale as i32,
+            area.size * self.scale,
+        );
+        self.target.fill_solid(&scaled, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.fill_solid(&self.area, color)
+    }
+}
+
+/// Draws onto another target with black and white swapped
+pub struct Inverted<'a, D> {
+    target: &'a mut D,
+}
+impl<'a, D> Inverted<'a, D> {
+    pub fn new(target: &'a mut D) -> Self {
+        Self { target }
+    }
+}
+impl<D> Dimensions for Inverted<'_, D>
+where
+    D: Dimensions,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+impl<D> DrawTarget for Inverted<'_, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    type Color = BinaryColor;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, color.invert())),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.fill_solid(area, color.invert())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.clear(color.invert())
+    }
+}
@@ -0,0 +1,224 @@
+//! Visual styles applied around and over a layout.
+//!
+//! A [`Theme`] decides the colours of the badge and any decoration drawn
+//! around the layout: a border, or a black bar across the top holding the
+//! first line of the text.  Themes that decorate the edges leave a smaller
+//! [`Theme::content_area`] for the layout, see [`crate::Template::themed_layout`].
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    geometry::{Point, Size},
+    mono_font::{iso_8859_1::FONT_9X15_BOLD, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    primitives::{
+        CornerRadii, Primitive, PrimitiveStyleBuilder, Rectangle, RoundedRectangle, StrokeAlignment,
+    },
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+    Drawable,
+};
+
+/// Width of the border drawn by [`Theme::Framed`]
+const FRAME_WIDTH: u32 = 3;
+/// Gap between the border and the layout
+const FRAME_GAP: u32 = 3;
+/// Radius of the border's corners
+const FRAME_RADIUS: u32 = 8;
+/// Height of the bar drawn by [`Theme::HeaderBar`]
+const HEADER_HEIGHT: u32 = 20;
+
+/// Built-in themes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Theme {
+    /// Black on white
+    #[default]
+    Normal = 0,
+    /// White on black
+    Inverted = 1,
+    /// A border with rounded corners around the edge of the display
+    Framed = 2,
+    /// The first line of the text in white on a black bar across the top
+    HeaderBar = 3,
+}
+impl Theme {
+    pub const ALL: [Theme; 4] = [
+        Theme::Normal,
+        Theme::Inverted,
+        Theme::Framed,
+        Theme::HeaderBar,
+    ];
+
+    /// Identifier used to select the theme in a badge update
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// Human readable name of the theme
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Normal => "Normal",
+            Theme::Inverted => "Inverted",
+            Theme::Framed => "Framed",
+            Theme::HeaderBar => "Header bar (first line)",
+        }
+    }
+
+    /// Whether the layout is drawn white on black
+    pub fn is_inverted(self) -> bool {
+        self == Theme::Inverted
+    }
+
+    /// Whether the first line of the text goes in the header bar
+    pub fn has_header(self) -> bool {
+        self == Theme::HeaderBar
+    }
+
+    /// The part of a display of the given size left for the layout
+    pub fn content_area(self, size: Size) -> Rectangle {
+        match self {
+            Theme::Normal | Theme::Inverted => Rectangle::new(Point::zero(), size),
+            Theme::Framed => {
+                Rectangle::new(Point::zero(), size).offset(-((FRAME_WIDTH + FRAME_GAP) as i32))
+            }
+            Theme::HeaderBar => Rectangle::new(
+                Point::new(0, HEADER_HEIGHT as i32),
+                Size::new(size.width, size.height.saturating_sub(HEADER_HEIGHT)),
+            ),
+        }
+    }
+
+    /// Draw the decoration of the theme onto a cleared display.  `header` is the
+    /// text for the header bar.
+    pub fn decorate(
+        self,
+        display: &mut impl DrawTarget<Color = BinaryColor>,
+        header: &str,
+    ) -> Result<(), &'static str> {
+        // Note ink is `Off`, the driver treats Off as black.
+        const INK: BinaryColor = BinaryColor::Off;
+        let bounds = display.bounding_box();
+        match self {
+            Theme::Normal | Theme::Inverted => Ok(()),
+            Theme::Framed => {
+                let style = PrimitiveStyleBuilder::new()
+                    .stroke_color(INK)
+                    .stroke_width(FRAME_WIDTH)
+                    .stroke_alignment(StrokeAlignment::Inside)
+                    .build();
+                RoundedRectangle::new(bounds, CornerRadii::new(Size::new_equal(FRAME_RADIUS)))
+                    .into_styled(style)
+                    .draw(display)
+                    .map_err(|_| "draw theme")
+            }
+            Theme::HeaderBar => {
+                let bar =
+                    Rectangle::new(bounds.top_left, Size::new(bounds.size.width, HEADER_HEIGHT));
+                display.fill_solid(&bar, INK).map_err(|_| "draw theme")?;
+                let centred = TextStyleBuilder::new()
+                    .alignment(Alignment::Center)
+                    .baseline(Baseline::Middle)
+                    .build();
+                Text::with_text_style(
+                    header,
+                    bar.center(),
+                    MonoTextStyle::new(&FONT_9X15_BOLD, BinaryColor::On),
+                    centred,
+                )
+                .draw(&mut display.clipped(&bar))
+                .map_err(|_| "draw theme")?;
+                Ok(())
+            }
+        }
+    }
+}
+impl TryFrom<u8> for Theme {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Theme, Self::Error> {
+        Theme::ALL
+            .into_iter()
+            .find(|theme| theme.id() == value)
+            .ok_or("unknown theme")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{draw_template_frame, Framebuffer, Logo, Template, TextMode};
+    use embedded_graphics::Pixel;
+
+    #[test]
+    fn test_theme_ids() {
+        for theme in Theme::ALL {
+            assert_eq!(Theme::try_from(theme.id()), Ok(theme));
+        }
+        assert!(Theme::try_from(4).is_err());
+    }
+
+    #[test]
+    fn test_content_area() {
+        let size = Size::new(296, 128);
+        let full = Rectangle::new(Point::zero(), size);
+        assert_eq!(Theme::Inverted.content_area(size), full);
+        assert_eq!(
+            Theme::Framed.content_area(size),
+            Rectangle::new(Point::new(6, 6), Size::new(284, 116))
+        );
+        assert_eq!(
+            Theme::HeaderBar.content_area(size),
+            Rectangle::new(Point::new(0, 20), Size::new(296, 108))
+        );
+    }
+
+    #[test]
+    fn test_themes() {
+        let size = Size::new(80, 40);
+        let render = |theme| {
+            let mut framebuffer = Framebuffer::new(size);
+            draw_template_frame(
+                &mut framebuffer,
+                Template::Headline,
+                theme,
+                "Title\nHi",
+                None,
+                TextMode::Static,
+                0,
+            )
+            .unwrap();
+            framebuffer
+        };
+        let black = |framebuffer: &Framebuffer| {
+            framebuffer
+                .pixels()
+                .filter(|Pixel(_, color)| color.is_off())
+                .count()
+        };
+        let normal = render(Theme::Normal);
+        let inverted = render(Theme::Inverted);
+        assert_eq!(
+            black(&normal) + black(&inverted),
+            (size.width * size.height) as usize
+        );
+
+        // the border runs around the edge and nothing else comes near it
+        let framed = render(Theme::Framed);
+        assert_eq!(
+            framed.pixel(Point::new(0, size.height as i32 / 2)),
+            Some(BinaryColor::Off)
+        );
+        assert_eq!(
+            framed.pixel(Point::new(4, size.height as i32 / 2)),
+            Some(BinaryColor::On)
+        );
+
+        // the first line goes in the header bar, leaving the rest for the layout
+        let header = render(Theme::HeaderBar);
+        assert_eq!(header.pixel(Point::new(0, 0)), Some(BinaryColor::Off));
+        let layout = Template::Headline.themed_layout(size, Logo::default(), Theme::HeaderBar);
+        let fit = layout.analyze("Title\nHi");
+        assert_eq!(fit.header, Some("Title"));
+        assert_eq!(fit.regions[0].lines, ["Hi"]);
+        assert!(layout.regions[0].bounds.top_left.y >= 20);
+    }
+}
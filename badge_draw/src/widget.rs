@@ -0,0 +1,361 @@
+//! Small reusable UI elements.
+//!
+//! Every widget draws into the rectangle it is given, clearing it first, so the
+//! firmware's status bar and screens composed on the server can place them
+//! anywhere and redraw them in place when their data changes.
+
+use core::fmt::Write as _;
+
+use embedded_graphics::{
+    draw_target::{DrawTarget, DrawTargetExt},
+    geometry::{Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::{
+        CornerRadii, Line, Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle,
+        RoundedRectangle, StrokeAlignment, Triangle,
+    },
+    Drawable,
+};
+use embedded_text::alignment::{HorizontalAlignment, VerticalAlignment};
+
+use crate::font::Spacing;
+use crate::layout::TextRegion;
+
+/// Note ink is `Off`, the driver treats Off as black
+const INK: BinaryColor = BinaryColor::Off;
+const PAPER: BinaryColor = BinaryColor::On;
+
+fn clear(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+) -> Result<(), &'static str> {
+    display
+        .fill_solid(&bounds, PAPER)
+        .map_err(|_| "draw widget")
+}
+
+fn outline(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+) -> Result<(), &'static str> {
+    bounds
+        .into_styled(PrimitiveStyle::with_stroke(INK, 1))
+        .draw(display)
+        .map_err(|_| "draw widget")
+}
+
+/// `value` out of `max` of `length` pixels, rounded down
+fn proportion(value: u32, max: u32, length: u32) -> u32 {
+    if max == 0 {
+        return 0;
+    }
+    (u64::from(value.min(max)) * u64::from(length) / u64::from(max)) as u32
+}
+
+/// A battery outline with its terminal on the right, filled to `percent`.
+/// A charging battery has a lightning bolt cut out of it.
+pub fn draw_battery(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    percent: u8,
+    charging: bool,
+) -> Result<(), &'static str> {
+    clear(display, bounds)?;
+    let terminal_width = (bounds.size.width / 10).max(2);
+    let body = Rectangle::new(
+        bounds.top_left,
+        Size::new(
+            bounds.size.width.saturating_sub(terminal_width),
+            bounds.size.height,
+        ),
+    );
+    let terminal_height = bounds.size.height / 2;
+    let terminal = Rectangle::new(
+        bounds.top_left
+            + Point::new(
+                body.size.width as i32,
+                (bounds.size.height - terminal_height) as i32 / 2,
+            ),
+        Size::new(terminal_width, terminal_height),
+    );
+    outline(display, body)?;
+    display
+        .fill_solid(&terminal, INK)
+        .map_err(|_| "draw widget")?;
+
+    // A one pixel gap between the outline and the charge
+    let inside = body.offset(-2);
+    let level = Size::new(
+        proportion(u32::from(percent), 100, inside.size.width),
+        inside.size.height,
+    );
+    display
+        .fill_solid(&Rectangle::new(inside.top_left, level), INK)
+        .map_err(|_| "draw widget")?;
+
+    if charging {
+        // A lightning bolt of two triangles, white over the charge and black
+        // over the empty part of the battery
+        let charge = Rectangle::new(inside.top_left, level);
+        let empty = Rectangle::new(
+            inside.top_left + Point::new(level.width as i32, 0),
+            Size::new(inside.size.width - level.width, inside.size.height),
+        );
+        let centre = inside.center();
+        let (top, bottom) = (
+            inside.top_left.y,
+            inside.top_left.y + inside.size.height as i32,
+        );
+        let half = (inside.size.width as i32 / 6).max(1);
+        for triangle in [
+            Triangle::new(
+                Point::new(centre.x + half, top),
+                Point::new(centre.x - half, centre.y + 1),
+                Point::new(centre.x + 1, centre.y + 1),
+            ),
+            Triangle::new(
+                Point::new(centre.x - 1, centre.y),
+                Point::new(centre.x + half, centre.y),
+                Point::new(centre.x - half, bottom),
+            ),
+        ] {
+            for (area, color) in [(charge, PAPER), (empty, INK)] {
+                triangle
+                    .into_styled(PrimitiveStyle::with_fill(color))
+                    .draw(&mut display.clipped(&area))
+                    .map_err(|_| "draw widget")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How many of `max_bars` signal bars a received signal strength is worth.
+/// -90 dBm or weaker is no bars, -50 dBm or stronger is every bar.
+pub fn signal_bars(rssi_dbm: i32, max_bars: u8) -> u8 {
+    const WEAKEST: i32 = -90;
+    const STRONGEST: i32 = -50;
+    let strength = (rssi_dbm.clamp(WEAKEST, STRONGEST) - WEAKEST) as u32;
+    proportion(strength, (STRONGEST - WEAKEST) as u32, u32::from(max_bars)) as u8
+}
+
+/// Bars of increasing height from left to right, the first `bars` of them filled
+pub fn draw_signal(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    bars: u8,
+    max_bars: u8,
+) -> Result<(), &'static str> {
+    clear(display, bounds)?;
+    let count = u32::from(max_bars.max(1));
+    // Bars are twice as wide as the gaps between them
+    let pitch = bounds.size.width * 3 / (3 * count - 1).max(1);
+    let bar_width = (pitch * 2 / 3).max(1);
+    for i in 0..count {
+        let height = (bounds.size.height * (i + 1) / count).max(1);
+        let bar = Rectangle::new(
+            bounds.top_left + Point::new((i * pitch) as i32, (bounds.size.height - height) as i32),
+            Size::new(bar_width, height),
+        );
+        let style = if i < u32::from(bars) {
+            PrimitiveStyle::with_fill(INK)
+        } else {
+            PrimitiveStyle::with_stroke(INK, 1)
+        };
+        bar.into_styled(style)
+            .draw(display)
+            .map_err(|_| "draw widget")?;
+    }
+    Ok(())
+}
+
+/// A 24 hour clock, `HH:MM`, as large as fits
+pub fn draw_clock(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    hours: u8,
+    minutes: u8,
+) -> Result<(), &'static str> {
+    clear(display, bounds)?;
+    let mut text = heapless::String::<8>::new();
+    write!(text, "{:02}:{:02}", hours % 24, minutes % 60).map_err(|_| "format clock")?;
+    TextRegion::fit(bounds)
+        .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle)
+        .draw(display, &text)?;
+    Ok(())
+}
+
+/// An outlined bar filled to `value` out of `max`
+pub fn draw_progress(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    value: u32,
+    max: u32,
+) -> Result<(), &'static str> {
+    clear(display, bounds)?;
+    outline(display, bounds)?;
+    let inside = bounds.offset(-2);
+    let filled = Size::new(
+        proportion(value, max, inside.size.width),
+        inside.size.height,
+    );
+    display
+        .fill_solid(&Rectangle::new(inside.top_left, filled), INK)
+        .map_err(|_| "draw widget")
+}
+
+/// A line graph of the values, oldest on the left, scaled to fill the height
+/// between the smallest and largest value
+pub fn draw_sparkline(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    values: &[i32],
+) -> Result<(), &'static str> {
+    clear(display, bounds)?;
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return Ok(());
+    };
+    let Some(bottom_right) = bounds.bottom_right() else {
+        return Ok(());
+    };
+    let range = i64::from(max) - i64::from(min);
+    let height = i64::from(bounds.size.height - 1);
+    let steps = (values.len() as i64 - 1).max(1);
+    let width = i64::from(bounds.size.width - 1);
+    let point = |i: usize, value: i32| {
+        let y = match range {
+            // A flat line goes through the middle
+            0 => height / 2,
+            range => (i64::from(value) - i64::from(min)) * height / range,
+        };
+        Point::new(
+            bounds.top_left.x + (i as i64 * width / steps) as i32,
+            bottom_right.y - y as i32,
+        )
+    };
+
+    let style = PrimitiveStyle::with_stroke(INK, 1);
+    if let [value] = values {
+        return Line::new(
+            point(0, *value),
+            Point::new(bottom_right.x, point(0, *value).y),
+        )
+        .into_styled(style)
+        .draw(display)
+        .map_err(|_| "draw widget");
+    }
+    for (i, pair) in values.windows(2).enumerate() {
+        Line::new(point(i, pair[0]), point(i + 1, pair[1]))
+            .into_styled(style)
+            .draw(display)
+            .map_err(|_| "draw widget")?;
+    }
+    Ok(())
+}
+
+/// Text in a box with a rounded border, e.g. for announcements
+pub fn draw_callout(
+    display: &mut impl DrawTarget<Color = BinaryColor>,
+    bounds: Rectangle,
+    text: &str,
+) -> Result<(), &'static str> {
+    const BORDER: u32 = 2;
+    const RADIUS: u32 = 6;
+    const PADDING: u32 = 4;
+    clear(display, bounds)?;
+    let style = PrimitiveStyleBuilder::new()
+        .stroke_color(INK)
+        .stroke_width(BORDER)
+        .stroke_alignment(StrokeAlignment::Inside)
+        .build();
+    RoundedRectangle::new(bounds, CornerRadii::new(Size::new_equal(RADIUS)))
+        .into_styled(style)
+        .draw(display)
+        .map_err(|_| "draw widget")?;
+    TextRegion::fit(bounds.offset(-((BORDER + PADDING) as i32)))
+        .with_alignment(HorizontalAlignment::Center, VerticalAlignment::Middle)
+        .with_spacing(Spacing::Proportional)
+        .draw(display, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Framebuffer;
+    use embedded_graphics::{geometry::Dimensions, primitives::PointsIter};
+
+    fn black_in(framebuffer: &Framebuffer, area: Rectangle) -> usize {
+        area.points()
+            .filter(|&point| framebuffer.pixel(point) == Some(INK))
+            .count()
+    }
+
+    #[test]
+    fn test_battery() {
+        let bounds = Rectangle::new(Point::new(2, 2), Size::new(32, 14));
+        let mut framebuffer = Framebuffer::new(Size::new(40, 20));
+        draw_battery(&mut framebuffer, bounds, 0, false).unwrap();
+        let empty = black_in(&framebuffer, bounds);
+        draw_battery(&mut framebuffer, bounds, 50, false).unwrap();
+        let half = black_in(&framebuffer, bounds);
+        draw_battery(&mut framebuffer, bounds, 100, false).unwrap();
+        let full = black_in(&framebuffer, bounds);
+        assert!(empty < half && half < full, "{empty} {half} {full}");
+        // nothing is drawn outside the bounds
+        assert_eq!(black_in(&framebuffer, framebuffer.bounding_box()), full);
+
+        // the bolt shows whether the battery is empty or full
+        for percent in [0, 100] {
+            draw_battery(&mut framebuffer, bounds, percent, false).unwrap();
+            let plain = black_in(&framebuffer, bounds);
+            draw_battery(&mut framebuffer, bounds, percent, true).unwrap();
+            assert_ne!(black_in(&framebuffer, bounds), plain);
+        }
+    }
+
+    #[test]
+    fn test_signal_bars() {
+        assert_eq!(signal_bars(-100, 4), 0);
+        assert_eq!(signal_bars(-70, 4), 2);
+        assert_eq!(signal_bars(-30, 4), 4);
+
+        let bounds = Rectangle::new(Point::zero(), Size::new(19, 8));
+        let mut framebuffer = Framebuffer::new(bounds.size);
+        draw_signal(&mut framebuffer, bounds, 4, 4).unwrap();
+        // bars of 1, 2, 3 and 4 quarters of the height, 3 pixels wide
+        assert_eq!(black_in(&framebuffer, bounds), 3 * (2 + 4 + 6 + 8));
+        draw_signal(&mut framebuffer, bounds, 0, 4).unwrap();
+        assert!(black_in(&framebuffer, bounds) < 3 * (2 + 4 + 6 + 8));
+    }
+
+    #[test]
+    fn test_progress() {
+        let bounds = Rectangle::new(Point::zero(), Size::new(24, 6));
+        let mut framebuffer = Framebuffer::new(bounds.size);
+        draw_progress(&mut framebuffer, bounds, 1, 2).unwrap();
+        let inside = bounds.offset(-2);
+        // the left half of the inside is filled
+        assert_eq!(black_in(&framebuffer, inside), 10 * 2);
+        draw_progress(&mut framebuffer, bounds, 5, 0).unwrap();
+        assert_eq!(black_in(&framebuffer, inside), 0);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let bounds = Rectangle::new(Point::new(1, 1), Size::new(10, 5));
+        let mut framebuffer = Framebuffer::new(Size::new(12, 7));
+        draw_sparkline(&mut framebuffer, bounds, &[0, 10, -10, 5]).unwrap();
+        let total = black_in(&framebuffer, framebuffer.bounding_box());
+        assert!(total > 0);
+        assert_eq!(black_in(&framebuffer, bounds), total);
+        // the largest value touches the top and the smallest the bottom
+        assert_eq!(framebuffer.pixel(Point::new(4, 1)), Some(INK));
+        assert_eq!(framebuffer.pixel(Point::new(7, 5)), Some(INK));
+
+        draw_sparkline(&mut framebuffer, bounds, &[3, 3]).unwrap();
+        assert_eq!(black_in(&framebuffer, bounds), 10);
+        draw_sparkline(&mut framebuffer, bounds, &[]).unwrap();
+        assert_eq!(black_in(&framebuffer, bounds), 0);
+    }
+}
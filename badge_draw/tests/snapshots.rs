@@ -0,0 +1,150 @@
+//! Golden image tests: render representative texts the way the badge would and
+//! compare them with the reference images in `tests/snapshots`.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to write new reference images after an
+//! intended change to the rendering, then review and commit them.  A missing
+//! reference fails the test like a mismatch does.  On a mismatch the expected,
+//! actual and differing pixels are written side by side to a PNG in the target
+//! directory.
+
+use std::path::PathBuf;
+
+use badge_draw::Framebuffer;
+use embedded_graphics::{
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::BinaryColor,
+    Pixel,
+};
+
+/// Gap between the images in a diff
+const DIFF_GAP: u32 = 8;
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.pbm"))
+}
+
+fn update_requested() -> bool {
+    std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value != "0")
+}
+
+/// Expected, actual and the pixels that differ, left to right
+fn visual_diff(expected: &Framebuffer, actual: &Framebuffer) -> Framebuffer {
+    let Size { width, height } = actual.size();
+    let width = width.max(expected.size().width);
+    let height = height.max(expected.size().height);
+    let mut diff = Framebuffer::new(Size::new(3 * width + 2 * DIFF_GAP, height));
+    let offset = |column: u32| Point::new((column * (width + DIFF_GAP)) as i32, 0);
+    for (column, image) in [expected, actual].into_iter().enumerate() {
+        for Pixel(point, color) in image.pixels() {
+            diff.set_pixel(point + offset(column as u32), color);
+        }
+    }
+    for Pixel(point, color) in actual.pixels() {
+        if expected.pixel(point) != Some(color) {
+            diff.set_pixel(point + offset(2), BinaryColor::Off);
+        }
+    }
+    diff
+}
+
+/// Compare the rendering with its reference image, writing the reference instead
+/// if an update was requested
+fn assert_snapshot(name: &str, actual: &Framebuffer) {
+    let path = reference_path(name);
+    if update_requested() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual.to_pbm()).unwrap();
+        eprintln!("wrote snapshot {}", path.display());
+        return;
+    }
+    assert!(
+        path.exists(),
+        "snapshot {name} has no reference image at {}\n\
+         run with UPDATE_SNAPSHOTS=1 to write it, then review and commit it",
+        path.display()
+    );
+
+    let expected = Framebuffer::from_pbm(&std::fs::read(&path).unwrap())
+        .unwrap_or_else(|e| panic!("reading {}: {e}", path.display()));
+    if expected == *actual {
+        return;
+    }
+    let diff_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("snapshots")
+        .join(format!("{name}.diff.png"));
+    std::fs::create_dir_all(diff_path.parent().unwrap()).unwrap();
+    std::fs::write(&diff_path, visual_diff(&expected, actual).to_png()).unwrap();
+    match expected.count_differences(actual) {
+        Some(count) => panic!(
+            "snapshot {name} differs in {count} pixels, see {}\n\
+             rerun with UPDATE_SNAPSHOTS=1 if the change is intended",
+            diff_path.display()
+        ),
+        None => panic!(
+            "snapshot {name} is {:?} but the rendering is {:?}, see {}",
+            expected.size(),
+            actual.size(),
+            diff_path.display()
+        ),
+    }
+}
+
+fn render(text: &str) -> Framebuffer {
+    let mut framebuffer = Framebuffer::badge();
+    badge_draw::draw_display(&mut framebuffer, text, None).unwrap();
+    framebuffer
+}
+
+#[test]
+fn test_empty() {
+    assert_snapshot("empty", &render(""));
+}
+
+#[test]
+fn test_long() {
+    assert_snapshot(
+        "long",
+        &render("The quick brown fox jumps over the lazy dog, then it does it again"),
+    );
+}
+
+#[test]
+fn test_multiline() {
+    assert_snapshot("multiline", &render("John Aughey\nRust\nBadger 2040W"));
+}
+
+#[test]
+fn test_max_length() {
+    // 13x5 characters, the limit the web page used to apply
+    let text = "ABCDEFGHIJKLM".repeat(5);
+    assert_snapshot("max_length", &render(&text));
+}
+
+#[test]
+fn test_portrait() {
+    let mut framebuffer = Framebuffer::badge();
+    badge_draw::draw_rotated(
+        &mut framebuffer,
+        badge_draw::Rotation::Deg90,
+        badge_draw::Template::Ferris,
+        "John Aughey\nRust",
+        None,
+    )
+    .unwrap();
+    assert_snapshot("portrait", &framebuffer);
+}
+
+#[test]
+fn test_visual_diff() {
+    let expected = Framebuffer::new(Size::new(4, 2));
+    let mut actual = expected.clone();
+    actual.set_pixel(Point::new(1, 0), BinaryColor::Off);
+    let diff = visual_diff(&expected, &actual);
+    assert_eq!(diff.size(), Size::new(3 * 4 + 2 * DIFF_GAP, 2));
+    // the differing pixel is black in the right hand image
+    let right = 2 * (4 + DIFF_GAP) as i32;
+    assert_eq!(diff.pixel(Point::new(right + 1, 0)), Some(BinaryColor::Off));
+    assert_eq!(diff.pixel(Point::new(right, 0)), Some(BinaryColor::On));
+}
@@ -0,0 +1,148 @@
+//! Continuous health tests on the raw bits of a noise source, the repetition
+//! count and adaptive proportion tests of NIST SP 800-90B section 4.4.
+//!
+//! Both cutoffs give a false alarm rate of 2^-20 for a source with the
+//! min-entropy H claimed by [`RAW_BITS_PER_BIT`].
+
+/// Raw bits credited with one bit of min-entropy, H is a half.  Ring oscillator
+/// bits are biased and correlated, so well below what a perfect source would give.
+pub const RAW_BITS_PER_BIT: u32 = 2;
+
+/// Failing length of a run of identical bits, `1 + ceil(20 / H)`
+pub const REPETITION_CUTOFF: u32 = 41;
+
+/// Bits in each adaptive proportion window
+pub const PROPORTION_WINDOW: u32 = 1024;
+
+/// Failing count of the window's first bit within the window,
+/// `1 + CRITBINOM(1024, 2^-H, 1 - 2^-20)`
+pub const PROPORTION_CUTOFF: u32 = 793;
+
+/// Fails when a source is stuck, repeating the same bit
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepetitionCount {
+    last: Option<bool>,
+    run: u32,
+}
+impl RepetitionCount {
+    pub const fn new() -> Self {
+        Self { last: None, run: 0 }
+    }
+
+    pub fn check(&mut self, bit: bool) -> Result<(), &'static str> {
+        if self.last == Some(bit) {
+            self.run += 1;
+        } else {
+            self.last = Some(bit);
+            self.run = 1;
+        }
+        if self.run >= REPETITION_CUTOFF {
+            return Err("entropy source stuck");
+        }
+        Ok(())
+    }
+}
+
+/// Fails when one value becomes much more likely than the source's entropy allows
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AdaptiveProportion {
+    /// First bit of the window
+    first: bool,
+    /// Times the first bit has been seen in the window
+    count: u32,
+    /// Bits seen in the window, 0 starts a new one
+    seen: u32,
+}
+impl AdaptiveProportion {
+    pub const fn new() -> Self {
+        Self {
+            first: false,
+            count: 0,
+            seen: 0,
+        }
+    }
+
+    pub fn check(&mut self, bit: bool) -> Result<(), &'static str> {
+        if self.seen == 0 {
+            self.first = bit;
+            self.count = 0;
+        }
+        if bit == self.first {
+            self.count += 1;
+        }
+        self.seen = (self.seen + 1) % PROPORTION_WINDOW;
+        if self.count >= PROPORTION_CUTOFF {
+            return Err("entropy source biased");
+        }
+        Ok(())
+    }
+}
+
+/// Both tests, run on every raw bit
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HealthTests {
+    repetition: RepetitionCount,
+    proportion: AdaptiveProportion,
+}
+impl HealthTests {
+    pub const fn new() -> Self {
+        Self {
+            repetition: RepetitionCount::new(),
+            proportion: AdaptiveProportion::new(),
+        }
+    }
+
+    pub fn check(&mut self, bit: bool) -> Result<(), &'static str> {
+        self.repetition.check(bit)?;
+        self.proportion.check(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fair_bits;
+
+    #[test]
+    fn test_fair_source_passes() {
+        let mut health = HealthTests::new();
+        for bit in fair_bits(0x1234_5678).take(100_000) {
+            assert_eq!(health.check(bit), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_stuck_source_fails() {
+        let mut repetition = RepetitionCount::new();
+        for _ in 1..REPETITION_CUTOFF {
+            assert_eq!(repetition.check(true), Ok(()));
+        }
+        assert_eq!(repetition.check(true), Err("entropy source stuck"));
+
+        // a run broken just before the cutoff is fine
+        let mut repetition = RepetitionCount::new();
+        for _ in 1..REPETITION_CUTOFF {
+            repetition.check(false).unwrap();
+        }
+        assert_eq!(repetition.check(true), Ok(()));
+        assert_eq!(repetition.check(false), Ok(()));
+    }
+
+    #[test]
+    fn test_biased_source_fails() {
+        // seven ones for every zero never repeats long enough to look stuck
+        let biased = (1..).map(|i| i % 8 != 0);
+        let mut health = HealthTests::new();
+        let failure = biased
+            .take(PROPORTION_WINDOW as usize)
+            .map(|bit| health.check(bit))
+            .find(Result::is_err);
+        assert_eq!(failure, Some(Err("entropy source biased")));
+
+        // three to one is within what half a bit of entropy allows
+        let mut proportion = AdaptiveProportion::new();
+        for i in 1..=4 * PROPORTION_WINDOW {
+            assert_eq!(proportion.check(i % 4 != 0), Ok(()));
+        }
+    }
+}
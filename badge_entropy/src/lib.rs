@@ -0,0 +1,198 @@
+//! Randomness for the badge's network stack and TLS.
+//!
+//! Raw bits from a noise source, the RP2040's ring oscillator on the badge, go
+//! through the [`health`] tests into a [`Collector`].  Other inputs such as
+//! timer jitter are mixed in too but not counted, as nothing checks them.  Once
+//! enough raw bits are in, the collector seeds a ChaCha20 CSPRNG.
+//!
+//! The pool is stirred with ChaCha20 itself: each 32 byte block of input is
+//! XORed into the key, which is then replaced by the start of its own keystream.
+
+#![no_std]
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+pub use rand_chacha::ChaCha20Rng;
+
+pub mod health;
+
+use health::HealthTests;
+
+/// Raw bits collected for each seed, four times what the health tests' entropy
+/// estimate needs for a 256 bit seed
+pub const SEED_RAW_BITS: u32 = 4 * 256 * health::RAW_BITS_PER_BIT;
+
+/// Gathers entropy and seeds random number generators from it
+#[derive(Debug, Default, Clone)]
+pub struct Collector {
+    health: HealthTests,
+    /// Set once a health test fails, the source can't be trusted again
+    failure: Option<&'static str>,
+    key: [u8; 32],
+    /// Input waiting to be stirred into the key
+    block: [u8; 32],
+    filled: usize,
+    /// Raw bits not yet made into a byte, and how many
+    bits: u8,
+    bit_count: u8,
+    /// Raw bits since the last seed
+    raw_bits: u32,
+}
+impl Collector {
+    pub const fn new() -> Self {
+        Self {
+            health: HealthTests::new(),
+            failure: None,
+            key: [0; 32],
+            block: [0; 32],
+            filled: 0,
+            bits: 0,
+            bit_count: 0,
+            raw_bits: 0,
+        }
+    }
+
+    /// Add a raw bit from the noise source
+    pub fn push_bit(&mut self, bit: bool) -> Result<(), &'static str> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        if let Err(failure) = self.health.check(bit) {
+            self.failure = Some(failure);
+            return Err(failure);
+        }
+        self.bits = (self.bits << 1) | u8::from(bit);
+        self.bit_count += 1;
+        if self.bit_count == 8 {
+            self.absorb(self.bits);
+            self.bits = 0;
+            self.bit_count = 0;
+        }
+        self.raw_bits += 1;
+        Ok(())
+    }
+
+    /// Mix in input that isn't counted as entropy, e.g. timer jitter
+    pub fn mix(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.absorb(byte);
+        }
+    }
+
+    /// Whether there are enough raw bits for [`Collector::rng`]
+    pub fn is_ready(&self) -> bool {
+        self.failure.is_none() && self.raw_bits >= SEED_RAW_BITS
+    }
+
+    /// A generator seeded from everything collected.  The next one needs
+    /// another [`SEED_RAW_BITS`] raw bits.
+    pub fn rng(&mut self) -> Result<ChaCha20Rng, &'static str> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        if self.raw_bits < SEED_RAW_BITS {
+            return Err("not enough entropy");
+        }
+        self.stir();
+        // Replace the key so the seed can't be worked back out of the pool
+        let mut stream = ChaCha20Rng::from_seed(self.key);
+        let mut seed = [0; 32];
+        stream.fill_bytes(&mut self.key);
+        stream.fill_bytes(&mut seed);
+        self.raw_bits = 0;
+        Ok(ChaCha20Rng::from_seed(seed))
+    }
+
+    fn absorb(&mut self, byte: u8) {
+        self.block[self.filled] = byte;
+        self.filled += 1;
+        if self.filled == self.block.len() {
+            self.stir();
+        }
+    }
+
+    fn stir(&mut self) {
+        for (key, input) in self.key.iter_mut().zip(self.block) {
+            *key ^= input;
+        }
+        ChaCha20Rng::from_seed(self.key).fill_bytes(&mut self.key);
+        self.block = [0; 32];
+        self.filled = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fair, uncorrelated bit stream, xorshift is plenty for testing
+    pub fn fair_bits(mut state: u32) -> impl Iterator<Item = bool> {
+        core::iter::repeat_with(move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state & 1 == 1
+        })
+    }
+
+    fn collect(seed: u32, extra: &[u8]) -> Collector {
+        let mut collector = Collector::new();
+        for bit in fair_bits(seed).take(SEED_RAW_BITS as usize) {
+            collector.push_bit(bit).unwrap();
+        }
+        collector.mix(extra);
+        collector
+    }
+
+    fn first_u64(collector: &mut Collector) -> u64 {
+        collector.rng().unwrap().next_u64()
+    }
+
+    #[test]
+    fn test_seed_needs_enough_bits() {
+        let mut collector = Collector::new();
+        let mut bits = fair_bits(1);
+        for bit in bits.by_ref().take(SEED_RAW_BITS as usize - 1) {
+            collector.push_bit(bit).unwrap();
+        }
+        assert!(!collector.is_ready());
+        assert!(collector.rng().is_err());
+        collector.push_bit(bits.next().unwrap()).unwrap();
+        assert!(collector.is_ready());
+        assert!(collector.rng().is_ok());
+        // every seed needs fresh bits
+        assert_eq!(collector.rng().err(), Some("not enough entropy"));
+    }
+
+    #[test]
+    fn test_seeds_follow_inputs() {
+        let first = first_u64(&mut collect(1, &[]));
+        assert_eq!(first, first_u64(&mut collect(1, &[])));
+        assert_ne!(first, first_u64(&mut collect(2, &[])));
+        // uncounted input still changes the seed
+        assert_ne!(first, first_u64(&mut collect(1, &[0x42])));
+
+        // later seeds differ from earlier ones
+        let mut collector = collect(1, &[]);
+        let earlier = first_u64(&mut collector);
+        for bit in fair_bits(1).take(SEED_RAW_BITS as usize) {
+            collector.push_bit(bit).unwrap();
+        }
+        assert_ne!(earlier, first_u64(&mut collector));
+    }
+
+    #[test]
+    fn test_failed_source() {
+        let mut collector = Collector::new();
+        let failure = (0..health::REPETITION_CUTOFF)
+            .map(|_| collector.push_bit(true))
+            .find(Result::is_err);
+        assert_eq!(failure, Some(Err("entropy source stuck")));
+        // a source that has failed is never trusted again
+        assert!(collector.push_bit(false).is_err());
+        for bit in fair_bits(1).take(SEED_RAW_BITS as usize) {
+            let _ = collector.push_bit(bit);
+        }
+        assert!(!collector.is_ready());
+        assert_eq!(collector.rng().err(), Some("entropy source stuck"));
+    }
+}
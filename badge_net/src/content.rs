@@ -0,0 +1,287 @@
+//! Tracks what the badge should be showing as updates with a time-to-live and
+//! priority arrive and expire.
+//!
+//! Time is passed in as milliseconds from an arbitrary epoch so this logic can
+//! be driven by `embassy_time` on the badge and tested on the host.
+
+use crate::{OwnedUpdate, Priority};
+
+/// A temporary update and the time at which it should be removed.
+#[derive(Debug, Clone, PartialEq)]
+struct Overlay {
+    update: OwnedUpdate,
+    expires_at_ms: u64,
+}
+
+/// The content currently shown on the badge.
+///
+/// Permanent updates (no ttl) are merged into the base content.  Updates with a ttl
+/// are shown on top of the base content until they expire, at which point the badge
+/// reverts to the base content.  An urgent overlay can only be replaced by another
+/// urgent update, everything else is replaced by the newest update.
+///
+/// Only the fields that affect what is drawn are tracked, the LED frequency and
+/// power policy are applied as soon as they are received.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ContentState {
+    base: OwnedUpdate,
+    overlay: Option<Overlay>,
+}
+impl ContentState {
+    pub const fn new() -> Self {
+        Self {
+            base: OwnedUpdate {
+                text: None,
+                freq: None,
+                ttl: None,
+                priority: Priority::Normal,
+                layout: None,
+                logo: None,
+                rotation: None,
+                mode: None,
+                theme: None,
+                power: None,
+            },
+            overlay: None,
+        }
+    }
+
+    /// The content that should currently be displayed
+    pub fn current(&self) -> &OwnedUpdate {
+        self.overlay
+            .as_ref()
+            .map(|overlay| &overlay.update)
+            .unwrap_or(&self.base)
+    }
+
+    /// The permanent content, without any temporary update shown over it
+    pub fn base(&self) -> &OwnedUpdate {
+        &self.base
+    }
+
+    /// Time at which the current content will expire, if it is temporary
+    pub fn expires_at_ms(&self) -> Option<u64> {
+        self.overlay.as_ref().map(|overlay| overlay.expires_at_ms)
+    }
+
+    /// Apply a received update at time `now_ms`.
+    /// Returns true if the displayed content changed and the badge should redraw.
+    pub fn apply(&mut self, update: OwnedUpdate, now_ms: u64) -> bool {
+        let before = self.current().clone();
+        // Whether this update is allowed to replace the temporary content on screen
+        let outranks_overlay = match &self.overlay {
+            Some(overlay) => update.priority >= overlay.update.priority,
+            None => true,
+        };
+
+        match update.ttl {
+            Some(ttl) if update.text.is_some() => {
+                if outranks_overlay {
+                    // Anything the temporary update doesn't specify comes from the base content
+                    let mut content = self.base.clone();
+                    merge(&mut content, update);
+                    content.ttl = Some(ttl);
+                    self.overlay = Some(Overlay {
+                        update: content,
+                        expires_at_ms: now_ms.saturating_add(u64::from(ttl) * 1000),
+                    });
+                }
+            }
+            _ => {
+                let new_message = update.text.is_some();
+                // The rotation is how the badge is worn, not part of the message, so
+                // it applies straight away even while a temporary message is shown
+                if let (Some(rotation), Some(overlay)) = (update.rotation, &mut self.overlay) {
+                    overlay.update.rotation = Some(rotation);
+                }
+                merge(&mut self.base, update);
+                if new_message && outranks_overlay {
+                    self.overlay = None;
+                }
+            }
+        }
+
+        before != *self.current()
+    }
+
+    /// Remove any expired content.
+    /// Returns true if the displayed content changed and the badge should redraw.
+    pub fn tick(&mut self, now_ms: u64) -> bool {
+        match self.expires_at_ms() {
+            Some(expires_at_ms) if now_ms >= expires_at_ms => {
+                let before = self.current().clone();
+                self.overlay = None;
+                before != *self.current()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Copy the displayed fields that are set in `update` into `content`
+fn merge(content: &mut OwnedUpdate, update: OwnedUpdate) {
+    if update.text.is_some() {
+        content.text = update.text;
+        content.priority = update.priority;
+    }
+    if update.layout.is_some() {
+        content.layout = update.layout;
+    }
+    if update.rotation.is_some() {
+        content.rotation = update.rotation;
+    }
+    if update.mode.is_some() {
+        content.mode = update.mode;
+    }
+    if update.theme.is_some() {
+        content.theme = update.theme;
+    }
+    if let Some(logo) = update.logo {
+        // An empty logo reverts to the default
+        content.logo = (!logo.is_empty()).then_some(logo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(text: &str, ttl: Option<u32>, priority: Priority) -> OwnedUpdate {
+        OwnedUpdate {
+            text: Some(text.try_into().unwrap()),
+            ttl,
+            priority,
+            ..Default::default()
+        }
+    }
+
+    fn text(state: &ContentState) -> Option<&str> {
+        state.current().text.as_ref().map(|t| t.as_str())
+    }
+
+    #[test]
+    fn test_permanent_update() {
+        let mut state = ContentState::new();
+        assert_eq!(text(&state), None);
+        assert!(state.apply(update("Hello", None, Priority::Normal), 0));
+        assert_eq!(text(&state), Some("Hello"));
+        assert!(!state.tick(u64::MAX));
+        assert_eq!(text(&state), Some("Hello"));
+    }
+
+    #[test]
+    fn test_expiry_reverts_to_previous() {
+        let mut state = ContentState::new();
+        state.apply(update("Name", None, Priority::Normal), 0);
+        assert!(state.apply(update("Break", Some(10), Priority::Normal), 1000));
+        assert_eq!(text(&state), Some("Break"));
+        assert_eq!(state.expires_at_ms(), Some(11_000));
+
+        assert!(!state.tick(10_999));
+        assert_eq!(text(&state), Some("Break"));
+        assert!(state.tick(11_000));
+        assert_eq!(text(&state), Some("Name"));
+        assert_eq!(state.expires_at_ms(), None);
+    }
+
+    #[test]
+    fn test_urgent_preempts_normal() {
+        let mut state = ContentState::new();
+        state.apply(update("Name", None, Priority::Normal), 0);
+        state.apply(update("Urgent", Some(60), Priority::Urgent), 0);
+
+        // Normal messages, temporary or not, do not displace the urgent one
+        assert!(!state.apply(update("Temp", Some(10), Priority::Normal), 1000));
+        assert!(!state.apply(update("New name", None, Priority::Normal), 2000));
+        assert_eq!(text(&state), Some("Urgent"));
+
+        // but the permanent one is remembered for when the urgent message expires
+        assert!(state.tick(60_000));
+        assert_eq!(text(&state), Some("New name"));
+    }
+
+    #[test]
+    fn test_newest_normal_wins() {
+        let mut state = ContentState::new();
+        state.apply(update("Temp", Some(10), Priority::Normal), 0);
+        assert!(state.apply(update("Name", None, Priority::Normal), 1000));
+        assert_eq!(text(&state), Some("Name"));
+        assert_eq!(state.expires_at_ms(), None);
+    }
+
+    #[test]
+    fn test_expiry_without_base_reverts_to_default() {
+        let mut state = ContentState::new();
+        state.apply(update("Temp", Some(1), Priority::Urgent), 0);
+        assert!(state.tick(1000));
+        assert_eq!(text(&state), None);
+    }
+
+    #[test]
+    fn test_freq_only_update_keeps_text() {
+        let mut state = ContentState::new();
+        state.apply(update("Name", None, Priority::Normal), 0);
+        let freq = OwnedUpdate {
+            freq: Some(100),
+            ..Default::default()
+        };
+        assert!(!state.apply(freq, 0));
+        assert_eq!(text(&state), Some("Name"));
+    }
+
+    #[test]
+    fn test_temporary_text_keeps_layout() {
+        let mut state = ContentState::new();
+        let layout = OwnedUpdate {
+            layout: Some(2),
+            ..Default::default()
+        };
+        assert!(state.apply(layout, 0));
+        assert!(state.apply(update("Temp", Some(10), Priority::Normal), 0));
+        assert_eq!(state.current().layout, Some(2));
+
+        // layout changes while a message is temporary are kept for afterwards
+        let layout = OwnedUpdate {
+            layout: Some(1),
+            ..Default::default()
+        };
+        assert!(!state.apply(layout, 1000));
+        assert_eq!(state.current().layout, Some(2));
+        assert!(state.tick(10_000));
+        assert_eq!(state.current().layout, Some(1));
+    }
+
+    #[test]
+    fn test_rotation_applies_to_temporary_text() {
+        let mut state = ContentState::new();
+        assert!(state.apply(update("Temp", Some(10), Priority::Normal), 0));
+        let rotation = OwnedUpdate {
+            rotation: Some(1),
+            ..Default::default()
+        };
+        assert!(state.apply(rotation, 1000));
+        assert_eq!(state.current().rotation, Some(1));
+        assert!(state.tick(10_000));
+        assert_eq!(state.current().rotation, Some(1));
+    }
+
+    #[test]
+    fn test_logo() {
+        let mut state = ContentState::new();
+        let logo = |bytes: &[u8]| OwnedUpdate {
+            logo: Some(heapless::Vec::from_slice(bytes).unwrap()),
+            ..Default::default()
+        };
+        assert!(state.apply(logo(b"BM"), 0));
+        assert_eq!(state.current().logo.as_deref(), Some(&b"BM"[..]));
+        assert!(!state.apply(logo(b"BM"), 0));
+
+        // the logo stays when new text arrives
+        assert!(state.apply(update("Name", None, Priority::Normal), 0));
+        assert_eq!(state.current().logo.as_deref(), Some(&b"BM"[..]));
+
+        // an empty logo goes back to the default
+        assert!(state.apply(logo(b""), 0));
+        assert_eq!(state.current().logo, None);
+    }
+}
@@ -2,6 +2,44 @@
 
 use serde::{Deserialize, Serialize};
 
+pub mod content;
+pub mod saved;
+
+/// Size of the scratch buffer both ends use to send and receive a single frame.
+/// Large enough for an update carrying the longest text and logo.
+pub const MAX_FRAME_LEN: usize = 6144;
+
+/// Maximum length, in bytes, of the text carried by an [`OwnedUpdate`].
+/// Latin-1 characters take up to two bytes each in UTF-8.
+pub const MAX_TEXT_LEN: usize = 256;
+
+/// Maximum length, in bytes, of the logo BMP carried by an [`OwnedUpdate`].
+/// Fits a 1bpp image covering the whole 296x128 display, 5182 bytes.
+pub const MAX_LOGO_LEN: usize = 5248;
+
+/// First frame sent by the badge on a new connection so the server knows which
+/// badge it is talking to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Unique id of the badge, from its flash chip
+    pub badge_id: u64,
+    /// The display the badge draws on
+    pub panel: PanelInfo,
+}
+
+/// The badge's display as reported in [`Hello`], see `badge_draw::Panel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanelInfo {
+    /// Width in pixels, in the orientation the controller scans the panel
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// Colours the panel can show, see `badge_draw::Colors`
+    pub colors: u8,
+    /// Whether the panel supports partial refresh
+    pub partial_refresh: bool,
+}
+
 /// Request from the badge to the server
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum Request {
@@ -26,13 +64,55 @@ impl TryFrom<&[u8]> for Request {
     }
 }
 
+/// How urgently an update should be shown on the badge
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    /// Replaces the current content, newest message wins
+    #[default]
+    Normal,
+    /// Pre-empts the current content and is not displaced by normal messages
+    Urgent,
+}
+
+/// How the badge trades staying up to date for battery life, see `badge_power`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerPolicy {
+    /// Wi-Fi at full power and the server asked for updates every second
+    #[default]
+    AlwaysOn,
+    /// Wi-Fi power-save and the server asked less often while nobody is using the badge
+    Balanced,
+    /// Disconnect and sleep between syncs with the server, a button press wakes the badge
+    DeepSleep {
+        /// Seconds between syncs
+        interval_s: u32,
+    },
+}
+
 /// Response from the server to the device
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Update<'a> {
     /// Text to display
     pub text: Option<&'a str>,
     /// Frequency of the LED
     pub freq: Option<u32>,
+    /// Seconds the text stays on screen before the badge reverts to the previous content.
+    /// `None` means the text is permanent until the next update.
+    pub ttl: Option<u32>,
+    /// Priority of the text
+    pub priority: Priority,
+    /// Layout template used to draw the text, see `badge_draw::Template`
+    pub layout: Option<u8>,
+    /// 1bpp BMP drawn by layouts with a logo.  An empty logo reverts to the default.
+    pub logo: Option<&'a [u8]>,
+    /// How the display is mounted, see `badge_draw::Rotation`
+    pub rotation: Option<u8>,
+    /// How text too long for the layout is shown, see `badge_draw::TextMode`
+    pub mode: Option<u8>,
+    /// Colours and decoration of the layout, see `badge_draw::Theme`
+    pub theme: Option<u8>,
+    /// Power policy, kept until the server sends another
+    pub power: Option<PowerPolicy>,
 }
 impl Update<'_> {
     /// Serialize the update
@@ -48,6 +128,81 @@ impl<'a> TryFrom<&'a [u8]> for Update<'a> {
     }
 }
 
+/// An [`Update`] that owns its data so it can outlive the receive buffer.
+/// This allows updates to be queued in channels or persisted to flash without
+/// needing a global allocator.  It serializes to the same bytes as [`Update`].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedUpdate {
+    /// Text to display
+    pub text: Option<heapless::String<MAX_TEXT_LEN>>,
+    /// Frequency of the LED
+    pub freq: Option<u32>,
+    /// Seconds the text stays on screen, see [`Update::ttl`]
+    pub ttl: Option<u32>,
+    /// Priority of the text
+    pub priority: Priority,
+    /// Layout template used to draw the text
+    pub layout: Option<u8>,
+    /// Logo image, see [`Update::logo`]
+    pub logo: Option<heapless::Vec<u8, MAX_LOGO_LEN>>,
+    /// Display rotation, see [`Update::rotation`]
+    pub rotation: Option<u8>,
+    /// Text mode, see [`Update::mode`]
+    pub mode: Option<u8>,
+    /// Theme, see [`Update::theme`]
+    pub theme: Option<u8>,
+    /// Power policy, see [`Update::power`]
+    pub power: Option<PowerPolicy>,
+}
+impl OwnedUpdate {
+    /// Borrow this update as an [`Update`]
+    pub fn as_update(&self) -> Update<'_> {
+        Update {
+            text: self.text.as_ref().map(|text| text.as_str()),
+            freq: self.freq,
+            ttl: self.ttl,
+            priority: self.priority,
+            layout: self.layout,
+            logo: self.logo.as_deref(),
+            rotation: self.rotation,
+            mode: self.mode,
+            theme: self.theme,
+            power: self.power,
+        }
+    }
+}
+impl TryFrom<&Update<'_>> for OwnedUpdate {
+    type Error = &'static str;
+
+    fn try_from(value: &Update<'_>) -> Result<OwnedUpdate, Self::Error> {
+        let text = match value.text {
+            Some(text) => Some(heapless::String::try_from(text).map_err(|_| "text too long")?),
+            None => None,
+        };
+        let logo = match value.logo {
+            Some(logo) => Some(heapless::Vec::from_slice(logo).map_err(|_| "logo too large")?),
+            None => None,
+        };
+        Ok(OwnedUpdate {
+            text,
+            freq: value.freq,
+            ttl: value.ttl,
+            priority: value.priority,
+            layout: value.layout,
+            logo,
+            rotation: value.rotation,
+            mode: value.mode,
+            theme: value.theme,
+            power: value.power,
+        })
+    }
+}
+impl<'a> From<&'a OwnedUpdate> for Update<'a> {
+    fn from(value: &'a OwnedUpdate) -> Update<'a> {
+        value.as_update()
+    }
+}
+
 /// Unfortunately we need our own trait here because the traits between `embedded-io-async` and
 /// `tokio` aren't the same, so we define our framing and data sending traits here.
 #[allow(async_fn_in_trait)]
@@ -180,6 +335,14 @@ mod tests {
         let msg = Update {
             text: Some("Hello, World!"),
             freq: Some(10),
+            ttl: Some(30),
+            priority: Priority::Urgent,
+            layout: Some(1),
+            logo: Some(&[0x42, 0x4d, 0x00]),
+            rotation: Some(1),
+            mode: Some(2),
+            theme: Some(3),
+            power: Some(PowerPolicy::DeepSleep { interval_s: 300 }),
         };
         let buf = postcard::to_slice(&msg, &mut buf).unwrap();
         assert!(buf.len() > 0);
@@ -191,10 +354,7 @@ mod tests {
     #[test]
     fn test_empty_update() {
         let mut buf = [0u8; 64];
-        let msg = Update {
-            text: None,
-            freq: None,
-        };
+        let msg = Update::default();
         let buf = postcard::to_slice(&msg, &mut buf).unwrap();
         assert!(buf.len() > 0);
 
@@ -213,6 +373,7 @@ mod tests {
         let update = Update {
             text: Some("Hello, World!"),
             freq: Some(10),
+            ..Default::default()
         };
         let mut buf = [0u8; 64];
         let buf = update.serialize(&mut buf).unwrap();
@@ -225,4 +386,105 @@ mod tests {
         let buf = update.serialize(&mut buf);
         assert!(buf.is_err());
     }
+
+    #[test]
+    fn test_owned_update() {
+        let update = Update {
+            text: Some("Hello, World!"),
+            freq: Some(10),
+            ttl: Some(60),
+            priority: Priority::Urgent,
+            layout: Some(2),
+            logo: Some(&[0x42, 0x4d, 0x00]),
+            rotation: Some(3),
+            mode: Some(1),
+            theme: Some(2),
+            power: Some(PowerPolicy::Balanced),
+        };
+        let owned = OwnedUpdate::try_from(&update).unwrap();
+        assert_eq!(owned.as_update(), update);
+
+        // owned and borrowed forms are interchangeable on the wire
+        let mut buf = [0u8; 64];
+        let bytes = postcard::to_slice(&owned, &mut buf).unwrap();
+        let update2 = Update::try_from(&*bytes).unwrap();
+        assert_eq!(update2, update);
+        let owned2: OwnedUpdate =
+            postcard::from_bytes(update.serialize(&mut [0u8; 64]).unwrap()).unwrap();
+        assert_eq!(owned2, owned);
+
+        let empty = OwnedUpdate::try_from(&Update::default()).unwrap();
+        assert_eq!(empty, OwnedUpdate::default());
+    }
+
+    #[test]
+    fn test_owned_update_text_too_long() {
+        let long = [b'a'; MAX_TEXT_LEN + 1];
+        let update = Update {
+            text: Some(core::str::from_utf8(&long).unwrap()),
+            ..Default::default()
+        };
+        assert!(OwnedUpdate::try_from(&update).is_err());
+
+        let update = Update {
+            text: Some(core::str::from_utf8(&long[..MAX_TEXT_LEN]).unwrap()),
+            ..Default::default()
+        };
+        assert!(OwnedUpdate::try_from(&update).is_ok());
+    }
+
+    #[test]
+    fn test_owned_update_logo_too_large() {
+        let logo = [0u8; MAX_LOGO_LEN + 1];
+        let update = Update {
+            logo: Some(&logo),
+            ..Default::default()
+        };
+        assert!(OwnedUpdate::try_from(&update).is_err());
+
+        let update = Update {
+            logo: Some(&logo[..MAX_LOGO_LEN]),
+            ..Default::default()
+        };
+        assert!(OwnedUpdate::try_from(&update).is_ok());
+    }
+
+    #[test]
+    fn test_largest_update_fits_frame() {
+        let text = [b'a'; MAX_TEXT_LEN];
+        let logo = [0xffu8; MAX_LOGO_LEN];
+        let update = Update {
+            text: Some(core::str::from_utf8(&text).unwrap()),
+            freq: Some(u32::MAX),
+            ttl: Some(u32::MAX),
+            priority: Priority::Urgent,
+            layout: Some(u8::MAX),
+            logo: Some(&logo),
+            rotation: Some(u8::MAX),
+            mode: Some(u8::MAX),
+            theme: Some(u8::MAX),
+            power: Some(PowerPolicy::DeepSleep {
+                interval_s: u32::MAX,
+            }),
+        };
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let bytes = update.serialize(&mut buf).unwrap();
+        assert_eq!(Update::try_from(bytes).unwrap(), update);
+    }
+
+    #[test]
+    fn test_hello_serialize() {
+        let hello = Hello {
+            badge_id: 0xe660_5838_8347_2c2f,
+            panel: PanelInfo {
+                width: 296,
+                height: 128,
+                colors: 0,
+                partial_refresh: true,
+            },
+        };
+        let mut buf = [0u8; 24];
+        let bytes = postcard::to_slice(&hello, &mut buf).unwrap();
+        assert_eq!(postcard::from_bytes::<Hello>(bytes).unwrap(), hello);
+    }
 }
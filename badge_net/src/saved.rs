@@ -0,0 +1,193 @@
+//! The content the badge keeps in flash so it can show it again after a restart,
+//! before it has reached the server.
+//!
+//! Content is stored as key/value records.  The record at [`CONTENT_KEY`] holds
+//! the text, LED frequency and drawing settings.  A logo is too large for a
+//! single record so it is split into chunks stored from [`LOGO_KEY`] on, see
+//! [`logo_chunks`].
+//!
+//! There are two banks of logo chunks.  A new logo goes in the bank the content
+//! record doesn't point at, and only replaces the old one once the record is
+//! written, so a save that fails part way still leaves a whole logo.
+
+use core::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{OwnedUpdate, MAX_LOGO_LEN, MAX_TEXT_LEN};
+
+/// Key of the record holding everything but the logo
+pub const CONTENT_KEY: u8 = 0;
+/// Key of the first logo chunk
+pub const LOGO_KEY: u8 = 1;
+/// Largest logo chunk, small enough that a record fits a 4 KiB flash sector
+/// with room for smaller records
+pub const LOGO_CHUNK_LEN: usize = 2048;
+/// Chunks in each bank, enough for the largest logo
+pub const LOGO_BANK_CHUNKS: u8 = MAX_LOGO_LEN.div_ceil(LOGO_CHUNK_LEN) as u8;
+/// Banks of logo chunks
+pub const LOGO_BANKS: u8 = 2;
+/// Largest encoded content record
+pub const MAX_CONTENT_LEN: usize = MAX_TEXT_LEN + 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record<'a> {
+    #[serde(borrow)]
+    text: Option<&'a str>,
+    freq: Option<u32>,
+    layout: Option<u8>,
+    rotation: Option<u8>,
+    mode: Option<u8>,
+    theme: Option<u8>,
+    /// Bank holding the logo's chunks
+    logo_bank: u8,
+    /// Length of the logo stored in chunks
+    logo_len: Option<u16>,
+}
+
+/// Write the content record for `update` into `buf`, returning the bytes
+/// written.  The logo is only recorded by its length and the bank its chunks
+/// are in.
+pub fn encode_content<'a>(
+    update: &OwnedUpdate,
+    logo_bank: u8,
+    buf: &'a mut [u8],
+) -> Result<&'a mut [u8], &'static str> {
+    if logo_bank >= LOGO_BANKS {
+        return Err("no such logo bank");
+    }
+    let record = Record {
+        text: update.text.as_deref(),
+        freq: update.freq,
+        layout: update.layout,
+        rotation: update.rotation,
+        mode: update.mode,
+        theme: update.theme,
+        logo_bank,
+        logo_len: update.logo.as_ref().map(|logo| logo.len() as u16),
+    };
+    postcard::to_slice(&record, buf).map_err(|_| "saved content too large")
+}
+
+/// Read a content record written by [`encode_content`], returning the update
+/// and the logo bank.  If there is a logo the update has one of the stored
+/// length filled with zeros, to be read from the chunks given by [`logo_chunks`].
+pub fn decode_content(bytes: &[u8]) -> Result<(OwnedUpdate, u8), &'static str> {
+    let record: Record = postcard::from_bytes(bytes).map_err(|_| "invalid saved content")?;
+    if record.logo_bank >= LOGO_BANKS {
+        return Err("invalid logo bank");
+    }
+    let logo = match record.logo_len {
+        Some(len) if usize::from(len) > MAX_LOGO_LEN => return Err("saved logo too long"),
+        Some(len) => {
+            let mut logo = heapless::Vec::new();
+            logo.resize(usize::from(len), 0)
+                .expect("length was checked");
+            Some(logo)
+        }
+        None => None,
+    };
+    let update = OwnedUpdate {
+        text: record
+            .text
+            .map(heapless::String::try_from)
+            .transpose()
+            .map_err(|_| "saved text too long")?,
+        freq: record.freq,
+        layout: record.layout,
+        rotation: record.rotation,
+        mode: record.mode,
+        theme: record.theme,
+        logo,
+        ..Default::default()
+    };
+    Ok((update, record.logo_bank))
+}
+
+/// The key of each chunk of a logo of `len` bytes in `bank` and the part of
+/// the logo it holds
+pub fn logo_chunks(bank: u8, len: usize) -> impl Iterator<Item = (u8, Range<usize>)> {
+    (0..len)
+        .step_by(LOGO_CHUNK_LEN)
+        .zip(LOGO_KEY + bank * LOGO_BANK_CHUNKS..)
+        .map(move |(start, key)| (key, start..len.min(start + LOGO_CHUNK_LEN)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let update = OwnedUpdate {
+            text: Some(heapless::String::try_from("Ferris\nRustacean").unwrap()),
+            freq: Some(250),
+            layout: Some(2),
+            rotation: Some(1),
+            mode: Some(1),
+            theme: Some(3),
+            logo: Some(heapless::Vec::from_slice(&[7; 100]).unwrap()),
+            ..Default::default()
+        };
+        let mut buf = [0u8; MAX_CONTENT_LEN];
+        let bytes = encode_content(&update, 1, &mut buf).unwrap();
+        let (mut saved, bank) = decode_content(bytes).unwrap();
+        assert_eq!(bank, 1);
+        // the logo comes back empty, to be filled from its chunks
+        assert_eq!(saved.logo.as_deref(), Some(&[0; 100][..]));
+        saved.logo = update.logo.clone();
+        assert_eq!(saved, update);
+
+        let bytes = encode_content(&OwnedUpdate::default(), 0, &mut buf).unwrap();
+        assert_eq!(decode_content(bytes), Ok((OwnedUpdate::default(), 0)));
+        assert!(encode_content(&OwnedUpdate::default(), LOGO_BANKS, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_longest_text_fits() {
+        let mut text = heapless::String::new();
+        while text.push('\u{e9}').is_ok() {}
+        let update = OwnedUpdate {
+            text: Some(text),
+            freq: Some(u32::MAX),
+            layout: Some(u8::MAX),
+            rotation: Some(u8::MAX),
+            mode: Some(u8::MAX),
+            theme: Some(u8::MAX),
+            logo: Some(heapless::Vec::from_slice(&[0; MAX_LOGO_LEN]).unwrap()),
+            ..Default::default()
+        };
+        let mut buf = [0u8; MAX_CONTENT_LEN];
+        assert!(encode_content(&update, LOGO_BANKS - 1, &mut buf).is_ok());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(decode_content(&[]).is_err());
+        // a logo longer than an update can carry
+        let mut buf = [0u8; MAX_CONTENT_LEN];
+        let bytes = encode_content(&OwnedUpdate::default(), 0, &mut buf).unwrap();
+        let len = bytes.len();
+        let mut record: heapless::Vec<u8, MAX_CONTENT_LEN> =
+            heapless::Vec::from_slice(&buf[..len - 1]).unwrap();
+        record.extend_from_slice(&[1, 0xff, 0xff, 0x03]).unwrap();
+        assert_eq!(decode_content(&record), Err("saved logo too long"));
+    }
+
+    #[test]
+    fn test_logo_chunks() {
+        let chunks: heapless::Vec<_, 4> = logo_chunks(0, MAX_LOGO_LEN).collect();
+        assert_eq!(
+            chunks,
+            [(1, 0..2048), (2, 2048..4096), (3, 4096..MAX_LOGO_LEN)]
+        );
+        // the second bank follows the first
+        let chunks: heapless::Vec<_, 4> = logo_chunks(1, MAX_LOGO_LEN).collect();
+        assert_eq!(
+            chunks,
+            [(4, 0..2048), (5, 2048..4096), (6, 4096..MAX_LOGO_LEN)]
+        );
+        assert_eq!(logo_chunks(0, 2048).count(), 1);
+        assert_eq!(logo_chunks(1, 0).count(), 0);
+    }
+}
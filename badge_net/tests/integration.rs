@@ -53,6 +53,7 @@ async fn test_framing() {
     let update = Update {
         text: Some("Hello World"),
         freq: Some(123),
+        ..Default::default()
     };
 
     write_frame(&mut stream, &update, buf.as_mut_slice())
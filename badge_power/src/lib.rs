@@ -0,0 +1,191 @@
+//! Decides how hard the badge works to stay up to date, from the
+//! [`PowerPolicy`] the server sets.
+//!
+//! After every sync with the server the firmware asks [`PowerManager::next`]
+//! whether to keep the connection and ask again, or to leave the network and
+//! sleep.  A button press wakes the badge and keeps it responsive for a while
+//! so the wearer can use the apps.  Times are milliseconds from any clock that
+//! keeps running while the badge sleeps.
+
+#![no_std]
+
+pub use badge_net::PowerPolicy;
+
+/// How long the badge counts as in use after a button press
+pub const ACTIVE_MS: u64 = 30_000;
+
+/// Time between syncs with the balanced policy while the badge isn't in use
+pub const BALANCED_POLL_MS: u64 = 15_000;
+
+/// Shortest deep sleep, rejoining the network costs more than a shorter sleep saves
+pub const MIN_SLEEP_S: u32 = 30;
+
+/// How hard the Wi-Fi chip works while connected, see `cyw43::PowerManagementMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiPower {
+    /// No power saving, the lowest latency
+    Full,
+    /// Sleep between beacons
+    PowerSave,
+    /// Sleep through most beacons, only for short connections
+    SuperSave,
+}
+
+/// What to do after a sync with the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    /// Keep the connection and ask again after this many milliseconds.  The
+    /// server paces its replies to about one a second.
+    Poll(u64),
+    /// Close the connection, leave the network and sync again after this many milliseconds
+    Sleep(u64),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PowerManager {
+    policy: PowerPolicy,
+    /// When the badge stops counting as in use
+    active_until_ms: Option<u64>,
+}
+impl PowerManager {
+    pub const fn new() -> Self {
+        Self {
+            policy: PowerPolicy::AlwaysOn,
+            active_until_ms: None,
+        }
+    }
+
+    pub fn policy(&self) -> PowerPolicy {
+        self.policy
+    }
+
+    /// Use the policy from the server, returns whether it changed
+    pub fn set_policy(&mut self, policy: PowerPolicy) -> bool {
+        let changed = policy != self.policy;
+        self.policy = policy;
+        changed
+    }
+
+    pub fn wifi_power(&self) -> WifiPower {
+        match self.policy {
+            PowerPolicy::AlwaysOn => WifiPower::Full,
+            PowerPolicy::Balanced => WifiPower::PowerSave,
+            PowerPolicy::DeepSleep { .. } => WifiPower::SuperSave,
+        }
+    }
+
+    /// A button was pressed, or woke the badge
+    pub fn on_button(&mut self, now_ms: u64) {
+        self.active_until_ms = Some(now_ms.saturating_add(ACTIVE_MS));
+    }
+
+    /// Whether a button was pressed recently
+    pub fn is_active(&self, now_ms: u64) -> bool {
+        self.active_until_ms.is_some_and(|until| now_ms < until)
+    }
+
+    /// What to do after a sync.  `frame_interval_ms` is how often paged or
+    /// scrolling text on screen moves on, `None` for text that stays still.
+    /// Text stops moving while the badge sleeps.  `expires_at_ms` is when a
+    /// temporary message on screen expires, the badge is back by then to take it down.
+    pub fn next(
+        &self,
+        now_ms: u64,
+        frame_interval_ms: Option<u64>,
+        expires_at_ms: Option<u64>,
+    ) -> Next {
+        if self.is_active(now_ms) {
+            return Next::Poll(0);
+        }
+        let until_expiry = |ms: u64| {
+            expires_at_ms.map_or(ms, |expires_at_ms| {
+                ms.min(expires_at_ms.saturating_sub(now_ms))
+            })
+        };
+        match self.policy {
+            PowerPolicy::AlwaysOn => Next::Poll(0),
+            PowerPolicy::Balanced => Next::Poll(until_expiry(
+                frame_interval_ms
+                    .map_or(BALANCED_POLL_MS, |interval| interval.min(BALANCED_POLL_MS)),
+            )),
+            PowerPolicy::DeepSleep { interval_s } => {
+                Next::Sleep(until_expiry(u64::from(interval_s.max(MIN_SLEEP_S)) * 1000))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        let mut power = PowerManager::new();
+        assert_eq!(power.wifi_power(), WifiPower::Full);
+        assert_eq!(power.next(0, None, None), Next::Poll(0));
+
+        assert!(power.set_policy(PowerPolicy::Balanced));
+        assert!(!power.set_policy(PowerPolicy::Balanced));
+        assert_eq!(power.wifi_power(), WifiPower::PowerSave);
+        assert_eq!(power.next(0, None, None), Next::Poll(BALANCED_POLL_MS));
+        // moving text still moves on time
+        assert_eq!(power.next(0, Some(4000), None), Next::Poll(4000));
+        assert_eq!(
+            power.next(0, Some(60_000), None),
+            Next::Poll(BALANCED_POLL_MS)
+        );
+
+        power.set_policy(PowerPolicy::DeepSleep { interval_s: 600 });
+        assert_eq!(power.wifi_power(), WifiPower::SuperSave);
+        assert_eq!(power.next(0, None, None), Next::Sleep(600_000));
+        assert_eq!(power.next(0, Some(4000), None), Next::Sleep(600_000));
+        power.set_policy(PowerPolicy::DeepSleep { interval_s: 1 });
+        assert_eq!(
+            power.next(0, None, None),
+            Next::Sleep(u64::from(MIN_SLEEP_S) * 1000)
+        );
+    }
+
+    #[test]
+    fn test_back_when_message_expires() {
+        let mut power = PowerManager::new();
+        assert_eq!(power.next(0, None, Some(60_000)), Next::Poll(0));
+        power.set_policy(PowerPolicy::Balanced);
+        assert_eq!(power.next(0, None, Some(4000)), Next::Poll(4000));
+        assert_eq!(
+            power.next(0, None, Some(60_000)),
+            Next::Poll(BALANCED_POLL_MS)
+        );
+
+        power.set_policy(PowerPolicy::DeepSleep { interval_s: 600 });
+        assert_eq!(power.next(10_000, None, Some(70_000)), Next::Sleep(60_000));
+        assert_eq!(
+            power.next(10_000, None, Some(10_000_000)),
+            Next::Sleep(600_000)
+        );
+    }
+
+    #[test]
+    fn test_button_keeps_badge_awake() {
+        let mut power = PowerManager::new();
+        power.set_policy(PowerPolicy::DeepSleep { interval_s: 300 });
+        assert!(!power.is_active(0));
+
+        power.on_button(10_000);
+        assert!(power.is_active(10_000));
+        assert_eq!(power.next(10_000, None, None), Next::Poll(0));
+        assert_eq!(
+            power.next(10_000 + ACTIVE_MS - 1, None, None),
+            Next::Poll(0)
+        );
+        assert_eq!(
+            power.next(10_000 + ACTIVE_MS, None, None),
+            Next::Sleep(300_000)
+        );
+
+        // a later press starts the wait again
+        power.on_button(20_000);
+        assert!(power.is_active(10_000 + ACTIVE_MS));
+    }
+}
@@ -53,14 +53,13 @@ pub fn main_js() -> Result<(), JsValue> {
     "#,
     );
 
-    const WIDTH: u32 = 296;
-    const HEIGHT: u32 = 128;
+    let panel = badge_draw::Panel::BADGER_2040;
 
     let output_settings = OutputSettingsBuilder::new()
         .scale(1)
         .pixel_spacing(1)
         .build();
-    let mut text_display = WebSimulatorDisplay::new((WIDTH, HEIGHT), &output_settings, None);
+    let mut text_display = WebSimulatorDisplay::new(panel.size.into(), &output_settings, None);
     let mut img_display = WebSimulatorDisplay::new(
         (128, 128),
         &output_settings,
@@ -4,7 +4,22 @@ use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
 
-use crate::format_text_for_badge;
+use badge_draw::{Panel, Rotation, Stage, Status, Template, TextMode, Theme};
+use embedded_graphics::geometry::Size;
+
+use crate::picture::{Dither, Region};
+use crate::{format_text_for_badge, MAX_PUBLIC_TTL_SECS};
+
+/// The panel the page previews until the badge reports its own, the one on the Badger 2040 W
+const PANEL: Panel = Panel::BADGER_2040;
+
+/// Power policies offered on the page, see `update_power` for what each one is
+const POWER_OPTIONS: [&str; 4] = [
+    "Always on",
+    "Balanced",
+    "Sync every 5 minutes",
+    "Sync every 15 minutes",
+];
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -38,6 +53,7 @@ pub fn App() -> impl IntoView {
 fn HomePage() -> impl IntoView {
     view! {
         <Badge/>
+        <StatusPreview/>
     }
 }
 
@@ -57,46 +73,163 @@ fn Flash(rate: ReadSignal<u32>) -> impl IntoView {
 }
 
 #[component]
-fn Screen(text: ReadSignal<String>) -> impl IntoView {
+fn Screen(
+    text: ReadSignal<String>,
+    layout: ReadSignal<Template>,
+    /// 1bpp BMP shown as the logo, Ferris if `None`
+    logo: ReadSignal<Option<Vec<u8>>>,
+    /// How the badge is worn.  The display is drawn as the badge draws it and
+    /// turned back so the preview reads the right way up.
+    rotation: ReadSignal<Rotation>,
+    /// How text too long for the layout is shown, animated as on the badge
+    mode: ReadSignal<TextMode>,
+    /// Colours and decoration of the layout
+    theme: ReadSignal<Theme>,
+    /// The panel of the badge
+    panel: ReadSignal<Panel>,
+) -> impl IntoView {
     let screen_container = create_node_ref::<leptos::html::Div>();
     let display = Rc::new(RefCell::new(None));
 
-    // Effect to construct the display
-    {
-        let display = display.clone();
-        create_effect(move |_| {
-            use embedded_graphics_web_simulator::{
-                display::WebSimulatorDisplay, output_settings::OutputSettingsBuilder,
-            };
+    // Step through the frames at the badge's pace, starting again from the first
+    // whenever the content changes like the badge does
+    let (frame, set_frame) = create_signal(0u32);
+    create_effect(move |_| {
+        text.track();
+        layout.track();
+        theme.track();
+        set_frame(0);
+        mode().frame_interval_ms().map(|interval| {
+            gloo_timers::callback::Interval::new(interval as u32, move || {
+                set_frame.update(|frame| *frame = frame.wrapping_add(1))
+            })
+        })
+    });
+
+    create_effect(move |_| {
+        use embedded_graphics_web_simulator::{
+            display::WebSimulatorDisplay, output_settings::OutputSettingsBuilder,
+        };
 
-            let sc = screen_container.get().unwrap();
-            const WIDTH: u32 = 296;
-            const HEIGHT: u32 = 128;
+        let size = panel.get().size;
+        let text = text.get();
+        let text = format_text_for_badge(text);
+        let layout = layout.get();
+        let rotation = rotation.get();
+        let mode = mode.get();
+        let theme = theme.get();
+        let frame = frame.get();
+        let logo = logo.get();
+        let logo = logo
+            .as_deref()
+            .and_then(|bmp| badge_draw::Logo::from_bmp(bmp).ok());
+        let Some(sc) = screen_container.get() else {
+            return;
+        };
+        let mut display = display.borrow_mut();
+        // A different panel needs a canvas of its own size
+        if !matches!(&*display, Some((built, _)) if *built == size) {
+            sc.set_inner_html("");
             let output_settings = OutputSettingsBuilder::new()
                 .scale(1)
                 .pixel_spacing(0)
                 .build();
-            let mut text_display =
-                WebSimulatorDisplay::new((WIDTH, HEIGHT), &output_settings, Some(&sc));
-
-            badge_draw::draw_display(&mut text_display, "INIT").expect("could not draw display");
+            let canvas = WebSimulatorDisplay::new(size.into(), &output_settings, Some(&sc));
+            *display = Some((size, canvas));
+        }
+        if let Some((_, text_display)) = display.as_mut() {
+            let mut rotated = badge_draw::Rotated::new(text_display, rotation);
+            badge_draw::draw_template_frame(&mut rotated, layout, theme, &text, logo, mode, frame)
+                .expect("could not draw display");
             text_display.flush().expect("could not flush buffer");
+        }
+    });
 
-            display.replace(Some(text_display));
-        });
+    // Portrait previews are turned within the landscape footprint, so make room
+    // for the difference between the display's width and height
+    let transform = move || {
+        let rotation = rotation();
+        let size = panel().size;
+        let margin = if rotation.is_portrait() {
+            size.width.abs_diff(size.height) / 2
+        } else {
+            0
+        };
+        format!(
+            "transform: rotate(-{}deg); margin: {margin}px -{margin}px; width: fit-content",
+            rotation.degrees()
+        )
+    };
+
+    view! {
+        <div _ref=screen_container id="custom-container" class="badge" style=transform>
+        </div>
     }
+}
+
+/// Shows the screens the badge displays while it connects
+#[component]
+fn StatusPreview() -> impl IntoView {
+    let (stage, set_stage) = create_signal(Stage::Wifi);
+    let (failed, set_failed) = create_signal(false);
+    let screen_container = create_node_ref::<leptos::html::Div>();
+    let display = RefCell::new(None);
 
+    // Construct the display once the container exists and redraw on every change
     create_effect(move |_| {
-        let text = text.get();
-        let text = format_text_for_badge(text);
-        if let Some(text_display) = display.borrow_mut().as_mut() {
-            badge_draw::draw_display(text_display, &text).expect("could not draw display");
-            text_display.flush().expect("could not flush buffer");
+        use embedded_graphics_web_simulator::{
+            display::WebSimulatorDisplay, output_settings::OutputSettingsBuilder,
+        };
+
+        let mut status = Status::new(stage.get(), "dev.aughey.com");
+        if failed.get() {
+            status = status.with_error("Example error");
         }
+        let Some(sc) = screen_container.get() else {
+            return;
+        };
+        let mut display = display.borrow_mut();
+        let status_display = display.get_or_insert_with(|| {
+            let output_settings = OutputSettingsBuilder::new()
+                .scale(1)
+                .pixel_spacing(0)
+                .build();
+            WebSimulatorDisplay::new(PANEL.size.into(), &output_settings, Some(&sc))
+        });
+        badge_draw::draw_status(status_display, &status).expect("could not draw status");
+        status_display.flush().expect("could not flush buffer");
     });
 
+    let stage_options = Stage::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option selected=move|| v == stage()>
+                    {v.label()}
+                </option>
+            }
+        })
+        .collect_view();
+
     view! {
-        <div _ref=screen_container id="custom-container" class="badge">
+        <div>
+        <h2>"Connection status"</h2>
+        <div _ref=screen_container class="badge">
+        </div>
+        <div>Stage
+         <select on:change=move |ev| {
+        let label = event_target_value(&ev);
+        if let Some(stage) = Stage::ALL.into_iter().find(|s| s.label() == label) {
+            set_stage(stage);
+        }
+    }>
+        {stage_options}
+    </select>
+    <label>
+        <input type="checkbox" prop:checked=failed on:change=move |ev| set_failed(event_target_checked(&ev))/>
+        Failed
+    </label>
+    </div>
         </div>
     }
 }
@@ -105,8 +238,32 @@ fn Screen(text: ReadSignal<String>) -> impl IntoView {
 fn Badge() -> impl IntoView {
     let options = [50, 100, 250, 500, 1000];
     let (value, set_value) = create_signal(1000u32);
+    let ttl_options = [None, Some(30), Some(60), Some(MAX_PUBLIC_TTL_SECS)];
+    let (ttl, set_ttl) = create_signal(None::<u32>);
+    let (urgent, set_urgent) = create_signal(false);
+    let (layout, set_layout) = create_signal(Template::default());
+    let (rotation, set_rotation) = create_signal(Rotation::default());
+    let (mode, set_mode) = create_signal(TextMode::default());
+    let (theme, set_theme) = create_signal(Theme::default());
     let (messages, set_messages) = create_signal(Vec::new());
     let (badge_text, set_badge_text) = create_signal("Enter Text Here".to_string());
+    let (logo, set_logo) = create_signal(None::<Vec<u8>>);
+    let (token, set_token) = create_signal(String::new());
+    let (badge_id, set_badge_id) = create_signal(String::new());
+    let (panel, set_panel) = create_signal(PANEL);
+
+    // Preview and fit the text for the panel the badge reported, mounted the
+    // way the owner set
+    create_effect(move |_| {
+        let badge_id = badge_id();
+        spawn_local(async move {
+            if let Ok((width, height, rotation)) = badge_display(badge_id).await {
+                let size = Size::new(width, height);
+                set_panel(Panel::new(size, PANEL.colors, PANEL.partial_refresh));
+                set_rotation(Rotation::try_from(rotation).unwrap_or_default());
+            }
+        });
+    });
 
     // Text areas are finicky so we need to use a ref to get the value
     // and this get_input helper function to extract the value.
@@ -121,12 +278,43 @@ fn Badge() -> impl IntoView {
         }
     };
 
+    // Check the text against the layout the badge will draw so the sender can
+    // see whether any of it will be cut off
+    let fit_message = move || {
+        let sanitized = badge_draw::sanitize(&badge_text()).collect::<String>();
+        let text = format_text_for_badge(&sanitized);
+        let too_long = sanitized.chars().count() - text.chars().count();
+        let logo = logo();
+        let logo = logo
+            .as_deref()
+            .and_then(|bmp| badge_draw::Logo::from_bmp(bmp).ok());
+        let size = panel().drawing_size(rotation());
+        let fit = badge_draw::analyze_template(size, layout(), theme(), &text, logo);
+        // Pages and marquees show all of the text, over several frames
+        let frames =
+            badge_draw::template_frame_count(size, layout(), theme(), &text, logo, mode());
+        let overflow = if frames > 1 { 0 } else { fit.overflow_chars() };
+        match (overflow + too_long, frames) {
+            (0, 1) => "Fits".to_string(),
+            (0, frames) => format!("Fits in {frames} frames"),
+            (1, _) => "Overflows by 1 character".to_string(),
+            (overflow, _) => format!("Overflows by {overflow} characters"),
+        }
+    };
+
     // Fn to send the text to the badge
     let send_text_to_badge = move || {
         let text = badge_text();
         let freq = value();
+        let ttl = ttl();
+        let urgent = urgent();
+        let layout = layout();
+        let mode = mode();
+        let theme = theme();
         spawn_local(async move {
-            update_text(text.clone()).await.unwrap();
+            update_text(text.clone(), ttl, urgent, layout.id(), mode.id(), theme.id())
+                .await
+                .unwrap();
             update_frequency(freq).await.unwrap();
             set_messages.update(|m| {
                 m.push(format!("Sent text to the server: {}", text));
@@ -148,10 +336,61 @@ fn Badge() -> impl IntoView {
         })
         .collect_view();
 
+    let ttl_options = ttl_options
+        .into_iter()
+        .map(|v| {
+            let label = match v {
+                Some(secs) => format!("{secs} seconds"),
+                None => "Until replaced".to_string(),
+            };
+            view! {
+                <option value=v.map(|v| v.to_string()).unwrap_or_default() selected=move|| v == ttl()>
+                    {label}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let layout_options = Template::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == layout()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let mode_options = TextMode::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == mode()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let theme_options = Theme::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == theme()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
     view! {
         <div>
         <h1>"Badge"</h1>
-        <Screen text=badge_text/>
+        <div>Badge id
+        <input type="text" placeholder="hex id" prop:value=badge_id on:input=move |ev| set_badge_id(event_target_value(&ev))/>
+        </div>
+        <Screen text=badge_text layout=layout logo=logo rotation=rotation mode=mode theme=theme panel=panel/>
         <Flash rate=value/>
         <textarea _ref=input_ref
         on:input=move |_| {
@@ -159,6 +398,43 @@ fn Badge() -> impl IntoView {
         }>
         {badge_text.get_untracked()}
         </textarea>
+        <div class="fit">{fit_message}</div>
+        <div>Layout
+         <select on:change=move |ev| {
+        let layout = event_target_value(&ev)
+            .parse()
+            .ok()
+            .and_then(|id: u8| Template::try_from(id).ok())
+            .unwrap_or_default();
+        set_layout(layout);
+    }>
+        {layout_options}
+    </select>
+    </div>
+        <div>Theme
+         <select on:change=move |ev| {
+        let theme = event_target_value(&ev)
+            .parse()
+            .ok()
+            .and_then(|id: u8| Theme::try_from(id).ok())
+            .unwrap_or_default();
+        set_theme(theme);
+    }>
+        {theme_options}
+    </select>
+    </div>
+        <div>Long text
+         <select on:change=move |ev| {
+        let mode = event_target_value(&ev)
+            .parse()
+            .ok()
+            .and_then(|id: u8| TextMode::try_from(id).ok())
+            .unwrap_or_default();
+        set_mode(mode);
+    }>
+        {mode_options}
+    </select>
+    </div>
         <div>LED Flash Rate (ms)
          <select on:change=move |ev| {
         let new_value = event_target_value(&ev).parse().unwrap();
@@ -166,6 +442,17 @@ fn Badge() -> impl IntoView {
     }>
         {options}
     </select>
+    </div>
+        <div>Display for
+         <select on:change=move |ev| {
+        set_ttl(event_target_value(&ev).parse().ok());
+    }>
+        {ttl_options}
+    </select>
+    <label>
+        <input type="checkbox" prop:checked=urgent on:change=move |ev| set_urgent(event_target_checked(&ev))/>
+        Urgent
+    </label>
     </div>
         <button on:click=move |_| send_text_to_badge()>Send this state to Badge</button>
         <div>
@@ -177,9 +464,259 @@ fn Badge() -> impl IntoView {
             <li>{m}</li>
         }).collect_view()}
         </ul>
+        <Picture set_logo=set_logo set_layout=set_layout token=token badge_id=badge_id rotation=rotation/>
+        <Owner token=token set_token=set_token rotation=rotation set_rotation=set_rotation/>
     }
 }
 
+/// Settings only the badge owner may change, each sent as soon as it changes
+#[component]
+fn Owner(
+    token: ReadSignal<String>,
+    set_token: WriteSignal<String>,
+    rotation: ReadSignal<Rotation>,
+    set_rotation: WriteSignal<Rotation>,
+) -> impl IntoView {
+    let (power, set_power) = create_signal(0usize);
+    let (message, set_message) = create_signal(String::new());
+
+    let rotation_options = Rotation::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == rotation()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let power_options = POWER_OPTIONS
+        .into_iter()
+        .enumerate()
+        .map(|(v, name)| {
+            view! {
+                <option value=v selected=move|| v == power()>
+                    {name}
+                </option>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div>
+        <h2>"Owner"</h2>
+        <div>Owner token
+        <input type="password" prop:value=token on:input=move |ev| set_token(event_target_value(&ev))/>
+        </div>
+        <div>Rotation
+         <select on:change=move |ev| {
+        let rotation = event_target_value(&ev)
+            .parse()
+            .ok()
+            .and_then(|id: u8| Rotation::try_from(id).ok())
+            .unwrap_or_default();
+        set_rotation(rotation);
+        let token = token.get_untracked();
+        spawn_local(async move {
+            match update_rotation(token, rotation.id()).await {
+                Ok(m) => set_message(m),
+                Err(e) => set_message(format!("Could not change the rotation: {e}")),
+            }
+        });
+    }>
+        {rotation_options}
+    </select>
+    </div>
+        <div>Power
+         <select on:change=move |ev| {
+        let power = event_target_value(&ev)
+            .parse()
+            .ok()
+            .filter(|&v: &usize| v < POWER_OPTIONS.len())
+            .unwrap_or_default();
+        set_power(power);
+        let token = token.get_untracked();
+        spawn_local(async move {
+            match update_power(token, power).await {
+                Ok(m) => set_message(m),
+                Err(e) => set_message(format!("Could not change the power policy: {e}")),
+            }
+        });
+    }>
+        {power_options}
+    </select>
+    </div>
+        <div>{message}</div>
+        </div>
+    }
+}
+
+/// Upload a photo, preview it converted to black and white and send it to a badge.
+/// Both need the owner token.
+#[component]
+fn Picture(
+    set_logo: WriteSignal<Option<Vec<u8>>>,
+    set_layout: WriteSignal<Template>,
+    token: ReadSignal<String>,
+    /// The badge the picture is made for and sent to
+    badge_id: ReadSignal<String>,
+    /// How the badge is mounted, previews are made for it
+    rotation: ReadSignal<Rotation>,
+) -> impl IntoView {
+    let (picture, set_picture) = create_signal(None::<Vec<u8>>);
+    let (dither, set_dither) = create_signal(Dither::default());
+    let (region, set_region) = create_signal(Region::default());
+    let (message, set_message) = create_signal(String::new());
+
+    // Convert the picture on the server whenever it, the options or the badge change
+    create_effect(move |_| {
+        let Some(data) = picture.get() else {
+            return;
+        };
+        let (dither, region) = (dither.get(), region.get());
+        let (badge_id, rotation) = (badge_id.get(), rotation.get());
+        let token = token.get();
+        if token.is_empty() {
+            set_message("Enter the owner token to convert pictures".to_string());
+            return;
+        }
+        spawn_local(async move {
+            let preview = Upload::Preview {
+                badge_id: &badge_id,
+                rotation,
+            };
+            match upload_picture(preview, &token, data, dither, region).await {
+                Ok(bmp) => {
+                    set_logo(Some(bmp));
+                    set_layout(region.template());
+                    set_message(String::new());
+                }
+                Err(e) => set_message(format!("Could not convert the picture: {e}")),
+            }
+        });
+    });
+
+    let send_to_badge = move || {
+        let Some(data) = picture.get_untracked() else {
+            set_message("Choose a picture first".to_string());
+            return;
+        };
+        let badge_id = badge_id.get_untracked();
+        let (dither, region) = (dither.get_untracked(), region.get_untracked());
+        let token = token.get_untracked();
+        spawn_local(async move {
+            let send = Upload::Send { badge_id: &badge_id };
+            match upload_picture(send, &token, data, dither, region).await {
+                Ok(_) => set_message("Sent the picture to the badge".to_string()),
+                Err(e) => set_message(format!("Could not send the picture: {e}")),
+            }
+        });
+    };
+
+    let dither_options = Dither::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == dither()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    let region_options = Region::ALL
+        .into_iter()
+        .map(|v| {
+            view! {
+                <option value=v.id() selected=move|| v == region()>
+                    {v.name()}
+                </option>
+            }
+        })
+        .collect_view();
+
+    view! {
+        <div>
+        <h2>"Picture"</h2>
+        <input type="file" accept="image/png,image/jpeg" on:change=move |ev| {
+        let input: web_sys::HtmlInputElement = event_target(&ev);
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        spawn_local(async move {
+            match gloo_file::futures::read_as_bytes(&gloo_file::File::from(file)).await {
+                Ok(data) => set_picture(Some(data)),
+                Err(e) => set_message(format!("Could not read the picture: {e}")),
+            }
+        });
+    }/>
+        <div>Dithering
+         <select on:change=move |ev| {
+        if let Ok(dither) = event_target_value(&ev).parse() {
+            set_dither(dither);
+        }
+    }>
+        {dither_options}
+    </select>
+    </div>
+        <div>Fit to
+         <select on:change=move |ev| {
+        if let Ok(region) = event_target_value(&ev).parse() {
+            set_region(region);
+        }
+    }>
+        {region_options}
+    </select>
+    </div>
+        <button on:click=move |_| send_to_badge()>Send picture to badge</button>
+        <div>{message}</div>
+        </div>
+    }
+}
+
+/// What a picture is uploaded for
+enum Upload<'a> {
+    /// Only convert it, for the panel of the badge mounted with the rotation.
+    /// Without a badge id it is made for the default panel.
+    Preview { badge_id: &'a str, rotation: Rotation },
+    /// Convert it and set it as the badge's logo
+    Send { badge_id: &'a str },
+}
+
+/// Send a PNG or JPEG to the server to be converted, returning the 1bpp BMP.
+/// Converting pictures needs the owner token.
+async fn upload_picture(
+    upload: Upload<'_>,
+    token: &str,
+    data: Vec<u8>,
+    dither: Dither,
+    region: Region,
+) -> Result<Vec<u8>, gloo_net::Error> {
+    use gloo_net::http::Request;
+
+    let query = format!("dither={}&region={}", dither.id(), region.id());
+    let request = match upload {
+        Upload::Preview { badge_id, rotation } => {
+            let mut url = format!("/picture?{query}&rotation={}", rotation.id());
+            if !badge_id.trim().is_empty() {
+                url.push_str(&format!("&badge={}", badge_id.trim()));
+            }
+            Request::post(&url)
+        }
+        Upload::Send { badge_id } => Request::put(&format!("/picture/{}?{query}", badge_id.trim())),
+    };
+    let response = request
+        .header("Authorization", &format!("Bearer {token}"))
+        .body(js_sys::Uint8Array::from(data.as_slice()))?
+        .send()
+        .await?;
+    if !response.ok() {
+        return Err(gloo_net::Error::GlooError(response.text().await?));
+    }
+    response.binary().await
+}
+
 /// 404 - Not Found
 #[component]
 fn NotFound() -> impl IntoView {
@@ -202,6 +739,23 @@ fn NotFound() -> impl IntoView {
     }
 }
 
+/// Width and height of the panel the badge reported and the rotation id it is
+/// mounted with.  Without a badge id, or before the badge connects, the panel
+/// is the default one.
+#[server(BadgeDisplay, "/badgedisplay")]
+async fn badge_display(badge_id: String) -> Result<(u32, u32, u8), ServerFnError> {
+    let panel = match badge_id.trim() {
+        "" => Panel::default(),
+        badge_id => {
+            let badge_id = u64::from_str_radix(badge_id, 16)
+                .map_err(|_| ServerFnError::new("badge id must be hex"))?;
+            crate::badge_channels::get_panel(badge_id)
+        }
+    };
+    let rotation = crate::badge_channels::get_rotation();
+    Ok((panel.size.width, panel.size.height, rotation.id()))
+}
+
 #[server(UpdateFreq, "/updatefreq")]
 async fn update_frequency(freq: u32) -> Result<String, ServerFnError> {
     use tracing::info;
@@ -210,13 +764,64 @@ async fn update_frequency(freq: u32) -> Result<String, ServerFnError> {
     Ok(format!("Updated frequency to {freq}"))
 }
 
+#[server(UpdateRotation, "/updaterotation")]
+async fn update_rotation(token: String, rotation: u8) -> Result<String, ServerFnError> {
+    use tracing::info;
+    crate::owner::check(&token).map_err(ServerFnError::new)?;
+    let rotation = Rotation::try_from(rotation).map_err(ServerFnError::new)?;
+    info!("Updating rotation to {}", rotation.name());
+    crate::badge_channels::set_rotation(rotation.id());
+    Ok(format!("Updated rotation to {}", rotation.name()))
+}
+
+#[server(UpdatePower, "/updatepower")]
+async fn update_power(token: String, option: usize) -> Result<String, ServerFnError> {
+    use badge_net::PowerPolicy;
+    use tracing::info;
+    crate::owner::check(&token).map_err(ServerFnError::new)?;
+    let policy = match option {
+        0 => PowerPolicy::AlwaysOn,
+        1 => PowerPolicy::Balanced,
+        2 => PowerPolicy::DeepSleep { interval_s: 300 },
+        3 => PowerPolicy::DeepSleep { interval_s: 900 },
+        _ => return Err(ServerFnError::new("unknown power policy")),
+    };
+    info!("Updating power policy to {policy:?}");
+    crate::badge_channels::set_power_policy(policy);
+    Ok(format!("Updated power policy to {}", POWER_OPTIONS[option]))
+}
+
 #[server(UpdateText, "/updatetext")]
-async fn update_text(text: String) -> Result<String, ServerFnError> {
+async fn update_text(
+    text: String,
+    ttl: Option<u32>,
+    urgent: bool,
+    layout: u8,
+    mode: u8,
+    theme: u8,
+) -> Result<String, ServerFnError> {
+    use badge_net::Priority;
     use tracing::info;
-    info!("Updating text to {text}");
+    info!("Updating text to {text} (ttl {ttl:?}, urgent {urgent})");
     // sort of input validation here so that all downstream actions are safe
     let text = format_text_for_badge(text);
+    let ttl = crate::public_ttl(ttl, urgent);
+    let layout = Template::try_from(layout).map_err(ServerFnError::new)?;
+    let mode = TextMode::try_from(mode).map_err(ServerFnError::new)?;
+    let theme = Theme::try_from(theme).map_err(ServerFnError::new)?;
+    let priority = if urgent {
+        Priority::Urgent
+    } else {
+        Priority::Normal
+    };
     // truncate text
-    crate::badge_channels::set_text(&text);
+    crate::badge_channels::set_text(
+        &text,
+        ttl,
+        priority,
+        layout.id(),
+        mode.id(),
+        theme.id(),
+    );
     Ok(format!("Updated text to {text}"))
 }
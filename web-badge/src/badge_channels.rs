@@ -1,7 +1,52 @@
-use std::sync::Mutex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use badge_draw::{Colors, Framebuffer, Panel, Rotation};
+use badge_net::content::ContentState;
+use badge_net::{OwnedUpdate, PanelInfo, PowerPolicy, Priority};
+use embedded_graphics::geometry::Size;
+
+const _: () = assert!(crate::MAX_TEXT_LEN == badge_net::MAX_TEXT_LEN);
 
 static UPDATE_FREQ: Mutex<Option<u32>> = Mutex::new(None);
-static TEXT: Mutex<Option<String>> = Mutex::new(None);
+static POWER_POLICY: Mutex<Option<PowerPolicy>> = Mutex::new(None);
+static CONTENT: Mutex<ContentState> = Mutex::new(ContentState::new());
+static PANELS: Mutex<BTreeMap<u64, Panel>> = Mutex::new(BTreeMap::new());
+
+/// Content to send to the badge along with how long it should stay on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadgeContent {
+    pub text: Option<String>,
+    /// Layout template id, see `badge_draw::Template`
+    pub layout: Option<u8>,
+    /// Display rotation id, see `badge_draw::Rotation`
+    pub rotation: Option<u8>,
+    /// Text mode id, see `badge_draw::TextMode`
+    pub mode: Option<u8>,
+    /// Theme id, see `badge_draw::Theme`
+    pub theme: Option<u8>,
+    pub priority: Priority,
+    /// Expiry time in the same clock as [`now_ms`], `None` for permanent text
+    pub expires_at_ms: Option<u64>,
+}
+impl BadgeContent {
+    /// Seconds left before the text expires, rounded up
+    pub fn remaining_ttl(&self) -> Option<u32> {
+        self.expires_at_ms.map(|expires_at_ms| {
+            let remaining = expires_at_ms.saturating_sub(now_ms()).div_ceil(1000);
+            u32::try_from(remaining).unwrap_or(u32::MAX)
+        })
+    }
+}
+
+/// Milliseconds since the server started, the clock used for message expiry
+fn now_ms() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    let elapsed = START.get_or_init(Instant::now).elapsed().as_millis();
+    u64::try_from(elapsed).unwrap_or(u64::MAX)
+}
 
 pub fn set_frequency(freq: u32) {
     UPDATE_FREQ.lock().unwrap().replace(freq);
@@ -11,11 +56,140 @@ pub fn get_frequency() -> Option<u32> {
     UPDATE_FREQ.lock().unwrap().clone()
 }
 
-pub fn set_text(text: impl AsRef<str>) {
+/// Set how the badge saves its battery, sent to every badge as it connects
+pub fn set_power_policy(policy: PowerPolicy) {
+    POWER_POLICY.lock().unwrap().replace(policy);
+}
+
+pub fn get_power_policy() -> Option<PowerPolicy> {
+    *POWER_POLICY.lock().unwrap()
+}
+
+/// Set the text to display.  Temporary and urgent text follows the same rules as on the
+/// badge so the server always knows what the badge is showing.
+pub fn set_text(
+    text: impl AsRef<str>,
+    ttl: Option<u32>,
+    priority: Priority,
+    layout: u8,
+    mode: u8,
+    theme: u8,
+) {
     let text = crate::format_text_for_badge(text);
-    TEXT.lock().unwrap().replace(text);
+    let update = OwnedUpdate {
+        text: Some(
+            text.as_str()
+                .try_into()
+                .expect("formatted text always fits in an update"),
+        ),
+        ttl,
+        priority,
+        layout: Some(layout),
+        mode: Some(mode),
+        theme: Some(theme),
+        ..Default::default()
+    };
+    CONTENT.lock().unwrap().apply(update, now_ms());
+}
+
+/// Set how the badge is mounted.  This applies straight away, even to temporary text.
+pub fn set_rotation(rotation: u8) {
+    let update = OwnedUpdate {
+        rotation: Some(rotation),
+        ..Default::default()
+    };
+    CONTENT.lock().unwrap().apply(update, now_ms());
+}
+
+/// Directory the per-badge logos are persisted in, one BMP per badge
+const LOGO_DIR: &str = "logos";
+
+fn logo_path(badge_id: u64) -> PathBuf {
+    PathBuf::from(LOGO_DIR).join(format!("{badge_id:016x}.bmp"))
+}
+
+/// Set the logo shown by the badge, replacing Ferris.
+/// The logo must be a 1bpp BMP small enough to send to the badge.
+pub fn set_logo(badge_id: u64, bmp: &[u8]) -> anyhow::Result<()> {
+    if bmp.len() > badge_net::MAX_LOGO_LEN {
+        anyhow::bail!(
+            "logo is {} bytes, the limit is {}",
+            bmp.len(),
+            badge_net::MAX_LOGO_LEN
+        );
+    }
+    badge_draw::Logo::from_bmp(bmp).map_err(anyhow::Error::msg)?;
+    std::fs::create_dir_all(LOGO_DIR)?;
+    std::fs::write(logo_path(badge_id), bmp)?;
+    Ok(())
+}
+
+/// Remove the badge's logo so it goes back to showing Ferris
+pub fn clear_logo(badge_id: u64) -> anyhow::Result<()> {
+    match std::fs::remove_file(logo_path(badge_id)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The logo set for the badge, if any
+pub fn get_logo(badge_id: u64) -> Option<Vec<u8>> {
+    std::fs::read(logo_path(badge_id)).ok()
+}
+
+/// The panel described by a badge's [`PanelInfo`].  A picture covering the
+/// panel must fit in a logo, so larger panels are refused.
+pub fn panel_from_info(info: PanelInfo) -> Result<Panel, &'static str> {
+    if info.width == 0 || info.height == 0 {
+        return Err("panel has no pixels");
+    }
+    let size = Size::new(info.width.into(), info.height.into());
+    if Framebuffer::bmp_len(size) > badge_net::MAX_LOGO_LEN {
+        return Err("panel is too large for a picture covering it");
+    }
+    Ok(Panel::new(
+        size,
+        Colors::try_from(info.colors)?,
+        info.partial_refresh,
+    ))
+}
+
+/// Record the panel a badge reported when it connected
+pub fn set_panel(badge_id: u64, info: PanelInfo) -> anyhow::Result<()> {
+    let panel = panel_from_info(info).map_err(anyhow::Error::msg)?;
+    PANELS.lock().unwrap().insert(badge_id, panel);
+    Ok(())
+}
+
+/// The panel of the badge, assumed to be a Badger 2040 W until it connects
+pub fn get_panel(badge_id: u64) -> Panel {
+    PANELS
+        .lock()
+        .unwrap()
+        .get(&badge_id)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// How the badge is mounted, the same for every badge
+pub fn get_rotation() -> Rotation {
+    get_content()
+        .rotation
+        .and_then(|id| Rotation::try_from(id).ok())
+        .unwrap_or_default()
 }
 
-pub fn get_text() -> Option<String> {
-    TEXT.lock().unwrap().clone()
+pub fn get_content() -> BadgeContent {
+    let mut content = CONTENT.lock().unwrap();
+    content.tick(now_ms());
+    let current = content.current();
+    BadgeContent {
+        text: current.text.as_ref().map(|text| text.to_string()),
+        layout: current.layout,
+        rotation: current.rotation,
+        mode: current.mode,
+        theme: current.theme,
+        priority: current.priority,
+        expires_at_ms: content.expires_at_ms(),
+    }
 }
@@ -5,11 +5,15 @@ use anyhow::Result;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 use tracing::{error, info};
+use web_badge::badge_channels::BadgeContent;
 
 pub async fn server(
     _args: impl IntoIterator<Item = String>,
     get_frequency: impl Fn() -> Option<u32> + Send + 'static + Clone,
-    get_text: impl Fn() -> Option<String> + Send + 'static + Clone,
+    get_power_policy: impl Fn() -> Option<badge_net::PowerPolicy> + Send + 'static + Clone,
+    get_content: impl Fn() -> BadgeContent + Send + 'static + Clone,
+    get_logo: impl Fn(u64) -> Option<Vec<u8>> + Send + 'static + Clone,
+    set_panel: impl Fn(u64, badge_net::PanelInfo) -> Result<()> + Send + 'static + Clone,
 ) -> Result<()> {
     // let mut args = args.into_iter();
     // args.next();
@@ -93,9 +97,21 @@ pub async fn server(
         let stream = ReadWriteWrapper { inner: stream };
 
         let get_frequency = get_frequency.clone();
-        let get_text = get_text.clone();
+        let get_power_policy = get_power_policy.clone();
+        let get_content = get_content.clone();
+        let get_logo = get_logo.clone();
+        let set_panel = set_panel.clone();
         tokio::spawn(async move {
-            match handle_connection(stream, get_frequency, get_text).await {
+            match handle_connection(
+                stream,
+                get_frequency,
+                get_power_policy,
+                get_content,
+                get_logo,
+                set_panel,
+            )
+            .await
+            {
                 Ok(_) => info!("Connection handled successfully"),
                 Err(e) => error!("Error handling connection: {:?}", e),
             }
@@ -137,20 +153,36 @@ where
 async fn handle_connection<C>(
     mut stream: C,
     get_rate: impl Fn() -> Option<u32>,
-    get_text: impl Fn() -> Option<String>,
+    get_power_policy: impl Fn() -> Option<badge_net::PowerPolicy>,
+    get_content: impl Fn() -> BadgeContent,
+    get_logo: impl Fn(u64) -> Option<Vec<u8>>,
+    set_panel: impl Fn(u64, badge_net::PanelInfo) -> Result<()>,
 ) -> Result<()>
 where
     C: badge_net::AsyncRead + badge_net::AsyncWrite + Unpin,
 {
+    let mut buf = [0u8; badge_net::MAX_FRAME_LEN];
+    let hello = badge_net::read_framed_value::<badge_net::Hello>(&mut stream, buf.as_mut_slice())
+        .await
+        .map_err(anyhow::Error::msg)?;
+    let badge_id = hello.badge_id;
+    info!("Badge {badge_id:016x} connected");
+    // Content is still sent to a badge with a panel we don't understand, made
+    // for the default panel
+    if let Err(e) = set_panel(badge_id, hello.panel) {
+        error!("Badge {badge_id:016x} reported an unusable panel: {e}");
+    }
+
     info!("Reading from stream");
     let mut count = 0u32;
 
-    let mut last_text = None;
+    let mut last_content = None;
     let mut last_freq = None;
+    let mut last_power = None;
+    // The badge may be showing any logo when it connects, so always send ours first
+    let mut last_logo = None;
 
     loop {
-        let mut buf = [0u8; 256];
-
         count = count.wrapping_add(1);
 
         let request =
@@ -164,11 +196,22 @@ where
         // sleep 3 seconds
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
 
-        let text = {
-            let thistext = get_text();
-            if last_text != thistext {
-                last_text = thistext.clone();
-                thistext
+        let content = {
+            let thiscontent = Some(get_content());
+            if last_content != thiscontent {
+                last_content = thiscontent.clone();
+                thiscontent
+            } else {
+                None
+            }
+        };
+
+        // An empty logo tells the badge to go back to Ferris
+        let logo = {
+            let thislogo = get_logo(badge_id).unwrap_or_default();
+            if last_logo.as_ref() != Some(&thislogo) {
+                last_logo = Some(thislogo);
+                last_logo.as_deref()
             } else {
                 None
             }
@@ -184,12 +227,30 @@ where
             }
         };
 
+        let power = {
+            let thispower = get_power_policy();
+            if last_power != thispower {
+                last_power = thispower;
+                thispower
+            } else {
+                None
+            }
+        };
+
         //info!("Sending badge count {count}");
         badge_net::write_frame(
             &mut stream,
             &badge_net::Update {
-                text: text.as_ref().map(|x| x.as_str()),
+                text: content.as_ref().and_then(|x| x.text.as_deref()),
                 freq: freq,
+                ttl: content.as_ref().and_then(|x| x.remaining_ttl()),
+                priority: content.as_ref().map(|x| x.priority).unwrap_or_default(),
+                layout: content.as_ref().and_then(|x| x.layout),
+                logo,
+                rotation: content.as_ref().and_then(|x| x.rotation),
+                mode: content.as_ref().and_then(|x| x.mode),
+                theme: content.as_ref().and_then(|x| x.theme),
+                power,
             },
             buf.as_mut_slice(),
         )
@@ -3,6 +3,11 @@ pub mod app;
 #[cfg(feature = "ssr")]
 pub mod badge_channels;
 
+#[cfg(feature = "ssr")]
+pub mod owner;
+
+pub mod picture;
+
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
@@ -14,12 +19,32 @@ pub fn hydrate() {
     mount_to_body(App);
 }
 
+/// Longest time, in seconds, a message submitted from the public web page may stay on the badge
+pub const MAX_PUBLIC_TTL_SECS: u32 = 5 * 60;
+
+/// Limit the time-to-live requested by a public submission.
+/// Urgent messages pre-empt the badge owner's content so they must always expire.
+pub fn public_ttl(ttl: Option<u32>, urgent: bool) -> Option<u32> {
+    match ttl {
+        Some(ttl) => Some(ttl.min(MAX_PUBLIC_TTL_SECS)),
+        None if urgent => Some(MAX_PUBLIC_TTL_SECS),
+        None => None,
+    }
+}
+
+/// Longest text, in bytes, that can be sent to the badge.  The same as
+/// `badge_net::MAX_TEXT_LEN`, which the browser build doesn't depend on.
+pub const MAX_TEXT_LEN: usize = 256;
+
+/// Keep only what the badge fonts can draw, substituting look-alikes for common
+/// characters such as curly quotes and emoji.  Text that is too long to send is
+/// cut off, whether it fits on the display is up to [`badge_draw::analyze_template`].
 pub fn format_text_for_badge(text: impl AsRef<str>) -> String {
-    // truncate text to 13x5 characters
-    const TEXT_LIMIT: usize = 13 * 5;
-    text.as_ref()
-        .chars()
-        .take(TEXT_LIMIT)
-        .filter(|c| c.is_ascii())
+    let mut len = 0;
+    badge_draw::sanitize(text.as_ref())
+        .take_while(|c| {
+            len += c.len_utf8();
+            len <= MAX_TEXT_LEN
+        })
         .collect::<String>()
 }
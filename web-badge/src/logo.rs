@@ -0,0 +1,122 @@
+//! HTTP endpoints to set the logo of a badge.
+//!
+//! `PUT /logo/{badge_id}` with a 1bpp BMP as the body sets the logo and
+//! `DELETE /logo/{badge_id}` reverts the badge to Ferris.  The badge id is the
+//! hex id the badge reports when it connects.  Changing a badge's logo is for
+//! its owner, so these need an `Authorization: Bearer <token>` header with the
+//! owner token, see [`web_badge::owner`].
+//!
+//! Photos are converted with `POST /picture`, which returns the 1bpp BMP as a
+//! preview, and `PUT /picture/{badge_id}`, which also sets it as the logo.  The
+//! body is a PNG or JPEG and the query string selects the conversion, e.g.
+//! `?dither=atkinson&region=logo`.  Pictures are made for the panel the badge
+//! reported, mounted the way the owner set.  A preview is made for the panel
+//! of the badge given with `badge={badge_id}` and the rotation given with
+//! `rotation={id}`, so it can match the page before a new rotation is saved.
+//! Like the logo, converting and putting pictures need the owner token, as
+//! decoding a large photo takes a lot of memory.
+
+use std::collections::HashMap;
+
+use actix_web::{error, http::header, web, HttpRequest, HttpResponse, Result};
+use badge_draw::{Panel, Rotation};
+use web_badge::picture::{Dither, Region};
+
+fn badge_id(path: &str) -> Result<u64> {
+    u64::from_str_radix(path, 16).map_err(|_| error::ErrorBadRequest("badge id must be hex"))
+}
+
+/// Check the request carries the owner token
+fn check_owner(request: &HttpRequest) -> Result<()> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    web_badge::owner::check(token).map_err(error::ErrorUnauthorized)
+}
+
+pub async fn put(
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    check_owner(&request)?;
+    let badge_id = badge_id(&path)?;
+    web_badge::badge_channels::set_logo(badge_id, &body).map_err(error::ErrorBadRequest)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn delete(request: HttpRequest, path: web::Path<String>) -> Result<HttpResponse> {
+    check_owner(&request)?;
+    let badge_id = badge_id(&path)?;
+    web_badge::badge_channels::clear_logo(badge_id).map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Largest picture upload accepted
+pub const MAX_PICTURE_LEN: usize = 8 * 1024 * 1024;
+
+/// Convert the uploaded picture to a 1bpp BMP for the panel, mounted with the
+/// rotation, using the options in the query string
+async fn convert_picture(
+    query: &HashMap<String, String>,
+    body: web::Bytes,
+    panel: Panel,
+    rotation: Rotation,
+) -> Result<Vec<u8>> {
+    let option = |name: &str| query.get(name).map(String::as_str).unwrap_or_default();
+    let dither = match option("dither") {
+        "" => Dither::default(),
+        dither => dither.parse().map_err(error::ErrorBadRequest)?,
+    };
+    let region = match option("region") {
+        "" => Region::default(),
+        region => region.parse().map_err(error::ErrorBadRequest)?,
+    };
+    // Decoding and dithering a photo takes a while, keep it off the async workers
+    let picture =
+        web::block(move || web_badge::picture::convert(&body, region, &panel, rotation, dither))
+            .await?
+            .map_err(error::ErrorBadRequest)?;
+    Ok(picture.to_bmp())
+}
+
+pub async fn preview_picture(
+    request: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    check_owner(&request)?;
+    let panel = match query.get("badge") {
+        Some(badge) => web_badge::badge_channels::get_panel(badge_id(badge)?),
+        None => Panel::default(),
+    };
+    let rotation = match query.get("rotation") {
+        Some(rotation) => rotation
+            .parse()
+            .ok()
+            .and_then(|id: u8| Rotation::try_from(id).ok())
+            .ok_or_else(|| error::ErrorBadRequest("unknown rotation"))?,
+        None => web_badge::badge_channels::get_rotation(),
+    };
+    let bmp = convert_picture(&query, body, panel, rotation).await?;
+    Ok(HttpResponse::Ok().content_type("image/bmp").body(bmp))
+}
+
+pub async fn put_picture(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    check_owner(&request)?;
+    let badge_id = badge_id(&path)?;
+    // Pictures covering the display are made for the panel the badge reported
+    let panel = web_badge::badge_channels::get_panel(badge_id);
+    let rotation = web_badge::badge_channels::get_rotation();
+    let bmp = convert_picture(&query, body, panel, rotation).await?;
+    web_badge::badge_channels::set_logo(badge_id, &bmp).map_err(error::ErrorBadRequest)?;
+    Ok(HttpResponse::Ok().content_type("image/bmp").body(bmp))
+}
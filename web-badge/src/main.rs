@@ -4,6 +4,8 @@ mod websockets;
 #[cfg(feature = "ssr")]
 mod badgeserver;
 #[cfg(feature = "ssr")]
+mod logo;
+#[cfg(feature = "ssr")]
 use std::env;
 
 #[cfg(feature = "ssr")]
@@ -22,7 +24,10 @@ async fn main() -> std::io::Result<()> {
         badgeserver::server(
             args,
             web_badge::badge_channels::get_frequency,
-            web_badge::badge_channels::get_text,
+            web_badge::badge_channels::get_power_policy,
+            web_badge::badge_channels::get_content,
+            web_badge::badge_channels::get_logo,
+            web_badge::badge_channels::set_panel,
         )
         .await
         .unwrap();
@@ -46,6 +51,21 @@ async fn main() -> std::io::Result<()> {
             // serve the favicon from /favicon.ico
             .service(favicon)
             .service(web::resource("/ws").route(web::get().to(websockets::index)))
+            .service(
+                web::resource("/logo/{badge_id}")
+                    .route(web::put().to(logo::put))
+                    .route(web::delete().to(logo::delete)),
+            )
+            .service(
+                web::resource("/picture")
+                    .app_data(web::PayloadConfig::new(logo::MAX_PICTURE_LEN))
+                    .route(web::post().to(logo::preview_picture)),
+            )
+            .service(
+                web::resource("/picture/{badge_id}")
+                    .app_data(web::PayloadConfig::new(logo::MAX_PICTURE_LEN))
+                    .route(web::put().to(logo::put_picture)),
+            )
             .leptos_routes(leptos_options.to_owned(), routes.to_owned(), App)
             .app_data(web::Data::new(leptos_options.to_owned()))
         //.wrap(middleware::Compress::default())
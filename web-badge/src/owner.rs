@@ -0,0 +1,49 @@
+//! The owner's settings, such as how the badge is mounted, are only for the
+//! badge owner, not for everyone who opens the public page.
+//!
+//! The owner proves who they are with a token, set on the server in the
+//! `BADGE_OWNER_TOKEN` environment variable.  When it isn't set nobody can
+//! change the owner's settings.
+
+/// Environment variable holding the owner token
+pub const TOKEN_VAR: &str = "BADGE_OWNER_TOKEN";
+
+/// Whether the token is the owner's
+pub fn is_owner(token: &str) -> bool {
+    match std::env::var(TOKEN_VAR) {
+        Ok(expected) if !expected.is_empty() => same_token(expected.as_bytes(), token.as_bytes()),
+        _ => false,
+    }
+}
+
+/// Check the token, for the server functions and routes changing the owner's settings
+pub fn check(token: &str) -> Result<(), &'static str> {
+    if is_owner(token) {
+        Ok(())
+    } else {
+        Err("only the badge owner can change this")
+    }
+}
+
+/// Compare tokens in a time that doesn't depend on where they differ
+fn same_token(expected: &[u8], token: &[u8]) -> bool {
+    expected.len() == token.len()
+        && expected
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_token() {
+        assert!(same_token(b"secret", b"secret"));
+        assert!(!same_token(b"secret", b"secreT"));
+        assert!(!same_token(b"secret", b"secre"));
+        assert!(!same_token(b"secret", b""));
+    }
+}
@@ -0,0 +1,287 @@
+//! Turning photos into something the black and white badge can show.
+//!
+//! Pictures are scaled and cropped to fill either the whole display or the logo
+//! area, converted to greyscale and then to 1bpp with one of the [`Dither`]
+//! methods.  The result is a BMP delivered to the badge as its logo.
+
+use std::str::FromStr;
+
+use badge_draw::{Framebuffer, Panel, Rotation};
+use embedded_graphics::{
+    geometry::{Point, Size},
+    pixelcolor::BinaryColor,
+};
+
+/// How grey levels are turned into black and white pixels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Error diffusion to four neighbours, smooth gradients
+    #[default]
+    FloydSteinberg,
+    /// Diffuses only three quarters of the error, more contrast
+    Atkinson,
+    /// Each pixel is black or white on its own, best for line art and text
+    Threshold,
+}
+impl Dither {
+    pub const ALL: [Dither; 3] = [Dither::FloydSteinberg, Dither::Atkinson, Dither::Threshold];
+
+    /// Identifier used in query strings
+    pub fn id(self) -> &'static str {
+        match self {
+            Dither::FloydSteinberg => "floyd-steinberg",
+            Dither::Atkinson => "atkinson",
+            Dither::Threshold => "threshold",
+        }
+    }
+
+    /// Human readable name
+    pub fn name(self) -> &'static str {
+        match self {
+            Dither::FloydSteinberg => "Floyd–Steinberg",
+            Dither::Atkinson => "Atkinson",
+            Dither::Threshold => "Threshold",
+        }
+    }
+
+    /// Where the error of a pixel goes, as offsets and weights, and the divisor of the weights
+    fn kernel(self) -> (&'static [(i32, i32, i32)], i32) {
+        match self {
+            Dither::FloydSteinberg => (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16),
+            Dither::Atkinson => (
+                &[
+                    (1, 0, 1),
+                    (2, 0, 1),
+                    (-1, 1, 1),
+                    (0, 1, 1),
+                    (1, 1, 1),
+                    (0, 2, 1),
+                ],
+                8,
+            ),
+            Dither::Threshold => (&[], 1),
+        }
+    }
+}
+impl FromStr for Dither {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Dither::ALL
+            .into_iter()
+            .find(|dither| dither.id() == s)
+            .ok_or("unknown dither method")
+    }
+}
+
+/// The part of the display a picture is made for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// The whole display, shown with [`badge_draw::Template::Picture`]
+    #[default]
+    Display,
+    /// A square logo next to the text
+    Logo,
+}
+impl Region {
+    pub const ALL: [Region; 2] = [Region::Display, Region::Logo];
+
+    /// Identifier used in query strings
+    pub fn id(self) -> &'static str {
+        match self {
+            Region::Display => "display",
+            Region::Logo => "logo",
+        }
+    }
+
+    /// Human readable name
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Display => "Whole display",
+            Region::Logo => "Logo next to the text",
+        }
+    }
+
+    /// Size in pixels pictures are scaled and cropped to for a badge with the
+    /// panel, mounted with the rotation
+    pub fn size(self, panel: &Panel, rotation: Rotation) -> Size {
+        match self {
+            Region::Display => panel.drawing_size(rotation),
+            Region::Logo => Size::new(128, 128),
+        }
+    }
+
+    /// Template that shows a picture made for this region
+    pub fn template(self) -> badge_draw::Template {
+        match self {
+            Region::Display => badge_draw::Template::Picture,
+            Region::Logo => badge_draw::Template::Ferris,
+        }
+    }
+}
+impl FromStr for Region {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Region::ALL
+            .into_iter()
+            .find(|region| region.id() == s)
+            .ok_or("unknown picture region")
+    }
+}
+
+/// Convert 8 bit greyscale pixels, in rows `size.width` long, to black and white
+pub fn dither(grey: &[u8], size: Size, method: Dither) -> Framebuffer {
+    let (width, height) = (size.width as i32, size.height as i32);
+    let mut levels: Vec<i32> = grey.iter().map(|&level| i32::from(level)).collect();
+    levels.resize((width * height) as usize, 0xff);
+    let (kernel, divisor) = method.kernel();
+
+    let mut framebuffer = Framebuffer::new(size);
+    for y in 0..height {
+        for x in 0..width {
+            let level = levels[(y * width + x) as usize];
+            let white = level >= 0x80;
+            if !white {
+                framebuffer.set_pixel(Point::new(x, y), BinaryColor::Off);
+            }
+            let error = level - if white { 0xff } else { 0 };
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if (0..width).contains(&nx) && ny < height {
+                    levels[(ny * width + nx) as usize] += error * weight / divisor;
+                }
+            }
+        }
+    }
+    framebuffer
+}
+
+/// Widest and tallest picture decoded, enough for the photos of current phones
+#[cfg(feature = "ssr")]
+pub const MAX_PICTURE_SIDE: u32 = 8192;
+
+/// Most memory the decoder may allocate for a picture
+#[cfg(feature = "ssr")]
+pub const MAX_PICTURE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// Decode a PNG or JPEG, scale and crop it to fill the region of the panel and dither it.
+/// Pictures larger than [`MAX_PICTURE_SIDE`] are refused before they are decoded.
+#[cfg(feature = "ssr")]
+pub fn convert(
+    data: &[u8],
+    region: Region,
+    panel: &Panel,
+    rotation: Rotation,
+    method: Dither,
+) -> anyhow::Result<Framebuffer> {
+    let size = region.size(panel, rotation);
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_PICTURE_SIDE);
+    limits.max_image_height = Some(MAX_PICTURE_SIDE);
+    limits.max_alloc = Some(MAX_PICTURE_ALLOC);
+    let mut reader = image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+    let image = reader
+        .decode()?
+        .resize_to_fill(
+            size.width,
+            size.height,
+            image::imageops::FilterType::Triangle,
+        )
+        .into_luma8();
+    Ok(dither(image.as_raw(), size, method))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::Pixel;
+
+    fn black_pixels(framebuffer: &Framebuffer) -> usize {
+        framebuffer
+            .pixels()
+            .filter(|Pixel(_, color)| color.is_off())
+            .count()
+    }
+
+    #[test]
+    fn test_threshold() {
+        let size = Size::new(8, 4);
+        let dark = dither(&[0x7f; 32], size, Dither::Threshold);
+        assert_eq!(black_pixels(&dark), 32);
+        let light = dither(&[0x80; 32], size, Dither::Threshold);
+        assert_eq!(black_pixels(&light), 0);
+    }
+
+    #[test]
+    fn test_error_diffusion_keeps_tone() {
+        let size = Size::new(32, 32);
+        // a quarter grey comes out roughly a quarter black, Atkinson loses some
+        // of the error so it comes out lighter
+        for (method, expected) in [
+            (Dither::FloydSteinberg, 230..282),
+            (Dither::Atkinson, 128..256),
+        ] {
+            let framebuffer = dither(&[0xc0; 32 * 32], size, method);
+            let black = black_pixels(&framebuffer);
+            assert!(expected.contains(&black), "{method:?} gave {black}");
+            // pure black and white are unchanged
+            assert_eq!(black_pixels(&dither(&[0; 32 * 32], size, method)), 32 * 32);
+            assert_eq!(black_pixels(&dither(&[0xff; 32 * 32], size, method)), 0);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        for method in Dither::ALL {
+            assert_eq!(method.id().parse(), Ok(method));
+        }
+        for region in Region::ALL {
+            assert_eq!(region.id().parse(), Ok(region));
+        }
+        assert!("sepia".parse::<Dither>().is_err());
+    }
+
+    #[test]
+    fn test_region_size() {
+        let panel = Panel::BADGER_2040;
+        assert_eq!(
+            Region::Display.size(&panel, Rotation::Deg0),
+            Size::new(296, 128)
+        );
+        // portrait pictures are drawn turned, so they are taller than wide
+        assert_eq!(
+            Region::Display.size(&panel, Rotation::Deg90),
+            Size::new(128, 296)
+        );
+        assert_eq!(
+            Region::Logo.size(&panel, Rotation::Deg90),
+            Size::new(128, 128)
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn test_convert_refuses_huge_pictures() {
+        let png = |width, height| {
+            let mut png = std::io::Cursor::new(Vec::new());
+            image::GrayImage::new(width, height)
+                .write_to(&mut png, image::ImageFormat::Png)
+                .unwrap();
+            png.into_inner()
+        };
+        let convert = |png: &[u8]| {
+            let panel = Panel::default();
+            convert(
+                png,
+                Region::Logo,
+                &panel,
+                Rotation::default(),
+                Dither::Threshold,
+            )
+        };
+        assert!(convert(&png(64, 32)).is_ok());
+        assert!(convert(&png(MAX_PICTURE_SIDE + 1, 1)).is_err());
+    }
+}